@@ -0,0 +1,465 @@
+//! Publish composer opened from the topic activity screen: topic, multi-line payload,
+//! QoS, retain flag and, on MQTT v5 connections, a few publish properties.
+
+use bytes::Bytes;
+
+use crate::app::hex_dump::BYTES_PER_LINE;
+use crate::app::{MessageActivity, MessageProperties};
+use crate::mqtt::PublishRequest;
+
+/// Fields of the publish composer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComposerField {
+    Topic,
+    Payload,
+    Qos,
+    Retain,
+    /// Whether the payload is edited as text or hexadecimal bytes.
+    Format,
+    ContentType,
+    ResponseTopic,
+    UserProperties,
+}
+
+impl ComposerField {
+    /// Order in which Tab/Shift+Tab move through the composer.
+    const ORDER: [ComposerField; 8] = [
+        ComposerField::Topic,
+        ComposerField::Payload,
+        ComposerField::Qos,
+        ComposerField::Retain,
+        ComposerField::Format,
+        ComposerField::ContentType,
+        ComposerField::ResponseTopic,
+        ComposerField::UserProperties,
+    ];
+
+    /// Whether the field is an MQTT v5 publish property.
+    pub fn is_v5_property(self) -> bool {
+        matches!(
+            self,
+            ComposerField::ContentType
+                | ComposerField::ResponseTopic
+                | ComposerField::UserProperties
+        )
+    }
+}
+
+/// State of the publish composer.
+#[derive(Debug)]
+pub struct PublishComposer {
+    pub topic: String,
+    /// Lines of the payload. Never empty.
+    pub payload: Vec<String>,
+    /// Line and character of the payload cursor.
+    pub cursor: (usize, usize),
+    /// Whether the payload is edited as hexadecimal bytes, for binary payloads.
+    pub hex: bool,
+    pub qos: u8,
+    pub retain: bool,
+    pub content_type: String,
+    pub response_topic: String,
+    /// Comma separated `key=value` pairs.
+    pub user_properties: String,
+    /// Whether the connection uses MQTT v5 and the properties can be set.
+    pub v5: bool,
+    pub focus: ComposerField,
+    pub error: Option<String>,
+}
+
+impl PublishComposer {
+    /// An empty composer publishing to `topic`.
+    pub fn new(topic: &str, v5: bool) -> Self {
+        Self {
+            topic: topic.to_string(),
+            payload: vec![String::new()],
+            cursor: (0, 0),
+            hex: false,
+            qos: 0,
+            retain: false,
+            content_type: String::new(),
+            response_topic: String::new(),
+            user_properties: String::new(),
+            v5,
+            focus: ComposerField::Topic,
+            error: None,
+        }
+    }
+
+    /// A composer prefilled with a received message, to send it again as is or
+    /// after changing it. Payloads that are not UTF-8 text are edited as hex.
+    pub fn resend(topic: &str, message: &MessageActivity, v5: bool) -> Self {
+        let mut composer = Self::new(topic, v5);
+        composer.qos = message.qos;
+        composer.retain = message.retain;
+
+        match std::str::from_utf8(&message.payload) {
+            Ok(text) => composer.set_payload(text),
+            Err(_) => {
+                composer.hex = true;
+                composer.set_payload(&hex_lines(&message.payload));
+            }
+        }
+
+        if let Some(properties) = &message.properties {
+            composer.content_type = properties.content_type.clone().unwrap_or_default();
+            composer.response_topic = properties.response_topic.clone().unwrap_or_default();
+            composer.user_properties = properties
+                .user_properties
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+                .join(", ");
+        }
+
+        composer
+    }
+
+    /// Fields shown in the composer: the v5 properties only on v5 connections.
+    fn fields(&self) -> impl Iterator<Item = ComposerField> + '_ {
+        ComposerField::ORDER
+            .into_iter()
+            .filter(|field| self.v5 || !field.is_v5_property())
+    }
+
+    /// Move focus to the next field.
+    pub fn next_field(&mut self) {
+        let fields: Vec<ComposerField> = self.fields().collect();
+        let position = fields.iter().position(|f| *f == self.focus).unwrap_or(0);
+        self.focus = fields[(position + 1) % fields.len()];
+    }
+
+    /// Move focus to the previous field.
+    pub fn prev_field(&mut self) {
+        let fields: Vec<ComposerField> = self.fields().collect();
+        let position = fields.iter().position(|f| *f == self.focus).unwrap_or(0);
+        self.focus = fields[(position + fields.len() - 1) % fields.len()];
+    }
+
+    /// Returns the text of the focused single-line field.
+    fn focused_field_mut(&mut self) -> Option<&mut String> {
+        match self.focus {
+            ComposerField::Topic => Some(&mut self.topic),
+            ComposerField::ContentType => Some(&mut self.content_type),
+            ComposerField::ResponseTopic => Some(&mut self.response_topic),
+            ComposerField::UserProperties => Some(&mut self.user_properties),
+            ComposerField::Payload
+            | ComposerField::Qos
+            | ComposerField::Retain
+            | ComposerField::Format => None,
+        }
+    }
+
+    /// Inserts a character into the focused field. On the QoS field a digit selects the
+    /// level and a space cycles through them; on the retain and format fields a space
+    /// toggles them.
+    pub fn insert_char(&mut self, c: char) {
+        match self.focus {
+            ComposerField::Payload => {
+                let (row, col) = self.cursor;
+                let line = &mut self.payload[row];
+                line.insert(byte_index(line, col), c);
+                self.cursor.1 += 1;
+            }
+            ComposerField::Qos => match c {
+                '0'..='2' => self.qos = c as u8 - b'0',
+                ' ' => self.qos = (self.qos + 1) % 3,
+                _ => {}
+            },
+            ComposerField::Retain if c == ' ' => self.retain = !self.retain,
+            ComposerField::Format if c == ' ' => self.error = self.toggle_hex().err(),
+            _ => {
+                if let Some(field) = self.focused_field_mut() {
+                    field.push(c);
+                }
+            }
+        }
+    }
+
+    /// Splits the payload line at the cursor.
+    pub fn insert_newline(&mut self) {
+        let (row, col) = self.cursor;
+        let line = &mut self.payload[row];
+        let rest = line.split_off(byte_index(line, col));
+        self.payload.insert(row + 1, rest);
+        self.cursor = (row + 1, 0);
+    }
+
+    /// Deletes the character before the cursor, joining payload lines at their start.
+    pub fn delete_char(&mut self) {
+        if self.focus != ComposerField::Payload {
+            if let Some(field) = self.focused_field_mut() {
+                field.pop();
+            }
+            return;
+        }
+
+        match self.cursor {
+            (0, 0) => {}
+            (row, 0) => {
+                let line = self.payload.remove(row);
+                let previous = &mut self.payload[row - 1];
+                self.cursor = (row - 1, previous.chars().count());
+                previous.push_str(&line);
+            }
+            (row, col) => {
+                let line = &mut self.payload[row];
+                line.remove(byte_index(line, col - 1));
+                self.cursor.1 -= 1;
+            }
+        }
+    }
+
+    /// Moves the payload cursor by `rows` lines and `cols` characters, wrapping
+    /// characters onto the neighbouring lines.
+    pub fn move_cursor(&mut self, rows: isize, cols: isize) {
+        let (mut row, mut col) = self.cursor;
+        let len = |row: usize| self.payload[row].chars().count();
+
+        if rows != 0 {
+            row = row.saturating_add_signed(rows).min(self.payload.len() - 1);
+            col = col.min(len(row));
+        }
+
+        if cols < 0 {
+            if col > 0 {
+                col -= 1;
+            } else if row > 0 {
+                row -= 1;
+                col = len(row);
+            }
+        } else if cols > 0 {
+            if col < len(row) {
+                col += 1;
+            } else if row + 1 < self.payload.len() {
+                row += 1;
+                col = 0;
+            }
+        }
+
+        self.cursor = (row, col);
+    }
+
+    /// The payload text.
+    pub fn payload_text(&self) -> String {
+        self.payload.join("\n")
+    }
+
+    /// Replaces the payload with `text`, with the cursor at its end.
+    pub fn set_payload(&mut self, text: &str) {
+        self.payload = text.split('\n').map(str::to_string).collect();
+        let row = self.payload.len() - 1;
+        self.cursor = (row, self.payload[row].chars().count());
+    }
+
+    /// Switches the payload editor between text and hexadecimal bytes, converting the
+    /// payload. Fails when the bytes are not valid hex or not UTF-8 text.
+    pub fn toggle_hex(&mut self) -> Result<(), String> {
+        let bytes = self.payload_bytes()?;
+
+        if self.hex {
+            let text = String::from_utf8(bytes.to_vec())
+                .map_err(|_| "The payload is not UTF-8 text".to_string())?;
+            self.hex = false;
+            self.set_payload(&text);
+        } else {
+            self.hex = true;
+            self.set_payload(&hex_lines(&bytes));
+        }
+
+        Ok(())
+    }
+
+    /// The payload bytes: the text, or the hex digits decoded.
+    pub fn payload_bytes(&self) -> Result<Bytes, String> {
+        if !self.hex {
+            return Ok(self.payload_text().into());
+        }
+
+        let digits: Vec<u8> = self
+            .payload
+            .iter()
+            .flat_map(|line| line.bytes())
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+
+        if !digits.len().is_multiple_of(2) {
+            return Err("Invalid hex payload: odd number of digits".into());
+        }
+
+        digits
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .ok_or_else(|| {
+                        format!("Invalid hex payload: '{}'", String::from_utf8_lossy(pair))
+                    })
+            })
+            .collect::<Result<Vec<u8>, String>>()
+            .map(Bytes::from)
+    }
+
+    /// The message to publish, or why it cannot be published.
+    pub fn request(&self) -> Result<PublishRequest, String> {
+        let topic = self.topic.trim();
+
+        if topic.is_empty() || !rumqttc::valid_topic(topic) {
+            return Err(format!("Invalid topic '{}'", topic));
+        }
+
+        let properties = if self.v5 {
+            let response_topic = self.response_topic.trim();
+            if !response_topic.is_empty() && !rumqttc::valid_topic(response_topic) {
+                return Err(format!("Invalid response topic '{}'", response_topic));
+            }
+
+            Some(MessageProperties {
+                response_topic: non_empty(response_topic),
+                content_type: non_empty(self.content_type.trim()),
+                user_properties: parse_user_properties(&self.user_properties)?,
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+
+        Ok(PublishRequest {
+            topic: topic.to_string(),
+            payload: self.payload_bytes()?,
+            qos: self.qos,
+            retain: self.retain,
+            properties,
+        })
+    }
+}
+
+/// `bytes` as hex, `BYTES_PER_LINE` bytes per line.
+fn hex_lines(bytes: &[u8]) -> String {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parses comma separated `key=value` pairs.
+fn parse_user_properties(text: &str) -> Result<Vec<(String, String)>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .split_once('=')
+                .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+                .ok_or_else(|| format!("Expected KEY=VALUE, got '{}'", entry))
+        })
+        .collect()
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Byte offset of character `col` in `line`.
+fn byte_index(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map_or(line.len(), |(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::tests::message;
+
+    #[test]
+    fn test_payload_editor_splits_and_joins_lines() {
+        let mut composer = PublishComposer::new("devices/d1/cmd", false);
+        composer.focus = ComposerField::Payload;
+
+        for c in "{\"on\":1}".chars() {
+            composer.insert_char(c);
+        }
+        composer.move_cursor(0, -1);
+        composer.insert_newline();
+        composer.insert_char('é');
+
+        assert_eq!(composer.payload, vec!["{\"on\":1", "é}"]);
+        assert_eq!(composer.cursor, (1, 1));
+
+        composer.move_cursor(0, -1);
+        composer.delete_char();
+        assert_eq!(composer.payload_text(), "{\"on\":1é}");
+        assert_eq!(composer.cursor, (0, 7));
+    }
+
+    #[test]
+    fn test_request_validates_topic_and_properties() {
+        let mut composer = PublishComposer::new("devices/+/cmd", true);
+        assert_eq!(composer.request().unwrap_err(), "Invalid topic 'devices/+/cmd'");
+
+        composer.topic = "devices/d1/cmd".into();
+        composer.set_payload("on");
+        composer.focus = ComposerField::Qos;
+        composer.insert_char('2');
+        composer.user_properties = "source=ranger, trace = 42".into();
+        composer.content_type = "text/plain".into();
+
+        let request = composer.request().unwrap();
+        assert_eq!(request.payload, "on");
+        assert_eq!(request.qos, 2);
+        let properties = request.properties.unwrap();
+        assert_eq!(properties.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(
+            properties.user_properties,
+            vec![("source".into(), "ranger".into()), ("trace".into(), "42".into())]
+        );
+
+        composer.user_properties = "oops".into();
+        assert!(composer.request().is_err());
+    }
+
+    #[test]
+    fn test_resend_prefills_binary_payload_as_hex() {
+        let message = MessageActivity {
+            payload: Bytes::from_static(&[0x00, 0xff, 0x10]),
+            properties: Some(MessageProperties {
+                user_properties: vec![("site".into(), "plant-1".into())],
+                ..Default::default()
+            }),
+            qos: 1,
+            retain: true,
+            ..message("", time::OffsetDateTime::now_utc())
+        };
+
+        let mut composer = PublishComposer::resend("devices/d1/raw", &message, true);
+        assert!(composer.hex);
+        assert_eq!(composer.payload_text(), "00 ff 10");
+        assert_eq!(composer.user_properties, "site=plant-1");
+
+        let request = composer.request().unwrap();
+        assert_eq!(request.payload, message.payload);
+        assert_eq!((request.qos, request.retain), (1, true));
+
+        assert!(composer.toggle_hex().is_err());
+        composer.set_payload("68 69");
+        composer.toggle_hex().unwrap();
+        assert_eq!(composer.payload_text(), "hi");
+    }
+
+    #[test]
+    fn test_v5_fields_are_skipped_on_v311() {
+        let mut composer = PublishComposer::new("", false);
+        composer.focus = ComposerField::Format;
+        composer.next_field();
+        assert_eq!(composer.focus, ComposerField::Topic);
+        composer.prev_field();
+        assert_eq!(composer.focus, ComposerField::Format);
+    }
+}
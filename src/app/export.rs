@@ -0,0 +1,701 @@
+//! Export of the messages of the selected topic, a subtree of the topic tree or the
+//! whole session to a file, for analysis outside the application.
+//! Text payloads are written as is and binary payloads in base64. In JSON exports, JSON
+//! payloads are embedded as values; in CSV exports they can be flattened into one column
+//! per field. Exports are written by a background thread, which reads the messages kept
+//! in the history database a page at a time.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{Map, Value, json};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+
+use super::history::HISTORY_PAGE;
+use super::{MessageActivity, MessageHistory, TopicActivityMenuState, TopicView};
+use crate::capture::CaptureRecord;
+
+/// File format of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One row per message: timestamp, topic, payload, qos, retain.
+    Csv,
+    /// A JSON array of messages.
+    Json,
+    /// One JSON message per line.
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Ndjson => "NDJSON",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            ExportFormat::Csv => ExportFormat::Json,
+            ExportFormat::Json => ExportFormat::Ndjson,
+            ExportFormat::Ndjson => ExportFormat::Csv,
+        }
+    }
+}
+
+/// Topics whose messages are exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportScope {
+    /// The selected topic.
+    Topic,
+    /// The selected topic or node of the tree and every topic below it.
+    Subtree,
+    /// Every topic.
+    Session,
+}
+
+impl ExportScope {
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportScope::Topic => "Topic",
+            ExportScope::Subtree => "Subtree",
+            ExportScope::Session => "Session",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            ExportScope::Topic => ExportScope::Subtree,
+            ExportScope::Subtree => ExportScope::Session,
+            ExportScope::Session => ExportScope::Topic,
+        }
+    }
+}
+
+/// Field of the export form with the keyboard focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportField {
+    Path,
+    Format,
+    Scope,
+    Flatten,
+}
+
+impl ExportField {
+    /// Order in which Tab/Shift+Tab move through the form.
+    const ORDER: [ExportField; 4] = [
+        ExportField::Path,
+        ExportField::Format,
+        ExportField::Scope,
+        ExportField::Flatten,
+    ];
+}
+
+/// Form choosing what to export and where.
+#[derive(Debug)]
+pub struct ExportForm {
+    pub path: String,
+    pub format: ExportFormat,
+    pub scope: ExportScope,
+    /// Whether JSON payloads get one CSV column per field.
+    pub flatten: bool,
+    pub focus: ExportField,
+    pub error: Option<String>,
+}
+
+impl ExportForm {
+    /// A CSV export of `scope` to a file named after `now`, in the working directory.
+    pub fn new(scope: ExportScope, now: OffsetDateTime) -> Self {
+        let format = format_description!("[year][month][day]-[hour][minute][second]");
+        let stamp = now.format(format).unwrap_or_default();
+
+        Self {
+            path: format!("mqtt-ranger-export-{}.csv", stamp),
+            format: ExportFormat::Csv,
+            scope,
+            flatten: false,
+            focus: ExportField::Path,
+            error: None,
+        }
+    }
+
+    /// Move focus to the next field.
+    pub fn next_field(&mut self) {
+        let position = ExportField::ORDER.iter().position(|f| *f == self.focus).unwrap_or(0);
+        self.focus = ExportField::ORDER[(position + 1) % ExportField::ORDER.len()];
+    }
+
+    /// Move focus to the previous field.
+    pub fn prev_field(&mut self) {
+        let len = ExportField::ORDER.len();
+        let position = ExportField::ORDER.iter().position(|f| *f == self.focus).unwrap_or(0);
+        self.focus = ExportField::ORDER[(position + len - 1) % len];
+    }
+
+    /// Inserts a character into the path. On the other fields a space cycles through
+    /// the choices; the extension of the path follows the format.
+    pub fn insert_char(&mut self, c: char) {
+        match self.focus {
+            ExportField::Path => self.path.push(c),
+            ExportField::Format if c == ' ' => {
+                let next = self.format.next();
+                if let Some(stem) = self.path.strip_suffix(self.format.extension())
+                    && stem.ends_with('.')
+                {
+                    self.path = format!("{}{}", stem, next.extension());
+                }
+                self.format = next;
+            }
+            ExportField::Scope if c == ' ' => self.scope = self.scope.next(),
+            ExportField::Flatten if c == ' ' => self.flatten = !self.flatten,
+            _ => {}
+        }
+    }
+
+    /// Deletes the last character of the path.
+    pub fn delete_char(&mut self) {
+        if self.focus == ExportField::Path {
+            self.path.pop();
+        }
+    }
+}
+
+impl TopicActivityMenuState {
+    /// Opens the export form for the selected topic, or the selected node of the tree.
+    pub fn open_export_form(&mut self) {
+        let scope = match self.selected_topic() {
+            Some(_) => ExportScope::Topic,
+            None => ExportScope::Subtree,
+        };
+        let now = OffsetDateTime::now_local().unwrap_or(OffsetDateTime::now_utc());
+
+        self.export_form = Some(ExportForm::new(scope, now));
+    }
+
+    /// Names of the topics in `scope`.
+    fn export_topics(&self, scope: ExportScope) -> Result<Vec<String>, String> {
+        let selected = match self.view {
+            TopicView::Flat => self.topics.get(self.selected_index).map(|t| t.name.clone()),
+            TopicView::Tree => self.tree.selected.clone(),
+        };
+
+        let topics: Vec<String> = match scope {
+            ExportScope::Topic => {
+                self.selected_topic().map(|t| t.name.clone()).into_iter().collect()
+            }
+            ExportScope::Subtree => {
+                let Some(selected) = selected else {
+                    return Err("No topic selected".into());
+                };
+                let subtree = format!("{}/", selected);
+
+                self.topics
+                    .iter()
+                    .filter(|topic| topic.name == selected || topic.name.starts_with(&subtree))
+                    .map(|topic| topic.name.clone())
+                    .collect()
+            }
+            ExportScope::Session => self.topics.iter().map(|t| t.name.clone()).collect(),
+        };
+
+        if topics.is_empty() {
+            return Err("No topic selected".into());
+        }
+        Ok(topics)
+    }
+
+    /// The messages of `topics` to export: the number of each topic's messages that are
+    /// only kept in the history database, and a copy of those in memory.
+    fn export_snapshot(&self, topics: &[String]) -> Vec<ExportTopic> {
+        self.topics
+            .iter()
+            .filter(|topic| topics.contains(&topic.name))
+            .map(|topic| ExportTopic {
+                name: topic.name.clone(),
+                on_disk: if self.history.is_some() { topic.dropped } else { 0 },
+                in_memory: topic
+                    .messages
+                    .iter()
+                    .map(|message| capture_record(&topic.name, message))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Starts writing the export described by the form in the background and closes the
+    /// form, or shows why it cannot start.
+    pub fn run_export(&mut self) {
+        let Some(form) = self.export_form.as_ref() else {
+            return;
+        };
+        let path = PathBuf::from(form.path.trim());
+        let (format, flatten, scope) = (form.format, form.flatten, form.scope);
+
+        let topics = match self.export_topics(scope) {
+            Ok(topics) => self.export_snapshot(&topics),
+            Err(error) => {
+                if let Some(form) = self.export_form.as_mut() {
+                    form.error = Some(error);
+                }
+                return;
+            }
+        };
+        let history = self.history.as_ref().map(|history| history.path().to_path_buf());
+
+        let (tx, rx) = mpsc::channel();
+        let export_path = path.clone();
+        thread::spawn(move || {
+            let history = history.as_deref().map(MessageHistory::open).transpose();
+            let result = history.and_then(|history| {
+                let records = || ExportRecords::new(history.as_ref(), &topics);
+                write_export(&export_path, format, flatten, records)
+            });
+            let _ = tx.send(result);
+        });
+
+        self.export_form = None;
+        self.export_status = Some(ExportStatus::Running { path, result: rx });
+    }
+
+    /// Records the outcome of the export running in the background, once it is written.
+    pub fn poll_export(&mut self) {
+        let Some(ExportStatus::Running { path, result }) = &self.export_status else {
+            return;
+        };
+
+        let status = match result.try_recv() {
+            Ok(Ok(count)) => {
+                ExportStatus::Done(format!("Exported {} messages to {}", count, path.display()))
+            }
+            Ok(Err(error)) => ExportStatus::Failed(error),
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => ExportStatus::Failed("Export stopped".into()),
+        };
+        self.export_status = Some(status);
+    }
+}
+
+/// An export written in the background, or its outcome.
+#[derive(Debug)]
+pub enum ExportStatus {
+    Running {
+        path: PathBuf,
+        /// Number of messages written, or why the export failed.
+        result: Receiver<Result<usize, String>>,
+    },
+    Done(String),
+    Failed(String),
+}
+
+/// Messages of a topic to export.
+struct ExportTopic {
+    name: String,
+    /// Number of the first messages that are only in the history database.
+    on_disk: u64,
+    in_memory: Vec<CaptureRecord>,
+}
+
+fn capture_record(topic: &str, message: &MessageActivity) -> CaptureRecord {
+    CaptureRecord {
+        timestamp: message.received_at,
+        topic: topic.to_string(),
+        payload: message.payload.clone(),
+        qos: message.qos,
+        retain: message.retain,
+        properties: None,
+    }
+}
+
+/// Reads the messages of a topic in order: those on disk a page at a time, then those
+/// in memory.
+struct TopicCursor<'a> {
+    topic: &'a ExportTopic,
+    /// Number of the next message to read from disk.
+    next_on_disk: u64,
+    page: VecDeque<CaptureRecord>,
+    in_memory: std::slice::Iter<'a, CaptureRecord>,
+}
+
+impl<'a> TopicCursor<'a> {
+    fn new(topic: &'a ExportTopic) -> Self {
+        Self {
+            topic,
+            next_on_disk: 0,
+            page: VecDeque::new(),
+            in_memory: topic.in_memory.iter(),
+        }
+    }
+
+    fn next(&mut self, history: Option<&MessageHistory>) -> Result<Option<CaptureRecord>, String> {
+        while self.page.is_empty()
+            && let Some(history) = history
+            && self.next_on_disk < self.topic.on_disk
+        {
+            let count = HISTORY_PAGE.min(self.topic.on_disk - self.next_on_disk);
+            let messages = history.page(&self.topic.name, self.next_on_disk, count)?;
+            self.page = messages.iter().map(|m| capture_record(&self.topic.name, m)).collect();
+            self.next_on_disk += count;
+        }
+
+        Ok(self.page.pop_front().or_else(|| self.in_memory.next().cloned()))
+    }
+}
+
+/// The messages of several topics by time of reception, merged from a `TopicCursor` per
+/// topic. The iteration ends after the first error.
+struct ExportRecords<'a> {
+    history: Option<&'a MessageHistory>,
+    cursors: Vec<TopicCursor<'a>>,
+    /// The next message of every cursor.
+    next: Vec<Option<CaptureRecord>>,
+    /// Cursors that have a next message, by its time of reception, earliest first.
+    queue: BinaryHeap<Reverse<(OffsetDateTime, usize)>>,
+    started: bool,
+    failed: bool,
+}
+
+impl<'a> ExportRecords<'a> {
+    fn new(history: Option<&'a MessageHistory>, topics: &'a [ExportTopic]) -> Self {
+        Self {
+            history,
+            cursors: topics.iter().map(TopicCursor::new).collect(),
+            next: topics.iter().map(|_| None).collect(),
+            queue: BinaryHeap::new(),
+            started: false,
+            failed: false,
+        }
+    }
+
+    /// Reads the next message of cursor `index`.
+    fn advance(&mut self, index: usize) -> Result<(), String> {
+        let record = self.cursors[index].next(self.history)?;
+        if let Some(record) = &record {
+            self.queue.push(Reverse((record.timestamp, index)));
+        }
+        self.next[index] = record;
+        Ok(())
+    }
+
+    fn earliest(&mut self) -> Result<Option<CaptureRecord>, String> {
+        if !self.started {
+            self.started = true;
+            for index in 0..self.cursors.len() {
+                self.advance(index)?;
+            }
+        }
+
+        let Some(Reverse((_, index))) = self.queue.pop() else {
+            return Ok(None);
+        };
+        let record = self.next[index].take();
+        self.advance(index)?;
+        Ok(record)
+    }
+}
+
+impl Iterator for ExportRecords<'_> {
+    type Item = Result<CaptureRecord, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let result = self.earliest();
+        self.failed = result.is_err();
+        result.transpose()
+    }
+}
+
+/// Writes the records to `path` in `format` and returns how many were written.
+/// `records` is called once, or twice for CSV exports with flattened JSON payloads,
+/// whose columns are collected first.
+pub fn write_export<I>(
+    path: &Path,
+    format: ExportFormat,
+    flatten: bool,
+    records: impl Fn() -> I,
+) -> Result<usize, String>
+where
+    I: Iterator<Item = Result<CaptureRecord, String>>,
+{
+    let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    let mut out = BufWriter::new(File::create(path).map_err(error)?);
+
+    let count = match format {
+        ExportFormat::Csv => write_csv(&mut out, records, flatten)?,
+        ExportFormat::Json => write_json(&mut out, records())?,
+        ExportFormat::Ndjson => {
+            let mut count = 0;
+            for record in records() {
+                serde_json::to_writer(&mut out, &json_record(&record?))
+                    .map_err(|e| error(e.into()))?;
+                writeln!(out).map_err(error)?;
+                count += 1;
+            }
+            count
+        }
+    };
+    out.flush().map_err(error)?;
+
+    Ok(count)
+}
+
+/// Writes the records as a JSON array, one message per line.
+fn write_json(
+    out: &mut impl Write,
+    records: impl Iterator<Item = Result<CaptureRecord, String>>,
+) -> Result<usize, String> {
+    let mut count = 0;
+    for record in records {
+        let separator = if count == 0 { "[\n  " } else { ",\n  " };
+        out.write_all(separator.as_bytes()).map_err(|e| e.to_string())?;
+        serde_json::to_writer(&mut *out, &json_record(&record?)).map_err(|e| e.to_string())?;
+        count += 1;
+    }
+    let end = if count == 0 { "[]\n" } else { "\n]\n" };
+    out.write_all(end.as_bytes()).map_err(|e| e.to_string())?;
+
+    Ok(count)
+}
+
+/// The payload as text, or in base64 when it is not valid UTF-8.
+fn payload_text(record: &CaptureRecord) -> (String, bool) {
+    match std::str::from_utf8(&record.payload) {
+        Ok(text) => (text.to_string(), false),
+        Err(_) => (STANDARD.encode(&record.payload), true),
+    }
+}
+
+fn timestamp(record: &CaptureRecord) -> String {
+    record.timestamp.format(&Rfc3339).unwrap_or_default()
+}
+
+/// A message of a JSON export. JSON payloads are embedded as values, and binary payloads
+/// are written in base64 to `payload_base64` instead of `payload`.
+fn json_record(record: &CaptureRecord) -> Value {
+    let payload = match serde_json::from_slice::<Value>(&record.payload) {
+        Ok(value) => ("payload", value),
+        Err(_) => match payload_text(record) {
+            (text, false) => ("payload", Value::String(text)),
+            (base64, true) => ("payload_base64", Value::String(base64)),
+        },
+    };
+
+    let mut value = json!({
+        "timestamp": timestamp(record),
+        "topic": record.topic,
+    });
+    value[payload.0] = payload.1;
+    value["qos"] = record.qos.into();
+    value["retain"] = record.retain.into();
+
+    value
+}
+
+/// Writes the records as CSV. With `flatten`, every field of the JSON payloads gets a
+/// `payload.<path>` column after the fixed ones, collected in a first pass.
+fn write_csv<I>(
+    out: &mut impl Write,
+    records: impl Fn() -> I,
+    flatten: bool,
+) -> Result<usize, String>
+where
+    I: Iterator<Item = Result<CaptureRecord, String>>,
+{
+    let mut columns: Vec<String> = Vec::new();
+    if flatten {
+        for record in records() {
+            for (key, _) in flattened_payload(&record?) {
+                if !columns.contains(&key) {
+                    columns.push(key);
+                }
+            }
+        }
+    }
+
+    let header = ["timestamp", "topic", "payload", "qos", "retain"];
+    let header = header.iter().copied().chain(columns.iter().map(String::as_str));
+    write_csv_row(out, header).map_err(|e| e.to_string())?;
+
+    let mut count = 0;
+    for record in records() {
+        let record = record?;
+        let fields = if flatten { flattened_payload(&record) } else { Map::new() };
+        let fixed = [
+            timestamp(&record),
+            record.topic.clone(),
+            payload_text(&record).0,
+            record.qos.to_string(),
+            record.retain.to_string(),
+        ];
+        let extra = columns.iter().map(|column| match fields.get(column) {
+            Some(Value::String(text)) => text.clone(),
+            Some(Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        });
+        let row: Vec<String> = fixed.into_iter().chain(extra).collect();
+        write_csv_row(out, row.iter().map(String::as_str)).map_err(|e| e.to_string())?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// The leaves of a JSON object or array payload, by `payload.<path>` column.
+fn flattened_payload(record: &CaptureRecord) -> Map<String, Value> {
+    let mut fields = Map::new();
+    if let Ok(value @ (Value::Object(_) | Value::Array(_))) =
+        serde_json::from_slice::<Value>(&record.payload)
+    {
+        flatten_json("payload", &value, &mut fields);
+    }
+    fields
+}
+
+/// Collects the leaves of `value` under `prefix`, with object keys and array indexes
+/// joined by dots.
+fn flatten_json(prefix: &str, value: &Value, fields: &mut Map<String, Value>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten_json(&format!("{}.{}", prefix, key), value, fields);
+            }
+        }
+        Value::Array(items) => {
+            for (index, value) in items.iter().enumerate() {
+                flatten_json(&format!("{}.{}", prefix, index), value, fields);
+            }
+        }
+        leaf => {
+            fields.insert(prefix.to_string(), leaf.clone());
+        }
+    }
+}
+
+/// Writes a CSV row, quoting the fields that contain separators, quotes or line breaks.
+fn write_csv_row<'a>(
+    out: &mut impl Write,
+    fields: impl Iterator<Item = &'a str>,
+) -> std::io::Result<()> {
+    let row: Vec<String> = fields
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+
+    writeln!(out, "{}", row.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::tests::message;
+    use crate::test_util::TempDir;
+    use bytes::Bytes;
+    use std::time::Duration;
+    use time::macros::datetime;
+
+    fn record(topic: &str, payload: &'static [u8]) -> CaptureRecord {
+        CaptureRecord {
+            timestamp: datetime!(2025-03-01 12:30:05 UTC),
+            topic: topic.into(),
+            payload: Bytes::from_static(payload),
+            qos: 1,
+            retain: false,
+            properties: None,
+        }
+    }
+
+    fn records() -> Vec<CaptureRecord> {
+        vec![
+            record("plant/temp", br#"{"value":21.5,"tags":["a","b"]}"#),
+            record("plant/note", b"needs \"service\", soon"),
+            record("plant/raw", &[0xff, 0x00]),
+        ]
+    }
+
+    #[test]
+    fn test_csv_export_flattens_json_payloads() {
+        let mut out = Vec::new();
+        write_csv(&mut out, || records().into_iter().map(Ok), true).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            "timestamp,topic,payload,qos,retain,payload.value,payload.tags.0,payload.tags.1"
+        );
+        assert_eq!(
+            lines[1],
+            concat!(
+                r#"2025-03-01T12:30:05Z,plant/temp,"{""value"":21.5,""tags"":[""a"",""b""]}","#,
+                "1,false,21.5,a,b"
+            )
+        );
+        assert_eq!(
+            lines[2],
+            r#"2025-03-01T12:30:05Z,plant/note,"needs ""service"", soon",1,false,,,"#
+        );
+        assert_eq!(lines[3], "2025-03-01T12:30:05Z,plant/raw,/wA=,1,false,,,");
+    }
+
+    #[test]
+    fn test_export_merges_history_and_memory_by_time() {
+        let dir = TempDir::new("export");
+        let mut menu_state = TopicActivityMenuState::new();
+        menu_state.retention.max_messages_per_topic = Some(2);
+        menu_state.open_history(&dir.path().join("history.db")).unwrap();
+
+        let start = OffsetDateTime::now_utc();
+        for n in 0..600u64 {
+            let topic = if n % 3 == 0 { "plant/a" } else { "plant/b" };
+            menu_state.add_message(topic, message(&n.to_string(), start + Duration::from_secs(n)));
+        }
+
+        let topics = menu_state.export_snapshot(&["plant/a".into(), "plant/b".into()]);
+        let payloads: Vec<String> = ExportRecords::new(menu_state.history.as_ref(), &topics)
+            .map(|record| String::from_utf8(record.unwrap().payload.to_vec()).unwrap())
+            .collect();
+
+        assert_eq!(payloads, (0..600).map(|n| n.to_string()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_json_record_embeds_json_payloads() {
+        let values: Vec<Value> = records().iter().map(json_record).collect();
+
+        assert_eq!(values[0]["payload"]["tags"][1], "b");
+        assert_eq!(values[1]["payload"], "needs \"service\", soon");
+        assert_eq!(values[2]["payload_base64"], "/wA=");
+        assert_eq!(
+            values[2].to_string(),
+            concat!(
+                r#"{"timestamp":"2025-03-01T12:30:05Z","topic":"plant/raw","#,
+                r#""payload_base64":"/wA=","qos":1,"retain":false}"#
+            )
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Hex and ASCII dump of message payloads.
+//! Each line shows the offset of its first byte, up to 16 bytes in hex split in two
+//! groups of 8, and the same bytes as ASCII with non-printable bytes shown as `.`.
+
+/// Payload bytes shown per line.
+pub const BYTES_PER_LINE: usize = 16;
+
+/// A formatted line of a hex dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexDumpLine {
+    pub offset: String,
+    pub hex: String,
+    pub ascii: String,
+}
+
+/// Number of lines needed to dump `len` bytes.
+pub fn line_count(len: usize) -> usize {
+    len.div_ceil(BYTES_PER_LINE)
+}
+
+/// Formats `count` lines of the dump of `payload`, starting at line `first`.
+pub fn hex_dump(payload: &[u8], first: usize, count: usize) -> Vec<HexDumpLine> {
+    payload
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .skip(first)
+        .take(count)
+        .map(|(line, chunk)| format_line(line * BYTES_PER_LINE, chunk))
+        .collect()
+}
+
+fn format_line(offset: usize, chunk: &[u8]) -> HexDumpLine {
+    let mut hex = String::with_capacity(BYTES_PER_LINE * 3 + 1);
+
+    for i in 0..BYTES_PER_LINE {
+        if i == BYTES_PER_LINE / 2 {
+            hex.push(' ');
+        }
+        match chunk.get(i) {
+            Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+            None => hex.push_str("   "),
+        }
+    }
+
+    let ascii = chunk
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+
+    HexDumpLine {
+        offset: format!("{:08x}", offset),
+        hex,
+        ascii,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump_lines() {
+        let payload = b"Hello, MQTT!\x00\x01\x02\x7f\xffrest";
+
+        let lines = hex_dump(payload, 0, 10);
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].offset, "00000000");
+        assert_eq!(
+            lines[0].hex,
+            "48 65 6c 6c 6f 2c 20 4d  51 54 54 21 00 01 02 7f "
+        );
+        assert_eq!(lines[0].ascii, "Hello, MQTT!....");
+        assert_eq!(lines[1].offset, "00000010");
+        assert_eq!(lines[1].hex.trim_end(), "ff 72 65 73 74");
+        assert_eq!(lines[1].hex.len(), lines[0].hex.len());
+        assert_eq!(lines[1].ascii, ".rest");
+
+        assert_eq!(hex_dump(payload, 1, 10), lines[1..]);
+        assert_eq!(line_count(payload.len()), 2);
+        assert_eq!(line_count(0), 0);
+    }
+}
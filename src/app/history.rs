@@ -0,0 +1,325 @@
+//! Optional persistent message history in a SQLite database, indexed by topic and time.
+//! Every received message is written to disk as well as to the in-memory ring of its
+//! topic. The messages discarded from memory by the retention policy, and those of
+//! previous sessions, are paged back from disk when scrolling up the activity panel.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rusqlite::{Connection, params};
+use serde::Deserialize;
+use time::{OffsetDateTime, UtcOffset};
+
+use super::{MessageActivity, MessageProperties, TopicActivity, TopicActivityMenuState};
+use crate::mqtt::format_timestamp;
+
+/// Messages read from disk at once when scrolling back.
+pub const HISTORY_PAGE: u64 = 200;
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    PRAGMA synchronous = NORMAL;
+    CREATE TABLE IF NOT EXISTS messages (
+        topic TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        received_at INTEGER NOT NULL,
+        utc_offset INTEGER NOT NULL,
+        payload BLOB NOT NULL,
+        qos INTEGER NOT NULL,
+        retain INTEGER NOT NULL,
+        properties TEXT,
+        PRIMARY KEY (topic, seq)
+    );
+    CREATE INDEX IF NOT EXISTS messages_by_topic_and_time ON messages (topic, received_at);
+";
+
+/// Where the message history is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Only the messages allowed by the retention policy, lost on quit.
+    #[default]
+    Memory,
+    /// Every message, in a SQLite database.
+    Sqlite,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "memory" => Ok(StorageBackend::Memory),
+            "sqlite" => Ok(StorageBackend::Sqlite),
+            _ => Err(format!("Unknown storage '{}', expected memory or sqlite", text)),
+        }
+    }
+}
+
+/// Default database of the SQLite backend: `$XDG_DATA_HOME/mqtt-ranger/history.db`
+/// (`~/.local/share/mqtt-ranger/history.db`).
+pub fn default_history_path() -> Option<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+    })?;
+
+    Some(data_dir.join("mqtt-ranger").join("history.db"))
+}
+
+/// An open history database. Messages are numbered per topic from 0, in the order
+/// they were received.
+#[derive(Debug)]
+pub struct MessageHistory {
+    path: PathBuf,
+    connection: Connection,
+}
+
+impl MessageHistory {
+    /// Opens the database at `path`, creating it and its directory when missing.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| error(&e))?;
+        }
+        let connection = Connection::open(path).map_err(|e| error(&e))?;
+        connection.execute_batch(SCHEMA).map_err(|e| error(&e))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            connection,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every stored topic with its number of messages, by time of its first message.
+    pub fn topics(&self) -> Result<Vec<(String, u64)>, String> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT topic, MAX(seq) + 1 FROM messages GROUP BY topic ORDER BY MIN(received_at)",
+            )
+            .map_err(|e| e.to_string())?;
+
+        statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Stores message number `seq` of `topic`. Fails when the database already holds it,
+    /// e.g. written by another instance sharing the database.
+    pub fn insert(&self, topic: &str, seq: u64, message: &MessageActivity) -> Result<(), String> {
+        let received_at = i64::try_from(message.received_at.unix_timestamp_nanos())
+            .map_err(|e| e.to_string())?;
+        let properties = message
+            .properties
+            .as_ref()
+            .map(|properties| serde_json::to_string(properties).map_err(|e| e.to_string()))
+            .transpose()?;
+
+        self.connection
+            .prepare_cached(
+                "INSERT INTO messages
+                 (topic, seq, received_at, utc_offset, payload, qos, retain, properties)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )
+            .and_then(|mut statement| {
+                statement.execute(params![
+                    topic,
+                    seq,
+                    received_at,
+                    message.received_at.offset().whole_seconds(),
+                    message.payload.as_ref(),
+                    message.qos,
+                    message.retain,
+                    properties,
+                ])
+            })
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Messages `first` to `first + count - 1` of `topic`, oldest first.
+    pub fn page(
+        &self,
+        topic: &str,
+        first: u64,
+        count: u64,
+    ) -> Result<Vec<MessageActivity>, String> {
+        let mut statement = self
+            .connection
+            .prepare_cached(
+                "SELECT received_at, utc_offset, payload, qos, retain, properties FROM messages
+                 WHERE topic = ?1 AND seq >= ?2 AND seq < ?3 ORDER BY seq",
+            )
+            .map_err(|e| e.to_string())?;
+
+        let rows = statement
+            .query_map(params![topic, first, first + count], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i32>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    row.get::<_, u8>(3)?,
+                    row.get::<_, bool>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                ))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| e.to_string())?;
+
+        rows.into_iter()
+            .map(|(received_at, utc_offset, payload, qos, retain, properties)| {
+                let offset = UtcOffset::from_whole_seconds(utc_offset).unwrap_or(UtcOffset::UTC);
+                let received_at = OffsetDateTime::from_unix_timestamp_nanos(received_at.into())
+                    .map_err(|e| e.to_string())?
+                    .to_offset(offset);
+                let properties = properties
+                    .map(|json| serde_json::from_str::<MessageProperties>(&json))
+                    .transpose()
+                    .map_err(|e| e.to_string())?;
+
+                Ok(MessageActivity {
+                    payload: payload.into(),
+                    timestamp: format_timestamp(received_at),
+                    received_at,
+                    properties,
+                    qos,
+                    retain,
+                    decoded: Default::default(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Messages of a topic read back from disk.
+pub struct HistoryPage {
+    pub topic: String,
+    /// Number of the first message of the page.
+    pub first: u64,
+    pub messages: Vec<MessageActivity>,
+}
+
+impl HistoryPage {
+    /// Message number `position` of `topic`, when on this page.
+    pub fn get(&self, topic: &str, position: u64) -> Option<&MessageActivity> {
+        if self.topic != topic {
+            return None;
+        }
+        self.messages.get(usize::try_from(position.checked_sub(self.first)?).ok()?)
+    }
+}
+
+impl TopicActivityMenuState {
+    /// Opens the history database at `path` and lists the topics stored by previous
+    /// sessions, whose messages are then only on disk.
+    pub fn open_history(&mut self, path: &Path) -> Result<(), String> {
+        let history = MessageHistory::open(path)?;
+
+        for (name, count) in history.topics()? {
+            match self.topics.iter_mut().find(|topic| topic.name == name) {
+                Some(topic) => topic.dropped = topic.dropped.max(count),
+                None => {
+                    let mut topic = TopicActivity::new(name);
+                    topic.dropped = count;
+                    self.topics.push(topic);
+                }
+            }
+        }
+
+        self.history = Some(history);
+        Ok(())
+    }
+
+    /// Writes message number `seq` of `topic` to the history database, when enabled.
+    /// A failed write stops writing to the database.
+    pub(super) fn store_in_history(&mut self, topic: &str, seq: u64, message: &MessageActivity) {
+        let Some(history) = &self.history else {
+            return;
+        };
+
+        if let Err(error) = history.insert(topic, seq, message) {
+            self.history_error = Some(format!("{}: {}", history.path().display(), error));
+            self.history = None;
+            self.history_page = None;
+        }
+    }
+
+    /// Reads the page of history holding the selected message from disk, when it is no
+    /// longer kept in memory and not read yet.
+    pub fn sync_history_page(&mut self) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let (Some(topic), Some(position)) = (self.selected_topic(), self.selected_position())
+        else {
+            return;
+        };
+        let on_page = |page: &HistoryPage| page.get(&topic.name, position).is_some();
+        if position >= topic.dropped || self.history_page.as_ref().is_some_and(on_page) {
+            return;
+        }
+
+        let first = position / HISTORY_PAGE * HISTORY_PAGE;
+        let count = HISTORY_PAGE.min(topic.dropped - first);
+        match history.page(&topic.name, first, count) {
+            Ok(messages) => {
+                self.history_page = Some(HistoryPage {
+                    topic: topic.name.clone(),
+                    first,
+                    messages,
+                })
+            }
+            Err(error) => {
+                self.history_error = Some(format!("{}: {}", history.path().display(), error));
+                self.history_page = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::tests::message;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_scrolling_back_pages_discarded_messages_from_disk() {
+        let dir = TempDir::new("history");
+        let path = dir.path().join("history.db");
+
+        let mut menu_state = TopicActivityMenuState::new();
+        menu_state.retention.max_messages_per_topic = Some(2);
+        menu_state.open_history(&path).unwrap();
+        for payload in 0..500 {
+            menu_state.add_message("a", message(&payload.to_string(), OffsetDateTime::now_utc()));
+        }
+        assert_eq!(menu_state.topics[0].messages.len(), 2);
+
+        menu_state.message_up(300);
+        menu_state.sync_history_page();
+        assert_eq!(menu_state.selected_message().unwrap().payload, "199");
+        menu_state.message_home();
+        menu_state.sync_history_page();
+        assert_eq!(menu_state.selected_message().unwrap().payload, "0");
+        assert_eq!(menu_state.selected_message_index(), Some(0));
+
+        // A new session lists the topic and reads its messages from disk.
+        let mut menu_state = TopicActivityMenuState::new();
+        menu_state.open_history(&path).unwrap();
+        menu_state.sync_history_page();
+        assert_eq!(menu_state.topics[0].dropped, 500);
+        assert_eq!(menu_state.selected_message().unwrap().payload, "499");
+        menu_state.add_message("a", message("500", OffsetDateTime::now_utc()));
+        assert_eq!(menu_state.selected_message().unwrap().payload, "500");
+        let history = menu_state.history.as_ref().unwrap();
+        let duplicate = message("another instance", OffsetDateTime::now_utc());
+        assert!(history.insert("a", 500, &duplicate).is_err());
+    }
+}
@@ -0,0 +1,479 @@
+//! Pretty-printed JSON view of a message payload.
+//! The document is laid out one value per line and its objects and arrays can be
+//! folded. Payloads that are not valid JSON are shown as text with the parse error
+//! pointing at its position.
+
+use std::collections::BTreeSet;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::decoder::{Value, builtin};
+
+/// Syntax category of a piece of a line, used to color it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Key,
+    String,
+    Number,
+    Bool,
+    Null,
+    Punctuation,
+    /// Summary of a folded object or array.
+    Folded,
+    /// Raw payload text of an invalid document.
+    Plain,
+    /// Parse error message and position marker.
+    Error,
+}
+
+/// A line of the view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonLine {
+    pub depth: usize,
+    pub tokens: Vec<(TokenKind, String)>,
+    /// Path of the value this line belongs to, as child indices from the root.
+    path: Vec<usize>,
+    /// Whether the line opens or closes an object or array.
+    container: bool,
+}
+
+/// Where and why a payload could not be parsed as JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// 1-based line of the error.
+    pub line: usize,
+    /// 1-based column of the error, in characters.
+    pub column: usize,
+    /// The payload as text.
+    pub text: String,
+}
+
+impl ParseError {
+    fn new(error: serde_json::Error, text: String) -> Self {
+        let (mut line, mut column) = (error.line(), error.column());
+
+        // Column 0 means the error was found on the line break ending the previous line.
+        if column == 0 && line > 1 {
+            line -= 1;
+            column = text.lines().nth(line - 1).map_or(0, |l| l.chars().count()) + 1;
+        } else if let Some(error_line) = text.lines().nth(line.saturating_sub(1)) {
+            // serde_json counts the column in bytes.
+            let chars = error_line.char_indices().take_while(|(index, _)| *index + 1 < column);
+            column = chars.count() + 1;
+        }
+
+        Self {
+            message: error.to_string(),
+            line,
+            column,
+            text,
+        }
+    }
+}
+
+/// The document shown by the view.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonDocument {
+    Valid(Value),
+    Invalid(ParseError),
+}
+
+/// State of the JSON popup opened for the selected message.
+/// It keeps its own copy of the document, so incoming messages do not disturb it.
+#[derive(Debug)]
+pub struct JsonView {
+    pub title: String,
+    pub document: JsonDocument,
+    /// Paths of the folded objects and arrays.
+    folded: BTreeSet<Vec<usize>>,
+    /// Index of the highlighted line.
+    pub cursor: usize,
+}
+
+impl JsonView {
+    /// Parses `payload` as JSON. When it is not JSON but another decoder produced an
+    /// object or array (e.g. gzip-compressed JSON or CBOR), that structure is shown.
+    pub fn new(title: String, payload: &[u8], decoded: Option<&Value>) -> Self {
+        let document = match serde_json::from_slice::<serde_json::Value>(payload) {
+            Ok(json) => JsonDocument::Valid(builtin::from_json(json)),
+            Err(_) if matches!(decoded, Some(Value::Map(_) | Value::Array(_))) => {
+                JsonDocument::Valid(decoded.cloned().unwrap_or(Value::Null))
+            }
+            Err(e) => JsonDocument::Invalid(ParseError::new(
+                e,
+                String::from_utf8_lossy(payload).into_owned(),
+            )),
+        };
+
+        let cursor = match &document {
+            JsonDocument::Invalid(error) => error.line,
+            JsonDocument::Valid(_) => 0,
+        };
+
+        let mut view = Self {
+            title,
+            document,
+            folded: BTreeSet::new(),
+            cursor,
+        };
+        view.cursor = view.cursor.min(view.lines().len().saturating_sub(1));
+        view
+    }
+
+    /// The visible lines, honoring the folded objects and arrays.
+    pub fn lines(&self) -> Vec<JsonLine> {
+        let mut lines = Vec::new();
+
+        match &self.document {
+            JsonDocument::Valid(value) => {
+                self.push_value(value, None, &mut Vec::new(), true, &mut lines);
+            }
+            JsonDocument::Invalid(error) => push_error_lines(error, &mut lines),
+        }
+
+        lines
+    }
+
+    fn push_value(
+        &self,
+        value: &Value,
+        key: Option<&Value>,
+        path: &mut Vec<usize>,
+        last: bool,
+        lines: &mut Vec<JsonLine>,
+    ) {
+        let depth = path.len();
+        let comma = if last { "" } else { "," };
+        let mut tokens = Vec::new();
+
+        if let Some(key) = key {
+            let key = match key {
+                Value::Text(text) => text.clone(),
+                other => other.to_string(),
+            };
+            tokens.push((TokenKind::Key, quote(&key)));
+            tokens.push((TokenKind::Punctuation, ": ".to_string()));
+        }
+
+        let (open, close, count) = match value {
+            Value::Array(items) if !items.is_empty() => ("[", "]", items.len()),
+            Value::Map(entries) if !entries.is_empty() => ("{", "}", entries.len()),
+            scalar => {
+                tokens.push(scalar_token(scalar));
+                tokens.push((TokenKind::Punctuation, comma.to_string()));
+                lines.push(JsonLine {
+                    depth,
+                    tokens,
+                    path: path.clone(),
+                    container: false,
+                });
+                return;
+            }
+        };
+
+        if self.folded.contains(path) {
+            let unit = if open == "[" { "items" } else { "entries" };
+            tokens.push((TokenKind::Punctuation, open.to_string()));
+            tokens.push((TokenKind::Folded, format!(" … {} {} ", count, unit)));
+            tokens.push((TokenKind::Punctuation, format!("{}{}", close, comma)));
+            lines.push(JsonLine {
+                depth,
+                tokens,
+                path: path.clone(),
+                container: true,
+            });
+            return;
+        }
+
+        tokens.push((TokenKind::Punctuation, open.to_string()));
+        lines.push(JsonLine {
+            depth,
+            tokens,
+            path: path.clone(),
+            container: true,
+        });
+
+        match value {
+            Value::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    path.push(index);
+                    self.push_value(item, None, path, index + 1 == count, lines);
+                    path.pop();
+                }
+            }
+            Value::Map(entries) => {
+                for (index, (key, item)) in entries.iter().enumerate() {
+                    path.push(index);
+                    self.push_value(item, Some(key), path, index + 1 == count, lines);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+
+        lines.push(JsonLine {
+            depth,
+            tokens: vec![(TokenKind::Punctuation, format!("{}{}", close, comma))],
+            path: path.clone(),
+            container: true,
+        });
+    }
+
+    /// Moves the cursor `count` lines up.
+    pub fn up(&mut self, count: usize) {
+        self.cursor = self.cursor.saturating_sub(count);
+    }
+
+    /// Moves the cursor `count` lines down.
+    pub fn down(&mut self, count: usize) {
+        let last = self.lines().len().saturating_sub(1);
+        self.cursor = (self.cursor + count).min(last);
+    }
+
+    /// Moves the cursor to the last line.
+    pub fn end(&mut self) {
+        self.cursor = self.lines().len().saturating_sub(1);
+    }
+
+    /// Folds the object or array under the cursor, or unfolds it when it is folded.
+    pub fn toggle_fold(&mut self) {
+        let Some(line) = self.lines().into_iter().nth(self.cursor) else {
+            return;
+        };
+
+        if line.container && !self.folded.remove(&line.path) {
+            self.fold_path(line.path);
+        }
+    }
+
+    /// Folds the object or array under the cursor. On a value or a folded line,
+    /// folds the enclosing object or array instead.
+    pub fn fold(&mut self) {
+        let Some(line) = self.lines().into_iter().nth(self.cursor) else {
+            return;
+        };
+
+        if line.container && !self.folded.contains(&line.path) {
+            self.fold_path(line.path);
+        } else if let Some((_, parent)) = line.path.split_last() {
+            self.fold_path(parent.to_vec());
+        }
+    }
+
+    /// Unfolds the object or array under the cursor.
+    pub fn unfold(&mut self) {
+        if let Some(line) = self.lines().into_iter().nth(self.cursor) {
+            self.folded.remove(&line.path);
+        }
+    }
+
+    /// Folds every object and array below the root.
+    pub fn fold_all(&mut self) {
+        if let JsonDocument::Valid(value) = &self.document {
+            let mut paths = Vec::new();
+            collect_container_paths(value, &mut Vec::new(), &mut paths);
+            self.folded = paths.into_iter().filter(|path| !path.is_empty()).collect();
+            self.cursor = 0;
+        }
+    }
+
+    /// Unfolds everything, keeping the cursor on the same value.
+    pub fn unfold_all(&mut self) {
+        let path = self.lines().into_iter().nth(self.cursor).map(|line| line.path);
+        self.folded.clear();
+
+        if let Some(path) = path {
+            self.move_to(&path);
+        }
+    }
+
+    /// Folds the container at `path` and moves the cursor to it.
+    fn fold_path(&mut self, path: Vec<usize>) {
+        self.folded.insert(path.clone());
+        self.move_to(&path);
+    }
+
+    /// Moves the cursor to the first line of the value at `path`.
+    fn move_to(&mut self, path: &[usize]) {
+        if let Some(index) = self.lines().iter().position(|line| line.path == path) {
+            self.cursor = index;
+        }
+    }
+}
+
+/// Tabs of an invalid document are shown as this many spaces, since terminal cells
+/// cannot hold them.
+const TAB_WIDTH: usize = 4;
+
+/// Terminal cells taken by `c` in a shown line of an invalid document, `None` for the
+/// control characters left out of it. Wide characters such as CJK and emoji take two.
+fn cell_width(c: char) -> Option<usize> {
+    if c == '\t' { Some(TAB_WIDTH) } else { c.width() }
+}
+
+/// Lines of an invalid document: the error, then the text with a marker under the
+/// position of the error.
+fn push_error_lines(error: &ParseError, lines: &mut Vec<JsonLine>) {
+    let plain = |tokens| JsonLine {
+        depth: 0,
+        tokens,
+        path: Vec::new(),
+        container: false,
+    };
+
+    lines.push(plain(vec![(
+        TokenKind::Error,
+        format!("Invalid JSON: {}", error.message),
+    )]));
+
+    let marker = |text: &str| {
+        let width: usize = text
+            .chars()
+            .take(error.column.saturating_sub(1))
+            .filter_map(cell_width)
+            .sum();
+        plain(vec![(TokenKind::Error, format!("{}^", " ".repeat(width)))])
+    };
+
+    for (index, text) in error.text.lines().enumerate() {
+        let shown: String = text
+            .chars()
+            .filter(|&c| cell_width(c).is_some())
+            .map(|c| if c == '\t' { " ".repeat(TAB_WIDTH) } else { c.to_string() })
+            .collect();
+        lines.push(plain(vec![(TokenKind::Plain, shown)]));
+
+        if index + 1 == error.line {
+            lines.push(marker(text));
+        }
+    }
+
+    // Errors at the end of the input can be reported past the last line.
+    if error.line > error.text.lines().count() {
+        lines.push(marker(error.text.lines().last().unwrap_or_default()));
+    }
+}
+
+fn collect_container_paths(value: &Value, path: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+    let children: Vec<&Value> = match value {
+        Value::Array(items) => items.iter().collect(),
+        Value::Map(entries) => entries.iter().map(|(_, value)| value).collect(),
+        _ => return,
+    };
+
+    if !children.is_empty() {
+        paths.push(path.clone());
+    }
+
+    for (index, child) in children.into_iter().enumerate() {
+        path.push(index);
+        collect_container_paths(child, path, paths);
+        path.pop();
+    }
+}
+
+/// A scalar (or empty object or array) as a single token.
+fn scalar_token(value: &Value) -> (TokenKind, String) {
+    match value {
+        Value::Null => (TokenKind::Null, "null".to_string()),
+        Value::Bool(value) => (TokenKind::Bool, value.to_string()),
+        Value::Integer(_) | Value::Float(_) => (TokenKind::Number, value.to_string()),
+        Value::Text(text) => (TokenKind::String, quote(text)),
+        Value::Array(_) => (TokenKind::Punctuation, "[]".to_string()),
+        Value::Map(_) => (TokenKind::Punctuation, "{}".to_string()),
+        Value::Bytes(_) => (TokenKind::String, value.to_string()),
+    }
+}
+
+/// `text` as a JSON string literal.
+fn quote(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_else(|_| format!("{:?}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(lines: &[JsonLine]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                let tokens: String = line.tokens.iter().map(|(_, text)| text.as_str()).collect();
+                format!("{}{}", "  ".repeat(line.depth), tokens)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_json_view_pretty_prints_and_folds() {
+        let payload = br#"{"id": 7, "tags": ["a", "b"], "ok": true, "none": null, "empty": {}}"#;
+        let mut view = JsonView::new("a".into(), payload, None);
+
+        assert_eq!(
+            text(&view.lines()),
+            vec![
+                "{",
+                r#"  "id": 7,"#,
+                r#"  "tags": ["#,
+                r#"    "a","#,
+                r#"    "b""#,
+                "  ],",
+                r#"  "ok": true,"#,
+                r#"  "none": null,"#,
+                r#"  "empty": {}"#,
+                "}",
+            ]
+        );
+        assert_eq!(view.lines()[1].tokens[2].0, TokenKind::Number);
+
+        view.cursor = 3;
+        view.fold();
+        assert_eq!(view.cursor, 2);
+        assert_eq!(text(&view.lines())[2], r#"  "tags": [ … 2 items ],"#);
+        assert_eq!(view.lines().len(), 7);
+
+        view.toggle_fold();
+        assert_eq!(view.lines().len(), 10);
+
+        view.fold_all();
+        assert_eq!(view.lines().len(), 7);
+        view.cursor = 2;
+        view.unfold();
+        assert_eq!(view.lines().len(), 10);
+
+        view.fold_all();
+        view.cursor = 5;
+        view.unfold_all();
+        assert_eq!(view.lines().len(), 10);
+        assert_eq!(text(&view.lines())[view.cursor], r#"  "empty": {}"#);
+    }
+
+    #[test]
+    fn test_invalid_json_points_at_the_error() {
+        let view = JsonView::new("a".into(), b"{\n  \"id\": 7,\n  \"ok\": tru\n}", None);
+
+        let JsonDocument::Invalid(error) = &view.document else {
+            panic!("document should be invalid");
+        };
+        assert_eq!((error.line, error.column), (3, 12));
+
+        let lines = view.lines();
+        assert_eq!(lines[0].tokens[0].0, TokenKind::Error);
+        assert_eq!(text(&lines)[3], r#"  "ok": tru"#);
+        let marker = format!("{}^", " ".repeat(error.column - 1));
+        assert_eq!(lines[4].tokens[0], (TokenKind::Error, marker));
+        assert_eq!(view.cursor, 3);
+
+        let view = JsonView::new("a".into(), "{\"température\":\tx}".as_bytes(), None);
+        let lines = view.lines();
+        assert_eq!(text(&lines)[1], "{\"température\":    x}");
+        let marker = format!("{}^", " ".repeat(19));
+        assert_eq!(lines[2].tokens[0], (TokenKind::Error, marker));
+
+        let view = JsonView::new("a".into(), "{\"🚀温度\": x}".as_bytes(), None);
+        let lines = view.lines();
+        let marker = format!("{}^", " ".repeat(11));
+        assert_eq!(lines[2].tokens[0], (TokenKind::Error, marker));
+    }
+}
@@ -0,0 +1,1526 @@
+//! Application state and main event loop for the TUI application.
+//! This module defines the data structures and logic for managing
+//! the state of the MQTT topics and their associated messages.
+
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::capture::{Recorder, Replay};
+use crate::decoder::{AUTO, DecodedPayload, Decoders};
+use crate::mqtt::{MQTTConfig, ProtocolVersion, PublishRequest, SubscriptionFilter, TlsSettings};
+use retention::ArrivalQueue;
+
+pub mod composer;
+pub mod export;
+pub mod hex_dump;
+pub mod history;
+pub mod json_view;
+pub mod publish_job;
+pub mod recording;
+pub mod retention;
+pub mod sparkplug;
+pub mod topic_tree;
+
+pub use composer::PublishComposer;
+pub use export::{ExportField, ExportForm, ExportStatus};
+pub use history::{HistoryPage, MessageHistory, StorageBackend};
+pub use json_view::JsonView;
+pub use publish_job::{JobState, JobsPanel, PublishJob};
+pub use recording::RecordSettings;
+pub use retention::RetentionPolicy;
+pub use sparkplug::{SparkplugState, SparkplugView};
+pub use topic_tree::{TopicTree, TopicView, TreeRow};
+
+/// Association of an MQTT topic with its messages.
+/// Each topic has a name and the most recent messages received on that topic,
+/// as allowed by the retention policy.
+pub struct TopicActivity {
+    pub name: String,
+    pub messages: VecDeque<MessageActivity>,
+    /// Number of older messages discarded by the retention policy.
+    pub dropped: u64,
+    /// Whether the broker holds a retained message for the topic, as far as the
+    /// messages received tell.
+    pub retained: bool,
+}
+
+/// Represents a single MQTT message activity,
+pub struct MessageActivity {
+    /// Payload bytes exactly as received.
+    pub payload: Bytes,
+    pub timestamp: String,
+    /// When the message was received, used to expire old messages.
+    pub received_at: OffsetDateTime,
+    /// MQTT v5 publish properties. `None` when connected with MQTT 3.1.1.
+    pub properties: Option<MessageProperties>,
+    /// QoS the message was delivered with.
+    pub qos: u8,
+    /// Whether the broker delivered the message from its retained messages.
+    pub retain: bool,
+    /// The payload decoded the first time it is shown.
+    pub decoded: OnceCell<DecodedPayload>,
+}
+
+impl MessageActivity {
+    /// The payload as text, with invalid UTF-8 sequences replaced.
+    /// Only allocates when the payload is not valid UTF-8.
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.payload)
+    }
+}
+
+/// MQTT v5 publish properties attached to a received message. Properties that are not
+/// set are left out of capture files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessageProperties {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_format_indicator: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_expiry_interval: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic_alias: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_data: Option<Vec<u8>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub user_properties: Vec<(String, String)>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subscription_identifiers: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+}
+
+/// Lifecycle of a topic filter subscription.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    /// SUBSCRIBE sent, waiting for the SUBACK.
+    Pending,
+    /// Accepted by the broker with the granted QoS.
+    Granted(u8),
+    /// Rejected by the broker, with the reason it gave.
+    Failed(String),
+    /// UNSUBSCRIBE sent, waiting for the UNSUBACK.
+    Unsubscribing,
+}
+
+/// A topic filter the client subscribed to and what the broker answered.
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub filter: String,
+    pub requested_qos: u8,
+    /// Packet id of the last SUBSCRIBE or UNSUBSCRIBE, known once the packet has been sent.
+    pub pkid: Option<u16>,
+    pub status: SubscriptionStatus,
+}
+
+/// Pane of the topic activity screen that receives the Up/Down keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pane {
+    #[default]
+    Topics,
+    Messages,
+    /// The detail panel below the messages, when open.
+    Detail,
+}
+
+/// Publications kept for the status bar, oldest first.
+const MAX_PUBLICATIONS: usize = 50;
+
+/// Delivery state of a message published from the UI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublishStatus {
+    /// Queued in the client, not sent yet.
+    Queued,
+    /// Sent, waiting for the PUBACK (QoS 1) or the PUBCOMP (QoS 2).
+    AwaitingAck,
+    /// Sent at QoS 0, or acknowledged by the broker.
+    Delivered,
+    /// Refused by the broker, with the reason it gave.
+    Failed(String),
+}
+
+/// A message published from the UI and what the broker answered.
+#[derive(Debug, Clone)]
+pub struct Publication {
+    pub topic: String,
+    pub qos: u8,
+    /// Packet id of the PUBLISH, known once the packet has been sent.
+    pub pkid: Option<u16>,
+    pub status: PublishStatus,
+}
+
+/// Panel shown below the message list with details of the selected message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetailPanel {
+    /// MQTT v5 publish properties.
+    Properties,
+    /// Hex and ASCII dump of the payload bytes.
+    HexDump,
+    /// Structure of the decoded payload, one entry per line.
+    Decoded,
+}
+
+/// State of the connection to the broker, shown in the status bar.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Waiting for the first CONNACK.
+    #[default]
+    Connecting,
+    Connected,
+    /// The connection dropped; the next attempt starts after `retry_in`.
+    Reconnecting {
+        attempt: u32,
+        last_error: String,
+        retry_in: Duration,
+    },
+}
+
+/// State of the subscriptions overlay opened from the topic activity screen.
+#[derive(Debug, Default)]
+pub struct SubscriptionsPanel {
+    /// Index of the highlighted subscription.
+    pub selected: usize,
+    /// `filter[:qos]` being typed for a new subscription.
+    pub input: String,
+    pub error: Option<String>,
+}
+
+/// Confirmation shown before clearing retained messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClearRetained {
+    /// The selected topic, or the tree node whose subtree is cleared.
+    pub scope: String,
+    /// Retained topics that will receive an empty retained message.
+    pub topics: Vec<String>,
+    /// Why the last topic could not be cleared.
+    pub error: Option<String>,
+}
+
+impl ClearRetained {
+    /// The empty retained messages that clear the listed topics.
+    pub fn requests(&self) -> Vec<PublishRequest> {
+        self.topics
+            .iter()
+            .map(|topic| PublishRequest {
+                topic: topic.clone(),
+                qos: 1,
+                retain: true,
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+/// The messages listed in the activity panel around the cursor.
+#[derive(Default)]
+pub struct MessageWindow<'a> {
+    /// Index of the first message of the window among all the listed messages.
+    pub first: usize,
+    pub messages: Vec<&'a MessageActivity>,
+    /// Index of the selected message in the window.
+    pub selected: Option<usize>,
+}
+
+impl<'a> MessageWindow<'a> {
+    /// Keeps the messages at most `rows` away from the selected one, or the first
+    /// `rows` when none is selected.
+    fn around(
+        messages: impl Iterator<Item = &'a MessageActivity>,
+        selected: Option<usize>,
+        rows: usize,
+    ) -> Self {
+        let first = selected.map_or(0, |index| index.saturating_sub(rows));
+        let len = rows.saturating_mul(2).saturating_add(1);
+        MessageWindow {
+            first,
+            messages: messages.skip(first).take(len).collect(),
+            selected: selected.map(|index| index - first),
+        }
+    }
+}
+
+/// Represents the overall state of the application,
+/// including the list of topics and the currently selected topic.
+#[derive(Default)]
+pub struct TopicActivityMenuState {
+    pub topics: Vec<TopicActivity>,
+    pub selected_index: usize,
+    /// The panel shown below the message list, if any.
+    pub detail_panel: Option<DetailPanel>,
+    /// First visible line of the detail panel.
+    pub detail_scroll: usize,
+    /// Topic filters subscribed to, in the order the SUBSCRIBE packets were sent.
+    pub subscriptions: Vec<Subscription>,
+    /// The subscriptions overlay, when open.
+    pub subscriptions_panel: Option<SubscriptionsPanel>,
+    /// The JSON popup of a message, when open.
+    pub json_view: Option<JsonView>,
+    /// The publish composer, when open.
+    pub composer: Option<PublishComposer>,
+    /// The confirmation before clearing retained messages, when open.
+    pub clear_retained: Option<ClearRetained>,
+    /// Publish jobs, running or stopped.
+    pub publish_jobs: Vec<JobState>,
+    /// The publish jobs overlay, when open.
+    pub jobs_panel: Option<JobsPanel>,
+    /// The capture file received messages are written to, while recording.
+    pub recorder: Option<Recorder>,
+    pub record_settings: RecordSettings,
+    /// Why the last recording could not start or stopped.
+    pub record_error: Option<String>,
+    /// The capture file played instead of a broker connection, in replay mode.
+    pub replay: Option<Replay>,
+    /// The export form, when open.
+    pub export_form: Option<ExportForm>,
+    /// The export being written, or the outcome of the last one.
+    pub export_status: Option<ExportStatus>,
+    /// The database every message is written to, with the SQLite storage backend.
+    pub history: Option<MessageHistory>,
+    /// Why the history database could not be read or written.
+    pub history_error: Option<String>,
+    /// Messages read back from the history database around the message cursor.
+    pub history_page: Option<HistoryPage>,
+    /// Messages published from the UI, the most recent last.
+    pub publications: VecDeque<Publication>,
+    /// Protocol version of the connection, which decides whether the composer offers
+    /// the v5 properties.
+    pub protocol: ProtocolVersion,
+    pub connection: ConnectionState,
+    /// Whether the topic list is shown flat or as a tree.
+    pub view: TopicView,
+    pub tree: TopicTree,
+    pub focused_pane: Pane,
+    /// Position of the selected message among all messages ever received on the
+    /// selected topic, so it stays on the same message when older ones are discarded.
+    /// `None` follows the newest message as new ones arrive.
+    pub message_cursor: Option<u64>,
+    pub retention: RetentionPolicy,
+    /// Payload bytes currently kept across all topics.
+    pub total_bytes: usize,
+    /// Order in which the messages kept were received, under `max_total_bytes`.
+    arrivals: ArrivalQueue,
+    pub decoders: Decoders,
+    /// Edge nodes and devices, when the Sparkplug B mode is enabled.
+    pub sparkplug: Option<SparkplugState>,
+    /// The Sparkplug view, when open.
+    pub sparkplug_view: Option<SparkplugView>,
+}
+
+impl TopicActivityMenuState {
+    pub fn new() -> Self {
+        Self {
+            topics: Vec::new(),
+            selected_index: 0,
+            detail_panel: None,
+            detail_scroll: 0,
+            subscriptions: Vec::new(),
+            subscriptions_panel: None,
+            json_view: None,
+            composer: None,
+            clear_retained: None,
+            publish_jobs: Vec::new(),
+            jobs_panel: None,
+            recorder: None,
+            record_settings: RecordSettings::default(),
+            record_error: None,
+            replay: None,
+            export_form: None,
+            export_status: None,
+            history: None,
+            history_error: None,
+            history_page: None,
+            publications: VecDeque::new(),
+            protocol: ProtocolVersion::V311,
+            connection: ConnectionState::Connecting,
+            view: TopicView::Flat,
+            tree: TopicTree::default(),
+            focused_pane: Pane::Topics,
+            message_cursor: None,
+            retention: RetentionPolicy::default(),
+            total_bytes: 0,
+            arrivals: ArrivalQueue::default(),
+            decoders: Decoders::default(),
+            sparkplug: None,
+            sparkplug_view: None,
+        }
+    }
+
+    /// Registers a filter that is about to be subscribed to.
+    pub fn add_pending_subscription(&mut self, filter: &str, qos: u8) {
+        self.subscriptions.push(Subscription {
+            filter: filter.to_string(),
+            requested_qos: qos,
+            pkid: None,
+            status: SubscriptionStatus::Pending,
+        });
+    }
+
+    /// Associates a sent SUBSCRIBE packet with the oldest pending filter.
+    /// SUBSCRIBE packets leave the client in the order they were requested.
+    pub fn assign_subscribe_pkid(&mut self, pkid: u16) {
+        if let Some(subscription) = self
+            .subscriptions
+            .iter_mut()
+            .find(|s| s.pkid.is_none() && s.status == SubscriptionStatus::Pending)
+        {
+            subscription.pkid = Some(pkid);
+        }
+    }
+
+    /// Records the broker's answer for the filter subscribed with `pkid`.
+    pub fn apply_suback(&mut self, pkid: u16, results: &[Result<u8, String>]) {
+        let Some(subscription) = self
+            .subscriptions
+            .iter_mut()
+            .find(|s| s.pkid == Some(pkid) && s.status == SubscriptionStatus::Pending)
+        else {
+            return;
+        };
+
+        subscription.status = match results.first() {
+            Some(Ok(granted_qos)) => SubscriptionStatus::Granted(*granted_qos),
+            Some(Err(reason)) => SubscriptionStatus::Failed(reason.clone()),
+            None => SubscriptionStatus::Failed("empty SUBACK".into()),
+        };
+    }
+
+    /// Registers a filter added at runtime. A filter that is already subscribed is refused;
+    /// one that previously failed is replaced so it can be retried.
+    pub fn request_subscription(&mut self, filter: &SubscriptionFilter) -> Result<(), String> {
+        let already_subscribed = self.subscriptions.iter().any(|s| {
+            s.filter == filter.filter && !matches!(s.status, SubscriptionStatus::Failed(_))
+        });
+
+        if already_subscribed {
+            return Err(format!("Already subscribed to '{}'", filter.filter));
+        }
+
+        self.remove_subscription(&filter.filter);
+        self.add_pending_subscription(&filter.filter, filter.qos);
+
+        Ok(())
+    }
+
+    /// Forgets the subscription to `filter`, if any.
+    pub fn remove_subscription(&mut self, filter: &str) {
+        self.subscriptions.retain(|s| s.filter != filter);
+    }
+
+    /// Starts unsubscribing the subscription at `index`. Returns the filter to send in the
+    /// UNSUBSCRIBE, or `None` when nothing has to be sent: failed subscriptions are simply
+    /// dropped, and pending ones must wait for their SUBACK first.
+    pub fn request_unsubscribe(&mut self, index: usize) -> Result<Option<String>, String> {
+        let Some(subscription) = self.subscriptions.get_mut(index) else {
+            return Ok(None);
+        };
+
+        match subscription.status {
+            SubscriptionStatus::Granted(_) => {
+                subscription.status = SubscriptionStatus::Unsubscribing;
+                subscription.pkid = None;
+                Ok(Some(subscription.filter.clone()))
+            }
+            SubscriptionStatus::Failed(_) => {
+                self.subscriptions.remove(index);
+                Ok(None)
+            }
+            SubscriptionStatus::Pending => {
+                Err(format!("'{}' is still waiting for its SUBACK", subscription.filter))
+            }
+            SubscriptionStatus::Unsubscribing => Ok(None),
+        }
+    }
+
+    /// Associates a sent UNSUBSCRIBE packet with the oldest filter being unsubscribed.
+    pub fn assign_unsubscribe_pkid(&mut self, pkid: u16) {
+        if let Some(subscription) = self
+            .subscriptions
+            .iter_mut()
+            .find(|s| s.pkid.is_none() && s.status == SubscriptionStatus::Unsubscribing)
+        {
+            subscription.pkid = Some(pkid);
+        }
+    }
+
+    /// Records the broker's answer to the UNSUBSCRIBE sent with `pkid`: the filter is
+    /// removed, or kept as subscribed when the broker refused, with the reason shown in
+    /// the subscriptions panel.
+    pub fn apply_unsuback(&mut self, pkid: u16, error: Option<String>) {
+        let Some(index) = self
+            .subscriptions
+            .iter()
+            .position(|s| s.pkid == Some(pkid) && s.status == SubscriptionStatus::Unsubscribing)
+        else {
+            return;
+        };
+
+        match error {
+            Some(reason) => {
+                let subscription = &mut self.subscriptions[index];
+                subscription.status = SubscriptionStatus::Granted(subscription.requested_qos);
+
+                if let Some(panel) = self.subscriptions_panel.as_mut() {
+                    panel.error = Some(format!(
+                        "Unsubscribe from '{}' refused: {}",
+                        subscription.filter, reason
+                    ));
+                }
+            }
+            None => {
+                self.subscriptions.remove(index);
+            }
+        }
+    }
+
+    /// Prepares the subscriptions for the new session after a reconnect and returns the
+    /// filters to subscribe to again. The client starts a clean session and drops the
+    /// requests it had queued, so every pending or granted filter is reset to pending and
+    /// sent again. Unsubscribes in progress are dropped.
+    pub fn prepare_resubscribe(&mut self) -> Vec<SubscriptionFilter> {
+        self.subscriptions
+            .retain(|s| s.status != SubscriptionStatus::Unsubscribing);
+
+        let mut filters = Vec::new();
+
+        for subscription in &mut self.subscriptions {
+            if matches!(
+                subscription.status,
+                SubscriptionStatus::Pending | SubscriptionStatus::Granted(_)
+            ) {
+                subscription.status = SubscriptionStatus::Pending;
+                subscription.pkid = None;
+                filters.push(SubscriptionFilter {
+                    filter: subscription.filter.clone(),
+                    qos: subscription.requested_qos,
+                });
+            }
+        }
+
+        filters
+    }
+
+    /// Registers a message that was just queued for publishing. The oldest publications
+    /// are forgotten once `MAX_PUBLICATIONS` are kept.
+    pub fn add_pending_publication(&mut self, topic: &str, qos: u8) {
+        if self.publications.len() == MAX_PUBLICATIONS {
+            self.publications.pop_front();
+        }
+
+        self.publications.push_back(Publication {
+            topic: topic.to_string(),
+            qos,
+            pkid: None,
+            status: PublishStatus::Queued,
+        });
+    }
+
+    /// Fails the publications not acknowledged yet when the connection was lost. The
+    /// client starts a clean session and drops the requests it had queued or in flight,
+    /// so only messages published after the reconnect are sent.
+    pub fn fail_unsent_publications(&mut self) {
+        for publication in &mut self.publications {
+            if matches!(
+                publication.status,
+                PublishStatus::Queued | PublishStatus::AwaitingAck
+            ) {
+                publication.status = PublishStatus::Failed("connection lost".into());
+            }
+        }
+    }
+
+    /// Associates a sent PUBLISH packet with the oldest queued publication. QoS 0
+    /// messages are not acknowledged and are delivered as far as the client knows.
+    pub fn assign_publish_pkid(&mut self, pkid: u16) {
+        if let Some(publication) = self
+            .publications
+            .iter_mut()
+            .find(|p| p.status == PublishStatus::Queued)
+        {
+            publication.pkid = Some(pkid);
+            publication.status = match publication.qos {
+                0 => PublishStatus::Delivered,
+                _ => PublishStatus::AwaitingAck,
+            };
+        }
+    }
+
+    /// The publication at `qos` waiting for an acknowledgement of `pkid`.
+    fn awaiting_publication(&mut self, pkid: u16, qos: u8) -> Option<&mut Publication> {
+        self.publications.iter_mut().find(|p| {
+            p.pkid == Some(pkid) && p.qos == qos && p.status == PublishStatus::AwaitingAck
+        })
+    }
+
+    /// Records the PUBACK of a QoS 1 publication.
+    pub fn apply_puback(&mut self, pkid: u16, error: Option<String>) {
+        if let Some(publication) = self.awaiting_publication(pkid, 1) {
+            publication.status = error.map_or(PublishStatus::Delivered, PublishStatus::Failed);
+        }
+    }
+
+    /// Records the PUBREC of a QoS 2 publication, which only ends the exchange when the
+    /// broker refused the message.
+    pub fn apply_pubrec(&mut self, pkid: u16, error: Option<String>) {
+        if let Some(publication) = self.awaiting_publication(pkid, 2)
+            && let Some(reason) = error
+        {
+            publication.status = PublishStatus::Failed(reason);
+        }
+    }
+
+    /// Records the PUBCOMP of a QoS 2 publication.
+    pub fn apply_pubcomp(&mut self, pkid: u16, error: Option<String>) {
+        if let Some(publication) = self.awaiting_publication(pkid, 2) {
+            publication.status = error.map_or(PublishStatus::Delivered, PublishStatus::Failed);
+        }
+    }
+
+    /// Opens the publish composer with the topic selected in the topic list, or the
+    /// selected level of the tree.
+    pub fn open_composer(&mut self) {
+        let topic = self.publish_topic();
+        self.composer = Some(PublishComposer::new(&topic, self.protocol == ProtocolVersion::V5));
+    }
+
+    /// Topic offered when publishing: the selected topic, or the selected level of the
+    /// tree.
+    fn publish_topic(&self) -> String {
+        match (self.selected_topic(), self.view) {
+            (Some(topic), _) => topic.name.clone(),
+            (None, TopicView::Tree) => self.tree.selected.clone().unwrap_or_default(),
+            (None, TopicView::Flat) => String::new(),
+        }
+    }
+
+    /// Opens the publish composer prefilled with the selected message.
+    pub fn resend_selected_message(&mut self) {
+        let (Some(topic), Some(message)) = (self.selected_topic(), self.selected_message()) else {
+            return;
+        };
+
+        let composer =
+            PublishComposer::resend(&topic.name, message, self.protocol == ProtocolVersion::V5);
+        self.composer = Some(composer);
+    }
+
+    /// Asks for confirmation before clearing the retained message of the selected topic,
+    /// or of every retained topic under the selected node of the tree.
+    pub fn open_clear_retained(&mut self) {
+        let scope = match self.view {
+            TopicView::Flat => self.topics.get(self.selected_index).map(|t| t.name.clone()),
+            TopicView::Tree => self.tree.selected.clone(),
+        };
+        let Some(scope) = scope else {
+            return;
+        };
+        let subtree = format!("{}/", scope);
+
+        let topics = self
+            .topics
+            .iter()
+            .filter(|topic| topic.retained)
+            .filter(|topic| {
+                topic.name == scope
+                    || (self.view == TopicView::Tree && topic.name.starts_with(&subtree))
+            })
+            .map(|topic| topic.name.clone())
+            .collect();
+
+        self.clear_retained = Some(ClearRetained {
+            scope,
+            topics,
+            error: None,
+        });
+    }
+
+    /// Records that the retained message of `topic` was cleared, and removes it from
+    /// the confirmation.
+    pub fn mark_retained_cleared(&mut self, topic: &str) {
+        if let Some(activity) = self.topics.iter_mut().find(|t| t.name == topic) {
+            activity.retained = false;
+        }
+        if let Some(clear) = self.clear_retained.as_mut() {
+            clear.topics.retain(|t| t != topic);
+        }
+    }
+
+    /// The message under the cursor in the selected topic.
+    pub fn selected_message(&self) -> Option<&MessageActivity> {
+        let topic = self.selected_topic()?;
+        let position = self.selected_position()?;
+
+        match position.checked_sub(topic.dropped) {
+            Some(index) => topic.messages.get(index as usize),
+            None => self.history_page.as_ref()?.get(&topic.name, position),
+        }
+    }
+
+    /// Position of the oldest message of `topic` that can be shown: the first one ever
+    /// received when the older messages are kept on disk.
+    pub fn first_position(&self, topic: &TopicActivity) -> u64 {
+        if self.history.is_some() { 0 } else { topic.dropped }
+    }
+
+    /// Position of the message under the cursor among all messages ever received on the
+    /// selected topic: the newest one while following.
+    pub fn selected_position(&self) -> Option<u64> {
+        let topic = self.selected_topic()?;
+        let first = self.first_position(topic);
+        let last = (topic.dropped + topic.messages.len() as u64).checked_sub(1)?;
+        if last < first {
+            return None;
+        }
+
+        Some(self.message_cursor.map_or(last, |cursor| cursor.clamp(first, last)))
+    }
+
+    /// Number of messages of the selected topic that can be shown.
+    pub fn message_count(&self) -> u64 {
+        self.selected_topic().map_or(0, |topic| {
+            topic.dropped + topic.messages.len() as u64 - self.first_position(topic)
+        })
+    }
+
+    /// The messages listed in the activity panel within `rows` of the cursor: the page
+    /// read from disk when the cursor is on an older message than those kept in memory.
+    pub fn shown_messages(&self, rows: usize) -> MessageWindow<'_> {
+        let (Some(topic), Some(position)) = (self.selected_topic(), self.selected_position())
+        else {
+            return MessageWindow::default();
+        };
+
+        if position < topic.dropped
+            && let Some(page) = &self.history_page
+            && page.get(&topic.name, position).is_some()
+        {
+            let index = (position - page.first) as usize;
+            return MessageWindow::around(page.messages.iter(), Some(index), rows);
+        }
+
+        let index = position.checked_sub(topic.dropped).map(|index| index as usize);
+        MessageWindow::around(topic.messages.iter(), index, rows)
+    }
+
+    /// Index of the message under the cursor among the listed messages.
+    pub fn selected_message_index(&self) -> Option<usize> {
+        let window = self.shown_messages(0);
+        window.selected.map(|index| window.first + index)
+    }
+
+    /// Points the message cursor at `position` in the selected topic.
+    fn set_message_cursor(&mut self, position: u64) {
+        self.message_cursor = Some(position);
+        self.detail_scroll = 0;
+    }
+
+    /// Whether the message cursor follows the newest message.
+    pub fn is_following(&self) -> bool {
+        self.message_cursor.is_none()
+    }
+
+    /// Moves the message cursor `count` messages up, pausing follow mode.
+    pub fn message_up(&mut self, count: usize) {
+        if let Some(position) = self.selected_position() {
+            self.set_message_cursor(position.saturating_sub(count as u64));
+        }
+    }
+
+    /// Moves the message cursor `count` messages down. Reaching the newest message
+    /// resumes follow mode.
+    pub fn message_down(&mut self, count: usize) {
+        let Some(position) = self.selected_position() else {
+            return;
+        };
+        let last = self.selected_topic().map_or(0, |topic| {
+            (topic.dropped + topic.messages.len() as u64).saturating_sub(1)
+        });
+
+        if position + (count as u64) < last {
+            self.set_message_cursor(position + count as u64);
+        } else {
+            self.message_cursor = None;
+            self.detail_scroll = 0;
+        }
+    }
+
+    /// Moves the message cursor to the oldest message, pausing follow mode.
+    pub fn message_home(&mut self) {
+        if self.selected_position().is_some() {
+            self.set_message_cursor(0);
+        }
+    }
+
+    /// Moves the message cursor to the newest message and resumes follow mode.
+    pub fn message_end(&mut self) {
+        self.message_cursor = None;
+        self.detail_scroll = 0;
+    }
+
+    /// Moves the Up/Down keys to the next pane. The detail panel is skipped when closed.
+    pub fn switch_pane(&mut self) {
+        self.focused_pane = match self.focused_pane {
+            Pane::Topics => Pane::Messages,
+            Pane::Messages if self.detail_panel.is_some() => Pane::Detail,
+            Pane::Messages | Pane::Detail => Pane::Topics,
+        };
+    }
+
+    /// Opens `panel` below the message list, or closes it when it is already open.
+    pub fn toggle_detail_panel(&mut self, panel: DetailPanel) {
+        if self.detail_panel == Some(panel) {
+            self.detail_panel = None;
+            if self.focused_pane == Pane::Detail {
+                self.focused_pane = Pane::Messages;
+            }
+        } else {
+            self.detail_panel = Some(panel);
+        }
+        self.detail_scroll = 0;
+    }
+
+    /// Scrolls the detail panel `count` lines up.
+    pub fn detail_up(&mut self, count: usize) {
+        self.detail_scroll = self.detail_scroll.saturating_sub(count);
+    }
+
+    /// Scrolls the detail panel `count` lines down, stopping at its last line.
+    /// The properties panel fits on screen and does not scroll.
+    pub fn detail_down(&mut self, count: usize) {
+        let lines = match (self.detail_panel, self.selected_topic(), self.selected_message()) {
+            (Some(DetailPanel::HexDump), _, Some(message)) => {
+                hex_dump::line_count(message.payload.len())
+            }
+            (Some(DetailPanel::Decoded), Some(topic), Some(message)) => {
+                self.decoded(&topic.name, message).value.tree_lines().len()
+            }
+            _ => 0,
+        };
+
+        self.detail_scroll = (self.detail_scroll + count).min(lines.saturating_sub(1));
+    }
+
+    /// The payload of `message`, received on `topic`, decoded with the decoder
+    /// configured for the topic. Decoded once and cached in the message.
+    pub fn decoded<'a>(&self, topic: &str, message: &'a MessageActivity) -> &'a DecodedPayload {
+        message
+            .decoded
+            .get_or_init(|| self.decoders.decode(topic, &message.payload))
+    }
+
+    /// Opens the JSON popup for the selected message.
+    pub fn open_json_view(&mut self) {
+        let (Some(topic), Some(message)) = (self.selected_topic(), self.selected_message()) else {
+            return;
+        };
+
+        let title = format!("{} @ {}", topic.name, message.timestamp);
+        let decoded = self.decoded(&topic.name, message);
+        let view = JsonView::new(title, &message.payload, Some(&decoded.value));
+
+        self.json_view = Some(view);
+    }
+
+    /// Switches the selected topic to the next decoder, starting over with `auto`
+    /// after the last one, and decodes its messages again.
+    pub fn cycle_decoder(&mut self) {
+        let Some(topic) = self.selected_topic().map(|topic| topic.name.clone()) else {
+            return;
+        };
+        let names = self.decoders.names();
+        let current = self.decoders.decoder_for(&topic);
+        let next = names
+            .iter()
+            .position(|name| *name == current)
+            .map_or(AUTO, |index| names[(index + 1) % names.len()])
+            .to_string();
+
+        self.decoders.set_topic_decoder(&topic, &next);
+
+        if let Some(topic) = self.topics.iter_mut().find(|t| t.name == topic) {
+            for message in topic.messages.iter_mut() {
+                message.decoded.take();
+            }
+        }
+        self.history_page = None;
+        self.detail_scroll = 0;
+    }
+
+    /// The selected topic. In the tree view this is `None` when the selected node is
+    /// only a level of other topics' names.
+    pub fn selected_topic(&self) -> Option<&TopicActivity> {
+        match self.view {
+            TopicView::Flat => self.topics.get(self.selected_index),
+            TopicView::Tree => {
+                let rows = self.tree.rows(&self.topics);
+                let index = rows.get(self.tree.selected_position(&rows))?.topic_index?;
+                self.topics.get(index)
+            }
+        }
+    }
+
+    /// Switches between the flat list and the tree, keeping the selected topic.
+    pub fn toggle_view(&mut self) {
+        match self.view {
+            TopicView::Flat => {
+                if let Some(topic) = self.topics.get(self.selected_index) {
+                    self.tree.reveal(&topic.name);
+                }
+                self.view = TopicView::Tree;
+            }
+            TopicView::Tree => self.view = TopicView::Flat,
+        }
+    }
+
+    /// Applies `action` to the tree with its current rows, then points `selected_index`
+    /// at the selected node's topic so the flat list keeps the selection.
+    /// Does nothing while the flat list is shown.
+    fn update_tree(&mut self, action: impl FnOnce(&mut TopicTree, &[TreeRow])) {
+        if self.view != TopicView::Tree {
+            return;
+        }
+
+        let rows = self.tree.rows(&self.topics);
+        let previous = self.tree.selected.clone();
+        action(&mut self.tree, &rows);
+
+        if self.tree.selected != previous {
+            self.message_cursor = None;
+            self.detail_scroll = 0;
+        }
+
+        let rows = self.tree.rows(&self.topics);
+        if let Some(index) = rows
+            .get(self.tree.selected_position(&rows))
+            .and_then(|row| row.topic_index)
+        {
+            self.selected_index = index;
+        }
+    }
+
+    /// Expands the selected tree node.
+    pub fn expand_selected(&mut self) {
+        self.update_tree(TopicTree::expand);
+    }
+
+    /// Collapses the selected tree node, or moves to its parent.
+    pub fn collapse_selected(&mut self) {
+        self.update_tree(TopicTree::collapse);
+    }
+
+    /// Expands or collapses the selected tree node.
+    pub fn toggle_selected(&mut self) {
+        self.update_tree(TopicTree::toggle);
+    }
+
+    /// Expands every node of the tree.
+    pub fn expand_all(&mut self) {
+        if self.view == TopicView::Tree {
+            self.tree.expand_all(&self.topics);
+        }
+    }
+
+    /// Collapses every node of the tree.
+    pub fn collapse_all(&mut self) {
+        self.update_tree(|tree, _| tree.collapse_all());
+    }
+
+    /// Move the selection to the next topic in the list.
+    pub fn next(&mut self) {
+        if self.view == TopicView::Tree {
+            self.update_tree(TopicTree::next);
+            return;
+        }
+
+        if !self.topics.is_empty() {
+            self.selected_index = (self.selected_index + 1) % self.topics.len();
+            self.message_cursor = None;
+            self.detail_scroll = 0;
+        }
+    }
+
+    /// Move the selection to the previous topic in the list.
+    pub fn previous(&mut self) {
+        if self.view == TopicView::Tree {
+            self.update_tree(TopicTree::previous);
+            return;
+        }
+
+        if !self.topics.is_empty() {
+            if self.selected_index == 0 {
+                self.selected_index = self.topics.len() - 1;
+            } else {
+                self.selected_index -= 1;
+            }
+            self.message_cursor = None;
+            self.detail_scroll = 0;
+        }
+    }
+}
+
+
+/// Represents the fields in the configuration form.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FocusField {
+    Host,
+    Port,
+    Username,
+    Password,
+    Protocol,
+    Topics,
+    Tls,
+    TlsCaPath,
+    TlsClientCert,
+    TlsClientKey,
+    TlsInsecure,
+    TlsServerName,
+}
+
+impl FocusField {
+    /// Order in which Tab/Shift+Tab move through the form.
+    const ORDER: [FocusField; 12] = [
+        FocusField::Host,
+        FocusField::Port,
+        FocusField::Username,
+        FocusField::Password,
+        FocusField::Protocol,
+        FocusField::Topics,
+        FocusField::Tls,
+        FocusField::TlsCaPath,
+        FocusField::TlsClientCert,
+        FocusField::TlsClientKey,
+        FocusField::TlsInsecure,
+        FocusField::TlsServerName,
+    ];
+
+    fn position(self) -> usize {
+        Self::ORDER.iter().position(|f| *f == self).unwrap_or(0)
+    }
+}
+
+/// Represents the state of the configuration form.
+pub struct ConfigFormState {
+    pub host: String,
+    pub port: String,
+    pub username: String,
+    pub password: String,
+    pub protocol: ProtocolVersion,
+    /// Comma separated `filter[:qos]` list. Empty subscribes to `#`.
+    pub topics: String,
+    /// Whether to connect over TLS.
+    pub tls: bool,
+    pub tls_ca_path: String,
+    pub tls_client_cert: String,
+    pub tls_client_key: String,
+    /// Skip server certificate verification (lab brokers only).
+    pub tls_insecure: bool,
+    pub tls_server_name: String,
+    pub focus: FocusField,
+    pub error: Option<String>,
+    /// When true, the form is attempting to connect to the broker.
+    pub connecting: bool,
+    /// Spinner index for animated ellipsis (0..=3)
+    pub spinner_idx: usize,
+}
+
+impl ConfigFormState {
+    pub fn new() -> Self {
+        Self {
+            host: "".into(),
+            port: "".into(),
+            username: "".into(),
+            password: "".into(),
+            protocol: ProtocolVersion::V311,
+            topics: "".into(),
+            tls: false,
+            tls_ca_path: "".into(),
+            tls_client_cert: "".into(),
+            tls_client_key: "".into(),
+            tls_insecure: false,
+            tls_server_name: "".into(),
+            focus: FocusField::Host,
+            error: None,
+            connecting: false,
+            spinner_idx: 0,
+        }
+    }
+
+    /// Move focus to the next field in the form.
+    pub fn next_field(&mut self) {
+        let next = (self.focus.position() + 1) % FocusField::ORDER.len();
+        self.focus = FocusField::ORDER[next];
+    }
+
+    /// Move focus to the previous field in the form.
+    pub fn prev_field(&mut self) {
+        let len = FocusField::ORDER.len();
+        let prev = (self.focus.position() + len - 1) % len;
+        self.focus = FocusField::ORDER[prev];
+    }
+
+    /// Returns the text of the currently focused field, or `None` for toggle fields.
+    fn focused_field_mut(&mut self) -> Option<&mut String> {
+        match self.focus {
+            FocusField::Host => Some(&mut self.host),
+            FocusField::Port => Some(&mut self.port),
+            FocusField::Username => Some(&mut self.username),
+            FocusField::Password => Some(&mut self.password),
+            FocusField::Topics => Some(&mut self.topics),
+            FocusField::TlsCaPath => Some(&mut self.tls_ca_path),
+            FocusField::TlsClientCert => Some(&mut self.tls_client_cert),
+            FocusField::TlsClientKey => Some(&mut self.tls_client_key),
+            FocusField::TlsServerName => Some(&mut self.tls_server_name),
+            FocusField::Protocol | FocusField::Tls | FocusField::TlsInsecure => None,
+        }
+    }
+
+    /// Insert a character into the currently focused field.
+    /// On toggle fields, a space flips the value instead.
+    pub fn insert_char(&mut self, c: char) {
+        match self.focus {
+            FocusField::Protocol if c == ' ' => {
+                self.protocol = match self.protocol {
+                    ProtocolVersion::V311 => ProtocolVersion::V5,
+                    ProtocolVersion::V5 => ProtocolVersion::V311,
+                };
+            }
+            FocusField::Tls if c == ' ' => self.tls = !self.tls,
+            FocusField::TlsInsecure if c == ' ' => self.tls_insecure = !self.tls_insecure,
+            _ => {
+                if let Some(field) = self.focused_field_mut() {
+                    field.push(c);
+                }
+            }
+        }
+    }
+
+    /// Delete the last character from the currently focused field.
+    pub fn delete_char(&mut self) {
+        if let Some(field) = self.focused_field_mut() {
+            field.pop();
+        }
+    }
+
+    /// Builds the MQTT configuration from the current form values.
+    pub fn config(&self) -> Result<MQTTConfig, String> {
+        let port = self
+            .port
+            .parse::<u16>()
+            .map_err(|_| "Port must be a valid number".to_string())?;
+        let subscriptions = SubscriptionFilter::parse_list(&self.topics)?;
+
+        let tls = self.tls.then(|| TlsSettings {
+            ca_path: non_empty(&self.tls_ca_path).map(PathBuf::from),
+            client_cert_path: non_empty(&self.tls_client_cert).map(PathBuf::from),
+            client_key_path: non_empty(&self.tls_client_key).map(PathBuf::from),
+            insecure_skip_verify: self.tls_insecure,
+            server_name: non_empty(&self.tls_server_name),
+        });
+
+        Ok(MQTTConfig {
+            host: self.host.clone(),
+            port,
+            username: non_empty(&self.username),
+            password: non_empty(&self.password),
+            tls,
+            protocol: self.protocol,
+            subscriptions,
+            ..Default::default()
+        })
+    }
+}
+
+/// Returns `Some(value)` for non-empty form values.
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+impl Default for ConfigFormState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A received message with a text payload.
+    pub(super) fn message(payload: &str, received_at: OffsetDateTime) -> MessageActivity {
+        MessageActivity {
+            payload: bytes::Bytes::copy_from_slice(payload.as_bytes()),
+            timestamp: "".into(),
+            received_at,
+            properties: None,
+            qos: 0,
+            retain: false,
+            decoded: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_app_state_next() {
+        let mut menu_state = TopicActivityMenuState::new();
+
+        menu_state.topics.push(
+            TopicActivity::new("topic1")
+        );
+
+        menu_state.topics.push(
+            TopicActivity::new("topic2")
+        );
+
+        assert_eq!(menu_state.selected_index, 0);
+        menu_state.next();
+        assert_eq!(menu_state.selected_index, 1);
+        menu_state.next();
+        assert_eq!(menu_state.selected_index, 0);
+    }
+
+    #[test]
+    fn test_toggle_view_keeps_the_selected_topic() {
+        let mut menu_state = TopicActivityMenuState::new();
+
+        for name in ["site/a/temp", "site/b/temp", "status"] {
+            menu_state.topics.push(TopicActivity::new(name));
+        }
+
+        menu_state.selected_index = 1;
+        menu_state.toggle_view();
+        assert_eq!(menu_state.selected_topic().unwrap().name, "site/b/temp");
+
+        menu_state.next();
+        assert_eq!(menu_state.selected_topic().unwrap().name, "status");
+        menu_state.next();
+        assert_eq!(menu_state.tree.selected.as_deref(), Some("site"));
+        assert!(menu_state.selected_topic().is_none());
+
+        menu_state.previous();
+        menu_state.toggle_view();
+        assert_eq!(menu_state.selected_topic().unwrap().name, "status");
+    }
+
+    #[test]
+    fn test_message_cursor_pauses_and_resumes_follow_mode() {
+        let mut menu_state = TopicActivityMenuState::new();
+        for i in 0..5 {
+            menu_state.add_message("topic1", message(&i.to_string(), OffsetDateTime::now_utc()));
+        }
+
+        assert!(menu_state.is_following());
+        assert_eq!(menu_state.selected_message_index(), Some(4));
+
+        menu_state.message_up(1);
+        assert!(!menu_state.is_following());
+        assert_eq!(menu_state.selected_message().unwrap().payload, "3");
+
+        menu_state.message_up(10);
+        assert_eq!(menu_state.selected_message_index(), Some(0));
+
+        menu_state.message_down(2);
+        assert_eq!(menu_state.selected_message_index(), Some(2));
+        assert!(!menu_state.is_following());
+
+        menu_state.message_down(2);
+        assert!(menu_state.is_following());
+
+        menu_state.message_home();
+        assert_eq!(menu_state.selected_message_index(), Some(0));
+        menu_state.message_end();
+        assert_eq!(menu_state.selected_message_index(), Some(4));
+    }
+
+    #[test]
+    fn test_shown_messages_are_a_window_around_the_cursor() {
+        let mut menu_state = TopicActivityMenuState::new();
+        for i in 0..100 {
+            menu_state.add_message("topic1", message(&i.to_string(), OffsetDateTime::now_utc()));
+        }
+
+        let window = menu_state.shown_messages(10);
+        assert_eq!((window.first, window.messages.len(), window.selected), (89, 11, Some(10)));
+
+        menu_state.message_up(50);
+        let window = menu_state.shown_messages(10);
+        assert_eq!((window.first, window.messages.len(), window.selected), (39, 21, Some(10)));
+        assert_eq!(window.messages[10].payload, "49");
+    }
+
+    #[test]
+    fn test_cycle_decoder_reaches_auto_under_a_wildcard_override() {
+        let mut menu_state = TopicActivityMenuState::new();
+        menu_state.decoders.add_override("plant/#=json".parse().unwrap()).unwrap();
+        menu_state.add_message("plant/temp", message("21.5", OffsetDateTime::now_utc()));
+
+        let names: Vec<String> =
+            menu_state.decoders.names().iter().map(|name| name.to_string()).collect();
+        let json = names.iter().position(|name| name == "json").unwrap();
+        for name in names[json + 1..].iter().chain(&names[..=json]) {
+            menu_state.cycle_decoder();
+            assert_eq!(menu_state.decoders.decoder_for("plant/temp"), name);
+        }
+        assert_eq!(menu_state.decoders.decoder_for("plant/other"), "json");
+    }
+
+    #[test]
+    fn test_suback_is_matched_to_its_filter() {
+        let mut menu_state = TopicActivityMenuState::new();
+
+        menu_state.add_pending_subscription("sensors/#", 1);
+        menu_state.add_pending_subscription("secret/#", 0);
+
+        menu_state.assign_subscribe_pkid(1);
+        menu_state.assign_subscribe_pkid(2);
+
+        menu_state.apply_suback(2, &[Err("rejected (0x80)".into())]);
+        menu_state.apply_suback(1, &[Ok(0)]);
+
+        assert_eq!(menu_state.subscriptions[0].status, SubscriptionStatus::Granted(0));
+        assert_eq!(
+            menu_state.subscriptions[1].status,
+            SubscriptionStatus::Failed("rejected (0x80)".into())
+        );
+    }
+
+    #[test]
+    fn test_runtime_subscribe_and_unsubscribe() {
+        let mut menu_state = TopicActivityMenuState::new();
+        let filter: SubscriptionFilter = "sensors/#:1".parse().unwrap();
+
+        menu_state.request_subscription(&filter).unwrap();
+        assert!(menu_state.request_subscription(&filter).is_err());
+
+        menu_state.assign_subscribe_pkid(7);
+        assert!(menu_state.request_unsubscribe(0).is_err());
+        menu_state.apply_suback(7, &[Ok(1)]);
+
+        assert_eq!(menu_state.request_unsubscribe(0), Ok(Some("sensors/#".into())));
+        assert_eq!(menu_state.subscriptions[0].status, SubscriptionStatus::Unsubscribing);
+
+        menu_state.assign_unsubscribe_pkid(8);
+        menu_state.apply_unsuback(8, None);
+        assert!(menu_state.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn test_prepare_resubscribe_sends_pending_and_granted_filters() {
+        let mut menu_state = TopicActivityMenuState::new();
+
+        menu_state.add_pending_subscription("granted/#", 1);
+        menu_state.add_pending_subscription("rejected/#", 0);
+        menu_state.assign_subscribe_pkid(1);
+        menu_state.assign_subscribe_pkid(2);
+        menu_state.apply_suback(1, &[Ok(1)]);
+        menu_state.apply_suback(2, &[Err("rejected (0x80)".into())]);
+        menu_state.add_pending_subscription("queued/#", 2);
+
+        let filters = menu_state.prepare_resubscribe();
+
+        assert_eq!(
+            filters,
+            vec!["granted/#:1".parse().unwrap(), "queued/#:2".parse().unwrap()]
+        );
+        let statuses: Vec<&SubscriptionStatus> =
+            menu_state.subscriptions.iter().map(|s| &s.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                &SubscriptionStatus::Pending,
+                &SubscriptionStatus::Failed("rejected (0x80)".into()),
+                &SubscriptionStatus::Pending,
+            ]
+        );
+
+        menu_state.assign_subscribe_pkid(3);
+        menu_state.assign_subscribe_pkid(4);
+        assert_eq!(menu_state.subscriptions[0].pkid, Some(3));
+        assert_eq!(menu_state.subscriptions[2].pkid, Some(4));
+    }
+
+    #[test]
+    fn test_failed_subscription_can_be_retried() {
+        let mut menu_state = TopicActivityMenuState::new();
+        let filter: SubscriptionFilter = "secret/#".parse().unwrap();
+
+        menu_state.request_subscription(&filter).unwrap();
+        menu_state.assign_subscribe_pkid(1);
+        menu_state.apply_suback(1, &[Err("rejected (0x80)".into())]);
+
+        menu_state.request_subscription(&filter).unwrap();
+        assert_eq!(menu_state.subscriptions.len(), 1);
+        assert_eq!(menu_state.subscriptions[0].status, SubscriptionStatus::Pending);
+    }
+
+    #[test]
+    fn test_publish_acks_are_matched_to_their_publication() {
+        let mut menu_state = TopicActivityMenuState::new();
+
+        menu_state.add_pending_publication("lamp/set", 0);
+        menu_state.add_pending_publication("door/set", 1);
+        menu_state.add_pending_publication("valve/set", 2);
+        menu_state.add_pending_publication("siren/set", 2);
+
+        menu_state.assign_publish_pkid(0);
+        menu_state.assign_publish_pkid(1);
+        menu_state.assign_publish_pkid(2);
+        menu_state.assign_publish_pkid(3);
+        assert_eq!(menu_state.publications[0].status, PublishStatus::Delivered);
+        assert_eq!(menu_state.publications[1].status, PublishStatus::AwaitingAck);
+
+        menu_state.apply_puback(1, None);
+        menu_state.apply_pubrec(2, None);
+        menu_state.apply_pubrec(3, Some("NotAuthorized".into()));
+        assert_eq!(menu_state.publications[2].status, PublishStatus::AwaitingAck);
+        menu_state.apply_pubcomp(2, None);
+
+        let statuses: Vec<&PublishStatus> =
+            menu_state.publications.iter().map(|p| &p.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                &PublishStatus::Delivered,
+                &PublishStatus::Delivered,
+                &PublishStatus::Delivered,
+                &PublishStatus::Failed("NotAuthorized".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reconnect_fails_dropped_publications() {
+        let mut menu_state = TopicActivityMenuState::new();
+
+        menu_state.add_pending_publication("door/set", 1);
+        menu_state.add_pending_publication("lamp/set", 1);
+        menu_state.assign_publish_pkid(1);
+        menu_state.fail_unsent_publications();
+
+        menu_state.add_pending_publication("valve/set", 1);
+        menu_state.assign_publish_pkid(1);
+        menu_state.apply_puback(1, None);
+
+        let statuses: Vec<&PublishStatus> =
+            menu_state.publications.iter().map(|p| &p.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                &PublishStatus::Failed("connection lost".into()),
+                &PublishStatus::Failed("connection lost".into()),
+                &PublishStatus::Delivered,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clear_retained_lists_the_retained_topics_of_the_subtree() {
+        let mut menu_state = TopicActivityMenuState::new();
+        let messages = [
+            ("site/a/temp", "21", true),
+            ("site/a", "on", true),
+            ("site/b/temp", "19", false),
+            ("site/c/temp", "18", true),
+            ("site/c/temp", "", false),
+            ("sites/x", "1", true),
+        ];
+        for (topic, payload, retain) in messages {
+            menu_state.add_message(
+                topic,
+                MessageActivity {
+                    retain,
+                    ..message(payload, OffsetDateTime::now_utc())
+                },
+            );
+        }
+
+        menu_state.selected_index = 1;
+        menu_state.open_clear_retained();
+        assert_eq!(menu_state.clear_retained.as_ref().unwrap().topics, vec!["site/a"]);
+
+        menu_state.toggle_view();
+        menu_state.collapse_selected();
+        assert_eq!(menu_state.tree.selected.as_deref(), Some("site"));
+        menu_state.open_clear_retained();
+
+        let requests = menu_state.clear_retained.as_ref().unwrap().requests();
+        let topics: Vec<&str> = requests.iter().map(|r| r.topic.as_str()).collect();
+        assert_eq!(topics, vec!["site/a/temp", "site/a"]);
+        assert!(requests.iter().all(|r| r.retain && r.payload.is_empty()));
+
+        menu_state.mark_retained_cleared("site/a/temp");
+        assert_eq!(menu_state.clear_retained.as_ref().unwrap().topics, vec!["site/a"]);
+        assert!(!menu_state.topics[0].retained);
+    }
+
+    #[test]
+    fn test_config_form_focus_cycles_through_credentials() {
+        let mut form = ConfigFormState::new();
+
+        form.next_field();
+        form.next_field();
+        form.insert_char('u');
+        form.next_field();
+        form.insert_char('p');
+        form.insert_char('w');
+        form.delete_char();
+
+        assert_eq!(form.username, "u");
+        assert_eq!(form.password, "p");
+
+        form.prev_field();
+        form.prev_field();
+        assert_eq!(form.focus, FocusField::Port);
+        form.prev_field();
+        form.prev_field();
+        assert_eq!(form.focus, FocusField::TlsServerName);
+    }
+
+    #[test]
+    fn test_config_form_space_toggles_tls() {
+        let mut form = ConfigFormState::new();
+
+        while form.focus != FocusField::Tls {
+            form.next_field();
+        }
+        form.insert_char(' ');
+        assert!(form.tls);
+
+        form.insert_char('x');
+        form.delete_char();
+        assert!(form.tls);
+        assert!(form.tls_ca_path.is_empty());
+
+        form.prev_field();
+        form.prev_field();
+        assert_eq!(form.focus, FocusField::Protocol);
+        form.insert_char(' ');
+        assert_eq!(form.protocol, ProtocolVersion::V5);
+    }
+
+    #[test]
+    fn test_app_state_previous() {
+        let mut menu_state = TopicActivityMenuState::new();
+
+        menu_state.topics.push(
+            TopicActivity::new("topic1")
+        );
+
+        menu_state.topics.push(
+            TopicActivity::new("topic2")
+        );
+
+        assert_eq!(menu_state.selected_index, 0);
+        menu_state.previous();
+        assert_eq!(menu_state.selected_index, 1);
+        menu_state.previous();
+        assert_eq!(menu_state.selected_index, 0);
+    }
+
+}
\ No newline at end of file
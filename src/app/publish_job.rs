@@ -0,0 +1,494 @@
+//! Publish jobs, which publish a templated payload on a schedule to simulate devices.
+//! Jobs come from the `[[publish_job]]` sections of the configuration file or from the
+//! jobs overlay of the topic activity screen, where they are started and stopped.
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+use super::TopicActivityMenuState;
+use crate::mqtt::PublishRequest;
+
+/// Shortest interval between two messages of a job.
+pub const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Piece of a payload template.
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    /// Number of the message in the run of the job, starting at 1.
+    Counter,
+    /// Publish time in RFC 3339, UTC.
+    Timestamp,
+    /// Random version 4 UUID.
+    Uuid,
+    /// Random integer between two bounds, inclusive.
+    RandomInt(i64, i64),
+    /// Random number between two bounds, with as many decimals as the bounds have.
+    RandomFloat { min: f64, max: f64, decimals: usize },
+}
+
+/// Payload with placeholders replaced every time a job publishes: `{{counter}}`,
+/// `{{timestamp}}`, `{{uuid}}` and `{{random:MIN:MAX}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadTemplate {
+    source: String,
+    parts: Vec<TemplatePart>,
+}
+
+impl FromStr for PayloadTemplate {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(TemplatePart::Text(rest[..start].to_string()));
+            }
+
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| format!("Unclosed placeholder '{}'", &rest[start..]))?;
+            parts.push(parse_placeholder(after[..end].trim())?);
+            rest = &after[end + 2..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Text(rest.to_string()));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            parts,
+        })
+    }
+}
+
+/// Parses the text between `{{` and `}}`.
+fn parse_placeholder(name: &str) -> Result<TemplatePart, String> {
+    match name {
+        "counter" => return Ok(TemplatePart::Counter),
+        "timestamp" => return Ok(TemplatePart::Timestamp),
+        "uuid" => return Ok(TemplatePart::Uuid),
+        _ => {}
+    }
+
+    let bounds = name
+        .strip_prefix("random:")
+        .and_then(|range| range.split_once(':'))
+        .map(|(min, max)| (min.trim(), max.trim()))
+        .ok_or_else(|| format!("Unknown placeholder '{{{{{}}}}}'", name))?;
+    let invalid = || format!("Invalid random range '{}', expected random:MIN:MAX", name);
+
+    if let (Ok(min), Ok(max)) = (bounds.0.parse::<i64>(), bounds.1.parse::<i64>()) {
+        return (min <= max).then_some(TemplatePart::RandomInt(min, max)).ok_or_else(invalid);
+    }
+
+    let (Ok(min), Ok(max)) = (bounds.0.parse::<f64>(), bounds.1.parse::<f64>()) else {
+        return Err(invalid());
+    };
+    let decimals = |bound: &str| bound.split_once('.').map_or(0, |(_, digits)| digits.len());
+
+    (min <= max)
+        .then_some(TemplatePart::RandomFloat {
+            min,
+            max,
+            decimals: decimals(bounds.0).max(decimals(bounds.1)),
+        })
+        .ok_or_else(invalid)
+}
+
+impl PayloadTemplate {
+    /// The template as written.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The payload of message number `counter`, published at `now`.
+    pub fn render(&self, counter: u64, now: OffsetDateTime) -> String {
+        let mut payload = String::new();
+
+        for part in &self.parts {
+            match part {
+                TemplatePart::Text(text) => payload.push_str(text),
+                TemplatePart::Counter => payload.push_str(&counter.to_string()),
+                TemplatePart::Timestamp => {
+                    payload.push_str(&now.format(&Rfc3339).unwrap_or_default())
+                }
+                TemplatePart::Uuid => payload.push_str(&uuid::Uuid::new_v4().to_string()),
+                TemplatePart::RandomInt(min, max) => {
+                    payload.push_str(&fastrand::i64(*min..=*max).to_string())
+                }
+                TemplatePart::RandomFloat { min, max, decimals } => {
+                    let value = min + fastrand::f64() * (max - min);
+                    payload.push_str(&format!("{:.*}", decimals, value));
+                }
+            }
+        }
+
+        payload
+    }
+}
+
+/// A message published on a schedule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublishJob {
+    pub topic: String,
+    pub template: PayloadTemplate,
+    pub interval: Duration,
+    /// Messages published before the job stops, `None` to publish until stopped.
+    pub count: Option<u64>,
+    pub qos: u8,
+    pub retain: bool,
+}
+
+impl PublishJob {
+    /// Checks the topic, interval and QoS.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.topic.is_empty() || !rumqttc::valid_topic(&self.topic) {
+            return Err(format!("Invalid topic '{}'", self.topic));
+        }
+        if self.interval < MIN_INTERVAL {
+            return Err(format!("The interval must be at least {}ms", MIN_INTERVAL.as_millis()));
+        }
+        if self.qos > 2 {
+            return Err(format!("Invalid QoS {}", self.qos));
+        }
+
+        Ok(())
+    }
+
+    /// Message number `counter` of the job.
+    pub fn request(&self, counter: u64, now: OffsetDateTime) -> PublishRequest {
+        PublishRequest {
+            topic: self.topic.clone(),
+            payload: self.template.render(counter, now).into(),
+            qos: self.qos,
+            retain: self.retain,
+            properties: None,
+        }
+    }
+}
+
+/// A publish job and its progress.
+#[derive(Debug)]
+pub struct JobState {
+    pub job: PublishJob,
+    /// Messages published since the job was last started.
+    pub sent: u64,
+    /// When the next message is due, `None` while the job is stopped.
+    next_due: Option<Instant>,
+    /// Why the job stopped publishing.
+    pub error: Option<String>,
+}
+
+impl JobState {
+    /// A stopped job.
+    pub fn new(job: PublishJob) -> Self {
+        Self {
+            job,
+            sent: 0,
+            next_due: None,
+            error: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.next_due.is_some()
+    }
+
+    /// Starts the job from its first message, published right away.
+    pub fn start(&mut self, now: Instant) {
+        self.sent = 0;
+        self.next_due = Some(now);
+        self.error = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.next_due = None;
+    }
+
+    /// Starts a stopped job and stops a running one.
+    pub fn toggle(&mut self, now: Instant) {
+        if self.is_running() {
+            self.stop();
+        } else {
+            self.start(now);
+        }
+    }
+}
+
+/// Fields of the form defining a new job.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JobField {
+    Topic,
+    Payload,
+    Interval,
+    Count,
+    Qos,
+    Retain,
+}
+
+impl JobField {
+    /// Order in which Tab/Shift+Tab move through the form.
+    const ORDER: [JobField; 6] = [
+        JobField::Topic,
+        JobField::Payload,
+        JobField::Interval,
+        JobField::Count,
+        JobField::Qos,
+        JobField::Retain,
+    ];
+}
+
+/// Form defining a new publish job.
+#[derive(Debug)]
+pub struct JobForm {
+    pub topic: String,
+    /// Payload template.
+    pub payload: String,
+    /// Milliseconds between two messages.
+    pub interval: String,
+    /// Messages to publish, empty or 0 to publish until stopped.
+    pub count: String,
+    pub qos: u8,
+    pub retain: bool,
+    pub focus: JobField,
+    pub error: Option<String>,
+}
+
+impl JobForm {
+    /// A form publishing to `topic` every second.
+    pub fn new(topic: &str) -> Self {
+        Self {
+            topic: topic.to_string(),
+            payload: String::new(),
+            interval: "1000".to_string(),
+            count: String::new(),
+            qos: 0,
+            retain: false,
+            focus: JobField::Topic,
+            error: None,
+        }
+    }
+
+    /// Move focus to the next field.
+    pub fn next_field(&mut self) {
+        let position = JobField::ORDER.iter().position(|f| *f == self.focus).unwrap_or(0);
+        self.focus = JobField::ORDER[(position + 1) % JobField::ORDER.len()];
+    }
+
+    /// Move focus to the previous field.
+    pub fn prev_field(&mut self) {
+        let len = JobField::ORDER.len();
+        let position = JobField::ORDER.iter().position(|f| *f == self.focus).unwrap_or(0);
+        self.focus = JobField::ORDER[(position + len - 1) % len];
+    }
+
+    /// Returns the text of the focused text field.
+    fn focused_field_mut(&mut self) -> Option<&mut String> {
+        match self.focus {
+            JobField::Topic => Some(&mut self.topic),
+            JobField::Payload => Some(&mut self.payload),
+            JobField::Interval => Some(&mut self.interval),
+            JobField::Count => Some(&mut self.count),
+            JobField::Qos | JobField::Retain => None,
+        }
+    }
+
+    /// Inserts a character into the focused field. On the QoS field a digit selects the
+    /// level and a space cycles through them; on the retain field a space toggles it.
+    pub fn insert_char(&mut self, c: char) {
+        match self.focus {
+            JobField::Qos => match c {
+                '0'..='2' => self.qos = c as u8 - b'0',
+                ' ' => self.qos = (self.qos + 1) % 3,
+                _ => {}
+            },
+            JobField::Retain if c == ' ' => self.retain = !self.retain,
+            JobField::Interval | JobField::Count if !c.is_ascii_digit() => {}
+            _ => {
+                if let Some(field) = self.focused_field_mut() {
+                    field.push(c);
+                }
+            }
+        }
+    }
+
+    /// Deletes the last character of the focused field.
+    pub fn delete_char(&mut self) {
+        if let Some(field) = self.focused_field_mut() {
+            field.pop();
+        }
+    }
+
+    /// The job defined by the form, or why it is invalid.
+    pub fn job(&self) -> Result<PublishJob, String> {
+        let interval = self
+            .interval
+            .parse()
+            .map_err(|_| format!("Invalid interval '{}'", self.interval))?;
+        let count = match self.count.as_str() {
+            "" => None,
+            count => Some(
+                count
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid count '{}'", count))?,
+            ),
+        };
+
+        let job = PublishJob {
+            topic: self.topic.trim().to_string(),
+            template: self.payload.parse()?,
+            interval: Duration::from_millis(interval),
+            count: count.filter(|count| *count > 0),
+            qos: self.qos,
+            retain: self.retain,
+        };
+        job.validate()?;
+
+        Ok(job)
+    }
+}
+
+/// State of the publish jobs overlay.
+#[derive(Debug, Default)]
+pub struct JobsPanel {
+    /// Index of the highlighted job.
+    pub selected: usize,
+    /// The form defining a new job, when open.
+    pub form: Option<JobForm>,
+}
+
+impl TopicActivityMenuState {
+    /// Publishes the next message of every running job that is due at `now` with
+    /// `publish`, and registers it as a pending publication. A job stops once it has
+    /// sent its count, or when a message cannot be published. A job that fell behind
+    /// publishes one message and starts again from `now` rather than catching up.
+    pub fn run_due_jobs(
+        &mut self,
+        now: Instant,
+        wall_clock: OffsetDateTime,
+        mut publish: impl FnMut(&PublishRequest) -> Result<(), String>,
+    ) {
+        for index in 0..self.publish_jobs.len() {
+            let state = &mut self.publish_jobs[index];
+            let Some(due) = state.next_due.filter(|due| *due <= now) else {
+                continue;
+            };
+
+            let request = state.job.request(state.sent + 1, wall_clock);
+            if let Err(error) = publish(&request) {
+                state.error = Some(error);
+                state.stop();
+                continue;
+            }
+
+            state.sent += 1;
+            state.next_due = Some((due + state.job.interval).max(now));
+            if state.job.count.is_some_and(|count| state.sent >= count) {
+                state.stop();
+            }
+
+            self.add_pending_publication(&request.topic, request.qos);
+        }
+    }
+
+    /// Opens the form defining a new job in the jobs overlay, publishing to the
+    /// selected topic.
+    pub fn open_job_form(&mut self) {
+        let form = JobForm::new(&self.publish_topic());
+        self.jobs_panel.get_or_insert_default().form = Some(form);
+    }
+
+    /// Number of running publish jobs.
+    pub fn running_jobs(&self) -> usize {
+        self.publish_jobs.iter().filter(|state| state.is_running()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_replaces_placeholders() {
+        let template: PayloadTemplate =
+            "{\"n\": {{counter}}, \"t\": {{ random:20:25 }}, \"h\": {{random:0.5:1.25}}}"
+                .parse()
+                .unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&template.render(7, OffsetDateTime::UNIX_EPOCH)).unwrap();
+
+        assert_eq!(payload["n"], 7);
+        assert!((20..=25).contains(&payload["t"].as_i64().unwrap()));
+        assert!((0.5..=1.25).contains(&payload["h"].as_f64().unwrap()));
+
+        let template: PayloadTemplate = "{{timestamp}} {{uuid}}".parse().unwrap();
+        let payload = template.render(1, OffsetDateTime::UNIX_EPOCH);
+        assert!(payload.starts_with("1970-01-01T00:00:00Z "));
+        assert_eq!(payload.len(), 21 + 36);
+
+        assert_eq!(
+            "{{count}}".parse::<PayloadTemplate>().unwrap_err(),
+            "Unknown placeholder '{{count}}'"
+        );
+        assert!("{{random:5:1}}".parse::<PayloadTemplate>().is_err());
+        assert!("{{counter".parse::<PayloadTemplate>().is_err());
+    }
+
+    #[test]
+    fn test_due_jobs_publish_until_their_count() {
+        let mut menu_state = TopicActivityMenuState::new();
+        let mut form = JobForm::new("sim/temp");
+        form.payload = "{{counter}}".into();
+        form.interval = "100".into();
+        form.count = "2".into();
+        menu_state.publish_jobs.push(JobState::new(form.job().unwrap()));
+
+        let start = Instant::now();
+        let mut published = Vec::new();
+        let mut publish = |request: &PublishRequest| {
+            published.push(request.payload.clone());
+            Ok(())
+        };
+
+        menu_state.publish_jobs[0].start(start);
+        menu_state.run_due_jobs(start, OffsetDateTime::UNIX_EPOCH, &mut publish);
+        menu_state.run_due_jobs(start, OffsetDateTime::UNIX_EPOCH, &mut publish);
+        assert_eq!(menu_state.running_jobs(), 1);
+
+        let later = start + Duration::from_millis(100);
+        menu_state.run_due_jobs(later, OffsetDateTime::UNIX_EPOCH, &mut publish);
+        let last = later + Duration::from_millis(100);
+        menu_state.run_due_jobs(last, OffsetDateTime::UNIX_EPOCH, &mut publish);
+
+        assert_eq!(published, vec!["1", "2"]);
+        assert_eq!(menu_state.running_jobs(), 0);
+        assert_eq!(menu_state.publications.len(), 2);
+    }
+
+    #[test]
+    fn test_failed_publish_stops_the_job() {
+        let mut menu_state = TopicActivityMenuState::new();
+        let mut form = JobForm::new("sim/temp");
+        form.interval = "5".into();
+        assert_eq!(form.job().unwrap_err(), "The interval must be at least 10ms");
+
+        form.interval = "10".into();
+        menu_state.publish_jobs.push(JobState::new(form.job().unwrap()));
+
+        let now = Instant::now();
+        menu_state.publish_jobs[0].toggle(now);
+        menu_state.run_due_jobs(now, OffsetDateTime::UNIX_EPOCH, |_| Err("queue full".into()));
+
+        assert!(!menu_state.publish_jobs[0].is_running());
+        assert_eq!(menu_state.publish_jobs[0].error.as_deref(), Some("queue full"));
+        assert!(menu_state.publications.is_empty());
+    }
+}
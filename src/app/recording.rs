@@ -0,0 +1,76 @@
+//! Recording of the received messages to a capture file, started from the command line
+//! or toggled from the topic activity screen.
+
+use std::path::PathBuf;
+
+use time::OffsetDateTime;
+use time::macros::format_description;
+
+use super::TopicActivityMenuState;
+use crate::capture::recorder::DEFAULT_MAX_FILE_BYTES;
+use crate::capture::{CaptureRecord, Recorder};
+use crate::mqtt::MQTTEvent;
+
+/// Where and how recordings are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordSettings {
+    /// Capture file. `None` writes every recording to a new file named after the time
+    /// it started.
+    pub path: Option<PathBuf>,
+    /// Size at which the capture file is rotated, `None` to never rotate.
+    pub max_file_bytes: Option<u64>,
+}
+
+impl Default for RecordSettings {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_file_bytes: Some(DEFAULT_MAX_FILE_BYTES),
+        }
+    }
+}
+
+/// Capture file named after `now`, in the working directory.
+fn default_capture_path(now: OffsetDateTime) -> PathBuf {
+    let format = format_description!("[year][month][day]-[hour][minute][second]");
+    let stamp = now.format(format).unwrap_or_default();
+
+    PathBuf::from(format!("mqtt-ranger-{}.jsonl", stamp))
+}
+
+impl TopicActivityMenuState {
+    /// Starts recording to the configured capture file.
+    pub fn start_recording(&mut self) -> Result<(), String> {
+        let path = match &self.record_settings.path {
+            Some(path) => path.clone(),
+            None => default_capture_path(
+                OffsetDateTime::now_local().unwrap_or(OffsetDateTime::now_utc()),
+            ),
+        };
+
+        self.recorder = Some(Recorder::open(&path, self.record_settings.max_file_bytes)?);
+        self.record_error = None;
+
+        Ok(())
+    }
+
+    /// Starts recording, or stops the running recording.
+    pub fn toggle_recording(&mut self) {
+        if self.recorder.take().is_none() {
+            self.record_error = self.start_recording().err();
+        }
+    }
+
+    /// Writes a received message to the capture file while recording. A failed write
+    /// stops the recording.
+    pub fn record(&mut self, event: &MQTTEvent) {
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+
+        if let Err(error) = recorder.write(&CaptureRecord::from(event)) {
+            self.recorder = None;
+            self.record_error = Some(error);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Retention policy bounding the message history kept in memory.
+//! Every topic keeps its messages in a ring buffer; the oldest messages are discarded
+//! once a topic or the whole session exceeds the configured limits.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+use super::{MessageActivity, TopicActivity, TopicActivityMenuState};
+
+/// Default number of messages kept per topic.
+pub const DEFAULT_MAX_MESSAGES_PER_TOPIC: usize = 10_000;
+
+/// Limits applied to the message history. `None` disables a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Messages kept per topic.
+    pub max_messages_per_topic: Option<usize>,
+    /// Payload bytes kept across all topics.
+    pub max_total_bytes: Option<usize>,
+    /// Messages older than this are discarded.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_messages_per_topic: Some(DEFAULT_MAX_MESSAGES_PER_TOPIC),
+            max_total_bytes: None,
+            max_age: None,
+        }
+    }
+}
+
+/// Messages kept in memory in order of arrival, to find the oldest one to discard under
+/// `max_total_bytes` without scanning every topic. Entries of messages discarded by the
+/// other limits are skipped when reached, and removed in bulk as the queue grows.
+#[derive(Debug, Default)]
+pub(super) struct ArrivalQueue {
+    /// Topic index and message number of every message, oldest first.
+    entries: VecDeque<(usize, u64)>,
+    /// Length at which the entries of discarded messages are removed.
+    compact_at: usize,
+}
+
+impl ArrivalQueue {
+    fn push(&mut self, topic_index: usize, seq: u64, topics: &[TopicActivity]) {
+        self.entries.push_back((topic_index, seq));
+
+        if self.entries.len() >= self.compact_at {
+            self.entries.retain(|(index, seq)| *seq >= topics[*index].dropped);
+            self.compact_at = (self.entries.len() * 2).max(1024);
+        }
+    }
+
+    /// The topic of the oldest message still in memory.
+    fn pop_oldest(&mut self, topics: &[TopicActivity]) -> Option<usize> {
+        while let Some((index, seq)) = self.entries.pop_front() {
+            if seq >= topics[index].dropped {
+                return Some(index);
+            }
+        }
+        None
+    }
+}
+
+impl TopicActivity {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            messages: VecDeque::new(),
+            dropped: 0,
+            retained: false,
+        }
+    }
+}
+
+impl TopicActivityMenuState {
+    /// Appends a message to its topic, creating the topic on its first message, and
+    /// discards older messages as required by the retention policy.
+    pub fn add_message(&mut self, topic_name: &str, message: MessageActivity) {
+        let index = match self.topics.iter().position(|t| t.name == topic_name) {
+            Some(index) => index,
+            None => {
+                self.topics.push(TopicActivity::new(topic_name));
+                self.topics.len() - 1
+            }
+        };
+
+        // Retained messages are only flagged when delivered on subscribe. A live
+        // message with an empty payload is what subscribers see when the retained
+        // message of the topic is cleared.
+        let topic = &mut self.topics[index];
+        if message.retain {
+            topic.retained = true;
+        } else if message.payload.is_empty() {
+            topic.retained = false;
+        }
+
+        let seq = topic.dropped + topic.messages.len() as u64;
+        self.total_bytes += message.payload.len();
+        self.store_in_history(topic_name, seq, &message);
+        self.topics[index].messages.push_back(message);
+        if self.retention.max_total_bytes.is_some() {
+            self.arrivals.push(index, seq, &self.topics);
+        }
+
+        if let Some(max_messages) = self.retention.max_messages_per_topic {
+            while self.topics[index].messages.len() > max_messages {
+                self.drop_oldest(index);
+            }
+        }
+
+        if let Some(max_bytes) = self.retention.max_total_bytes {
+            while self.total_bytes > max_bytes {
+                let Some(oldest) = self.arrivals.pop_oldest(&self.topics) else {
+                    break;
+                };
+                self.drop_oldest(oldest);
+            }
+        }
+    }
+
+    /// Discards every message received more than `max_age` before `now`.
+    pub fn prune_expired(&mut self, now: OffsetDateTime) {
+        let Some(max_age) = self.retention.max_age else {
+            return;
+        };
+        let cutoff = now - max_age;
+
+        for index in 0..self.topics.len() {
+            while self.topics[index]
+                .messages
+                .front()
+                .is_some_and(|message| message.received_at < cutoff)
+            {
+                self.drop_oldest(index);
+            }
+        }
+    }
+
+    /// Removes the oldest message of a topic and counts it as discarded.
+    fn drop_oldest(&mut self, topic_index: usize) {
+        let topic = &mut self.topics[topic_index];
+
+        if let Some(message) = topic.messages.pop_front() {
+            topic.dropped += 1;
+            self.total_bytes -= message.payload.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::tests::message;
+
+    fn payloads(topic: &TopicActivity) -> Vec<&str> {
+        topic.messages.iter().map(|m| std::str::from_utf8(&m.payload).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_max_messages_per_topic_discards_the_oldest() {
+        let mut menu_state = TopicActivityMenuState::new();
+        menu_state.retention.max_messages_per_topic = Some(2);
+        let now = OffsetDateTime::now_utc();
+
+        for payload in ["1", "2", "3", "4"] {
+            menu_state.add_message("a", message(payload, now));
+        }
+        menu_state.add_message("b", message("5", now));
+
+        assert_eq!(payloads(&menu_state.topics[0]), vec!["3", "4"]);
+        assert_eq!(menu_state.topics[0].dropped, 2);
+        assert_eq!(menu_state.topics[1].dropped, 0);
+        assert_eq!(menu_state.total_bytes, 3);
+    }
+
+    #[test]
+    fn test_max_total_bytes_discards_the_oldest_message_of_any_topic() {
+        let mut menu_state = TopicActivityMenuState::new();
+        menu_state.retention.max_total_bytes = Some(10);
+        let now = OffsetDateTime::now_utc();
+
+        menu_state.add_message("a", message("aaaa", now - Duration::from_secs(3)));
+        menu_state.add_message("b", message("bbbb", now - Duration::from_secs(2)));
+        menu_state.add_message("a", message("cccc", now - Duration::from_secs(1)));
+
+        assert_eq!(payloads(&menu_state.topics[0]), vec!["cccc"]);
+        assert_eq!(payloads(&menu_state.topics[1]), vec!["bbbb"]);
+        assert_eq!(menu_state.topics[0].dropped, 1);
+        assert_eq!(menu_state.total_bytes, 8);
+    }
+
+    #[test]
+    fn test_max_total_bytes_skips_messages_discarded_by_the_topic_limit() {
+        let mut menu_state = TopicActivityMenuState::new();
+        menu_state.retention.max_messages_per_topic = Some(1);
+        menu_state.retention.max_total_bytes = Some(8);
+        let now = OffsetDateTime::now_utc();
+
+        menu_state.add_message("a", message("aaaa", now));
+        menu_state.add_message("a", message("bbbb", now));
+        menu_state.add_message("b", message("cccc", now));
+        menu_state.add_message("b", message("dd", now));
+
+        assert_eq!(payloads(&menu_state.topics[0]), vec!["bbbb"]);
+        assert_eq!(payloads(&menu_state.topics[1]), vec!["dd"]);
+
+        menu_state.add_message("c", message("eeee", now));
+        assert!(menu_state.topics[0].messages.is_empty());
+        assert_eq!(menu_state.total_bytes, 6);
+    }
+
+    #[test]
+    fn test_prune_expired_discards_old_messages() {
+        let mut menu_state = TopicActivityMenuState::new();
+        menu_state.retention.max_age = Some(Duration::from_secs(60));
+        let now = OffsetDateTime::now_utc();
+
+        menu_state.add_message("a", message("old", now - Duration::from_secs(120)));
+        menu_state.add_message("a", message("new", now - Duration::from_secs(30)));
+        menu_state.prune_expired(now);
+
+        assert_eq!(payloads(&menu_state.topics[0]), vec!["new"]);
+        assert_eq!(menu_state.topics[0].dropped, 1);
+    }
+
+    #[test]
+    fn test_message_cursor_stays_on_its_message_when_older_ones_are_dropped() {
+        let mut menu_state = TopicActivityMenuState::new();
+        menu_state.retention.max_messages_per_topic = Some(3);
+        let now = OffsetDateTime::now_utc();
+
+        for payload in ["1", "2", "3"] {
+            menu_state.add_message("a", message(payload, now));
+        }
+        menu_state.message_up(1);
+        assert_eq!(menu_state.selected_message().unwrap().payload, "2");
+
+        menu_state.add_message("a", message("4", now));
+        assert_eq!(menu_state.selected_message().unwrap().payload, "2");
+    }
+}
@@ -0,0 +1,381 @@
+//! Sparkplug B mode.
+//! Tracks the edge nodes and devices publishing under `spBv1.0`: their online state
+//! from the BIRTH and DEATH messages, the metric definitions that name the aliased
+//! metrics of later messages, the last value of every metric and sequence number gaps.
+
+use std::collections::BTreeMap;
+
+use crate::decoder::sparkplug::{self, Aliases, MetricDefinition, MetricValue, Payload};
+use crate::decoder::{DecodedPayload, Value};
+
+/// Parts of a Sparkplug B topic: `spBv1.0/<group>/<type>/<node>[/<device>]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SparkplugTopic<'a> {
+    pub group: &'a str,
+    pub message_type: &'a str,
+    pub node: &'a str,
+    pub device: Option<&'a str>,
+}
+
+impl<'a> SparkplugTopic<'a> {
+    /// Parses the topic of an edge node or device message. Host application `STATE`
+    /// topics and topics outside the namespace give `None`.
+    pub fn parse(topic: &'a str) -> Option<Self> {
+        let mut levels = topic.split('/');
+
+        if levels.next()? != sparkplug::NAMESPACE {
+            return None;
+        }
+
+        let group = levels.next()?;
+        let message_type = levels.next()?;
+        let node = levels.next()?;
+        let device = levels.next();
+
+        let valid = levels.next().is_none()
+            && sparkplug::MESSAGE_TYPES.contains(&message_type)
+            && message_type.starts_with('D') == device.is_some();
+
+        valid.then_some(Self {
+            group,
+            message_type,
+            node,
+            device,
+        })
+    }
+}
+
+/// Last value of every metric, by name.
+pub type Metrics = BTreeMap<String, Value>;
+
+/// A device attached to an edge node.
+#[derive(Debug, Default)]
+pub struct Device {
+    pub online: bool,
+    pub metrics: Metrics,
+}
+
+/// An edge node and its devices.
+#[derive(Debug, Default)]
+pub struct EdgeNode {
+    pub online: bool,
+    /// Birth/death sequence number of the current session, from the NBIRTH.
+    pub bd_seq: Option<u64>,
+    /// Sequence number of the last message published by the node or its devices.
+    pub last_seq: Option<u64>,
+    /// Messages that arrived with another sequence number than expected.
+    pub seq_gaps: u64,
+    /// Metric definitions of the node and its devices from the BIRTH messages.
+    pub aliases: Aliases,
+    pub metrics: Metrics,
+    pub devices: BTreeMap<String, Device>,
+}
+
+/// Row of the Sparkplug view: an edge node, or one of its devices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparkplugRow {
+    pub group: String,
+    pub node: String,
+    pub device: Option<String>,
+}
+
+/// State of the Sparkplug view opened from the topic activity screen.
+#[derive(Debug, Default)]
+pub struct SparkplugView {
+    /// Index of the highlighted row.
+    pub selected: usize,
+}
+
+/// Edge nodes seen since the Sparkplug mode was enabled.
+#[derive(Debug, Default)]
+pub struct SparkplugState {
+    /// Edge nodes by group and node id.
+    pub nodes: BTreeMap<(String, String), EdgeNode>,
+}
+
+impl SparkplugState {
+    /// Updates the edge node with a message received on `topic` and decodes its payload,
+    /// naming the metrics from the BIRTH messages seen so far. A sequence number gap is
+    /// reported as the error of the decoded payload. `None` when `topic` is not a
+    /// Sparkplug B topic or the payload cannot be decoded.
+    pub fn process(&mut self, topic: &str, payload: &[u8]) -> Option<DecodedPayload> {
+        let topic = SparkplugTopic::parse(topic)?;
+        let payload = sparkplug::decode_payload(payload).ok()?;
+
+        let node = self
+            .nodes
+            .entry((topic.group.to_string(), topic.node.to_string()))
+            .or_default();
+        let error = node.check_seq(topic.message_type, &payload);
+
+        match topic.message_type {
+            "NBIRTH" => {
+                node.online = true;
+                node.bd_seq = bd_seq(&payload);
+                node.aliases.clear();
+                node.metrics.clear();
+                // Devices announce themselves again after their node.
+                for device in node.devices.values_mut() {
+                    device.online = false;
+                }
+            }
+            "NDEATH" => {
+                // A DEATH left behind by an older session must not mark the node offline.
+                let current = node.bd_seq.is_none()
+                    || bd_seq(&payload).is_none()
+                    || node.bd_seq == bd_seq(&payload);
+
+                if current {
+                    node.online = false;
+                    for device in node.devices.values_mut() {
+                        device.online = false;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if topic.message_type.ends_with("BIRTH") {
+            for metric in &payload.metrics {
+                if let (Some(alias), Some(name)) = (metric.alias, &metric.name) {
+                    let definition = MetricDefinition {
+                        name: name.clone(),
+                        datatype: metric.datatype,
+                    };
+                    node.aliases.insert(alias, definition);
+                }
+            }
+        }
+
+        let metrics = match (topic.message_type, topic.device) {
+            ("NBIRTH" | "NDATA", _) => Some(&mut node.metrics),
+            (message_type @ ("DBIRTH" | "DDATA" | "DDEATH"), Some(device)) => {
+                let device = node.devices.entry(device.to_string()).or_default();
+
+                match message_type {
+                    "DBIRTH" => {
+                        device.online = true;
+                        device.metrics.clear();
+                    }
+                    "DDEATH" => device.online = false,
+                    _ => {}
+                }
+                Some(&mut device.metrics)
+            }
+            // Commands are requests to write, not values.
+            _ => None,
+        };
+
+        if let Some(metrics) = metrics {
+            for metric in &payload.metrics {
+                let definition = metric.definition(&node.aliases);
+                metrics.insert(definition.name, metric.value(definition.datatype));
+            }
+        }
+
+        let value = sparkplug::payload_value(&payload, &node.aliases);
+        Some(DecodedPayload::new(sparkplug::SPARKPLUG.to_string(), value, error))
+    }
+
+    /// Edge nodes, each followed by its devices, in the order of the Sparkplug view.
+    pub fn rows(&self) -> Vec<SparkplugRow> {
+        let mut rows = Vec::new();
+
+        for ((group, node_id), node) in &self.nodes {
+            rows.push(SparkplugRow {
+                group: group.clone(),
+                node: node_id.clone(),
+                device: None,
+            });
+            rows.extend(node.devices.keys().map(|device| SparkplugRow {
+                group: group.clone(),
+                node: node_id.clone(),
+                device: Some(device.clone()),
+            }));
+        }
+
+        rows
+    }
+
+    /// The edge node of `row`.
+    pub fn node(&self, row: &SparkplugRow) -> Option<&EdgeNode> {
+        self.nodes.get(&(row.group.clone(), row.node.clone()))
+    }
+
+    /// Metrics of the node or device of `row`.
+    pub fn metrics(&self, row: &SparkplugRow) -> Option<&Metrics> {
+        let node = self.node(row)?;
+
+        match &row.device {
+            Some(device) => node.devices.get(device).map(|device| &device.metrics),
+            None => Some(&node.metrics),
+        }
+    }
+}
+
+impl EdgeNode {
+    /// Checks the sequence number of a message published by the node or one of its
+    /// devices against the previous one, modulo 256. The NBIRTH starts over, and the
+    /// NDEATH, sent by the broker, and commands, sent by host applications, have none.
+    fn check_seq(&mut self, message_type: &str, payload: &Payload) -> Option<String> {
+        if matches!(message_type, "NDEATH" | "NCMD" | "DCMD") {
+            return None;
+        }
+
+        let seq = payload.seq?;
+        let expected = self.last_seq.map(|last| (last + 1) % 256);
+        self.last_seq = Some(seq);
+
+        match expected {
+            Some(expected) if message_type != "NBIRTH" && seq != expected => {
+                self.seq_gaps += 1;
+                Some(format!("seq gap: expected {}, got {}", expected, seq))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The `bdSeq` metric of a NBIRTH or NDEATH.
+fn bd_seq(payload: &Payload) -> Option<u64> {
+    let metric = payload
+        .metrics
+        .iter()
+        .find(|metric| metric.name.as_deref() == Some("bdSeq"))?;
+
+    match metric.value {
+        Some(MetricValue::LongValue(value)) => Some(value),
+        Some(MetricValue::IntValue(value)) => Some(value.into()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use super::*;
+    use crate::decoder::sparkplug::Metric;
+
+    fn metric(name: Option<&str>, alias: u64, value: MetricValue) -> Metric {
+        Metric {
+            name: name.map(str::to_string),
+            alias: Some(alias),
+            datatype: Some(4),
+            value: Some(value),
+            ..Default::default()
+        }
+    }
+
+    fn payload(seq: Option<u64>, metrics: Vec<Metric>) -> Vec<u8> {
+        Payload {
+            seq,
+            metrics,
+            ..Default::default()
+        }
+        .encode_to_vec()
+    }
+
+    fn birth(bd_seq: u64) -> Vec<u8> {
+        payload(
+            Some(0),
+            vec![metric(Some("bdSeq"), 0, MetricValue::LongValue(bd_seq))],
+        )
+    }
+
+    #[test]
+    fn test_parse_topic() {
+        assert_eq!(
+            SparkplugTopic::parse("spBv1.0/plant/DDATA/edge1/press"),
+            Some(SparkplugTopic {
+                group: "plant",
+                message_type: "DDATA",
+                node: "edge1",
+                device: Some("press"),
+            })
+        );
+        assert!(SparkplugTopic::parse("spBv1.0/plant/NDATA/edge1").is_some());
+        assert!(SparkplugTopic::parse("spBv1.0/plant/DDATA/edge1").is_none());
+        assert!(SparkplugTopic::parse("spBv1.0/plant/NDATA/edge1/press").is_none());
+        assert!(SparkplugTopic::parse("spBv1.0/STATE/host").is_none());
+        assert!(SparkplugTopic::parse("plant/DDATA/edge1/press").is_none());
+    }
+
+    #[test]
+    fn test_data_aliases_resolve_to_birth_names() {
+        let mut state = SparkplugState::default();
+        state.process("spBv1.0/plant/NBIRTH/edge1", &birth(1));
+
+        let dbirth = payload(
+            Some(1),
+            vec![metric(Some("Pressure"), 7, MetricValue::LongValue(10))],
+        );
+        state.process("spBv1.0/plant/DBIRTH/edge1/press", &dbirth);
+
+        let ddata = payload(Some(2), vec![metric(None, 7, MetricValue::LongValue(12))]);
+        let decoded = state.process("spBv1.0/plant/DDATA/edge1/press", &ddata).unwrap();
+
+        assert_eq!(decoded.decoder, "sparkplug");
+        assert_eq!(decoded.summary, "{seq: 2, metrics: {Pressure: 12}}");
+        assert_eq!(decoded.error, None);
+
+        let rows = state.rows();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            state.metrics(&rows[1]).unwrap().get("Pressure"),
+            Some(&Value::Integer(12))
+        );
+    }
+
+    #[test]
+    fn test_birth_and_death_set_online_state() {
+        let mut state = SparkplugState::default();
+        state.process("spBv1.0/plant/NBIRTH/edge1", &birth(1));
+        state.process("spBv1.0/plant/DBIRTH/edge1/press", &payload(Some(1), vec![]));
+
+        let key = ("plant".to_string(), "edge1".to_string());
+        assert!(state.nodes[&key].online);
+        assert!(state.nodes[&key].devices["press"].online);
+
+        // A DEATH from an older session is ignored.
+        let stale_death = payload(None, vec![metric(Some("bdSeq"), 0, MetricValue::LongValue(0))]);
+        state.process("spBv1.0/plant/NDEATH/edge1", &stale_death);
+        assert!(state.nodes[&key].online);
+
+        let death = payload(None, vec![metric(Some("bdSeq"), 0, MetricValue::LongValue(1))]);
+        state.process("spBv1.0/plant/NDEATH/edge1", &death);
+        assert!(!state.nodes[&key].online);
+        assert!(!state.nodes[&key].devices["press"].online);
+    }
+
+    #[test]
+    fn test_seq_gaps_are_flagged() {
+        let mut state = SparkplugState::default();
+        state.process("spBv1.0/plant/NBIRTH/edge1", &birth(1));
+        state.process("spBv1.0/plant/NDATA/edge1", &payload(Some(1), vec![]));
+
+        let decoded = state
+            .process("spBv1.0/plant/NDATA/edge1", &payload(Some(3), vec![]))
+            .unwrap();
+        assert_eq!(decoded.error.as_deref(), Some("seq gap: expected 2, got 3"));
+
+        // The sequence wraps around after 255 and starts over with a NBIRTH.
+        let node = state.nodes.values_mut().next().unwrap();
+        node.last_seq = Some(255);
+        assert!(
+            state
+                .process("spBv1.0/plant/NDATA/edge1", &payload(Some(0), vec![]))
+                .unwrap()
+                .error
+                .is_none()
+        );
+        assert!(
+            state
+                .process("spBv1.0/plant/NBIRTH/edge1", &birth(2))
+                .unwrap()
+                .error
+                .is_none()
+        );
+        assert_eq!(state.nodes.values().next().unwrap().seq_gaps, 1);
+    }
+}
@@ -0,0 +1,311 @@
+//! Hierarchical view of the topic list.
+//! Topic names are split on `/` into a tree whose nodes aggregate the topic count,
+//! message count, retained topics and last activity of everything below them.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::TopicActivity;
+
+/// Layout of the topic list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TopicView {
+    /// Every topic on its own line, in arrival order.
+    #[default]
+    Flat,
+    /// Topics grouped by their `/` separated levels.
+    Tree,
+}
+
+/// A visible line of the tree view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRow {
+    /// Full path of the node, e.g. `site/line`.
+    pub path: String,
+    /// Last level of the path, shown in the list.
+    pub label: String,
+    pub depth: usize,
+    /// Index into `TopicActivityMenuState::topics` when a topic has exactly this name.
+    pub topic_index: Option<usize>,
+    pub has_children: bool,
+    pub expanded: bool,
+    /// Number of topics at or below this node.
+    pub topic_count: usize,
+    /// Number of messages received at or below this node.
+    pub message_count: usize,
+    /// Number of topics at or below this node with a retained message.
+    pub retained_count: usize,
+    /// Timestamp of the newest message at or below this node.
+    pub last_activity: Option<String>,
+}
+
+/// Expand/collapse state and selection of the tree view.
+/// The tree itself is rebuilt from the topic list, so it never goes stale.
+#[derive(Debug, Default)]
+pub struct TopicTree {
+    /// Paths of the expanded nodes.
+    expanded: BTreeSet<String>,
+    /// Path of the selected node.
+    pub selected: Option<String>,
+}
+
+/// Node of the tree built from the topic list.
+#[derive(Default)]
+struct Node<'a> {
+    children: BTreeMap<&'a str, Node<'a>>,
+    topic_index: Option<usize>,
+    topic_count: usize,
+    message_count: usize,
+    retained_count: usize,
+    last_activity: Option<&'a str>,
+}
+
+impl<'a> Node<'a> {
+    fn build(topics: &'a [TopicActivity]) -> Self {
+        let mut root = Node::default();
+
+        for (index, topic) in topics.iter().enumerate() {
+            let last_activity = topic.messages.back().map(|m| m.timestamp.as_str());
+            let mut node = &mut root;
+
+            for level in topic.name.split('/') {
+                node = node.children.entry(level).or_default();
+                node.topic_count += 1;
+                node.message_count += topic.messages.len();
+                node.retained_count += usize::from(topic.retained);
+                node.last_activity = node.last_activity.max(last_activity);
+            }
+
+            node.topic_index = Some(index);
+        }
+
+        root
+    }
+}
+
+/// Joins a parent path and a level. Top-level nodes have no parent.
+fn child_path(parent: Option<&str>, level: &str) -> String {
+    match parent {
+        Some(parent) => format!("{}/{}", parent, level),
+        None => level.to_string(),
+    }
+}
+
+/// Path of the parent node, `None` for top-level nodes.
+fn parent_path(path: &str) -> Option<&str> {
+    path.rsplit_once('/').map(|(parent, _)| parent)
+}
+
+impl TopicTree {
+    /// The visible rows: top-level nodes plus the children of every expanded node.
+    pub fn rows(&self, topics: &[TopicActivity]) -> Vec<TreeRow> {
+        let root = Node::build(topics);
+        let mut rows = Vec::new();
+
+        self.push_rows(&root, None, 0, &mut rows);
+
+        rows
+    }
+
+    fn push_rows(&self, node: &Node, parent: Option<&str>, depth: usize, rows: &mut Vec<TreeRow>) {
+        for (level, child) in &node.children {
+            let path = child_path(parent, level);
+            let expanded = self.expanded.contains(&path);
+
+            rows.push(TreeRow {
+                path: path.clone(),
+                label: level.to_string(),
+                depth,
+                topic_index: child.topic_index,
+                has_children: !child.children.is_empty(),
+                expanded,
+                topic_count: child.topic_count,
+                message_count: child.message_count,
+                retained_count: child.retained_count,
+                last_activity: child.last_activity.map(str::to_string),
+            });
+
+            if expanded {
+                self.push_rows(child, Some(&path), depth + 1, rows);
+            }
+        }
+    }
+
+    /// Position of the selected node in `rows`, the first row when it is not visible.
+    pub fn selected_position(&self, rows: &[TreeRow]) -> usize {
+        self.selected
+            .as_ref()
+            .and_then(|selected| rows.iter().position(|row| &row.path == selected))
+            .unwrap_or(0)
+    }
+
+    /// Selects the node at `path` and expands its ancestors so it is visible.
+    pub fn reveal(&mut self, path: &str) {
+        let mut parent = parent_path(path);
+
+        while let Some(path) = parent {
+            self.expanded.insert(path.to_string());
+            parent = parent_path(path);
+        }
+
+        self.selected = Some(path.to_string());
+    }
+
+    /// Moves the selection to the next visible row, wrapping around.
+    pub fn next(&mut self, rows: &[TreeRow]) {
+        if !rows.is_empty() {
+            let position = (self.selected_position(rows) + 1) % rows.len();
+            self.selected = Some(rows[position].path.clone());
+        }
+    }
+
+    /// Moves the selection to the previous visible row, wrapping around.
+    pub fn previous(&mut self, rows: &[TreeRow]) {
+        if !rows.is_empty() {
+            let position = (self.selected_position(rows) + rows.len() - 1) % rows.len();
+            self.selected = Some(rows[position].path.clone());
+        }
+    }
+
+    /// Expands the selected node.
+    pub fn expand(&mut self, rows: &[TreeRow]) {
+        if let Some(row) = rows.get(self.selected_position(rows))
+            && row.has_children
+        {
+            self.expanded.insert(row.path.clone());
+        }
+    }
+
+    /// Collapses the selected node, or selects its parent when it is already collapsed.
+    pub fn collapse(&mut self, rows: &[TreeRow]) {
+        let Some(row) = rows.get(self.selected_position(rows)) else {
+            return;
+        };
+
+        if row.expanded {
+            self.expanded.remove(&row.path);
+        } else if let Some(parent) = parent_path(&row.path) {
+            self.selected = Some(parent.to_string());
+        }
+    }
+
+    /// Expands or collapses the selected node.
+    pub fn toggle(&mut self, rows: &[TreeRow]) {
+        match rows.get(self.selected_position(rows)) {
+            Some(row) if row.expanded => {
+                self.expanded.remove(&row.path);
+            }
+            Some(row) if row.has_children => {
+                self.expanded.insert(row.path.clone());
+            }
+            _ => {}
+        }
+    }
+
+    /// Expands every node that has children.
+    pub fn expand_all(&mut self, topics: &[TopicActivity]) {
+        for topic in topics {
+            let mut parent = parent_path(&topic.name);
+
+            while let Some(path) = parent {
+                self.expanded.insert(path.to_string());
+                parent = parent_path(path);
+            }
+        }
+    }
+
+    /// Collapses every node, moving the selection to its top-level ancestor.
+    pub fn collapse_all(&mut self) {
+        self.expanded.clear();
+
+        if let Some(selected) = &self.selected {
+            let top_level = selected.split('/').next().unwrap_or_default().to_string();
+            self.selected = Some(top_level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::MessageActivity;
+    use crate::app::tests::message;
+    use time::OffsetDateTime;
+
+    fn topic(name: &str, timestamps: &[&str]) -> TopicActivity {
+        let mut topic = TopicActivity::new(name);
+
+        topic.messages = timestamps
+            .iter()
+            .map(|timestamp| MessageActivity {
+                timestamp: timestamp.to_string(),
+                ..message("", OffsetDateTime::now_utc())
+            })
+            .collect();
+
+        topic
+    }
+
+    fn paths(rows: &[TreeRow]) -> Vec<&str> {
+        rows.iter().map(|row| row.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tree_aggregates_counts_per_subtree() {
+        let topics = vec![
+            topic("site/line1/temp", &["2024-01-01 10:00:00"]),
+            topic("site/line1/rpm", &["2024-01-01 10:00:05", "2024-01-01 10:00:09"]),
+            topic("site/line2/temp", &["2024-01-01 09:00:00"]),
+            topic("status", &[]),
+        ];
+        let mut tree = TopicTree::default();
+
+        let rows = tree.rows(&topics);
+        assert_eq!(paths(&rows), vec!["site", "status"]);
+        assert_eq!(rows[0].topic_count, 3);
+        assert_eq!(rows[0].message_count, 4);
+        assert_eq!(rows[0].last_activity.as_deref(), Some("2024-01-01 10:00:09"));
+        assert_eq!(rows[1].topic_index, Some(3));
+        assert!(!rows[1].has_children);
+
+        tree.expand(&rows);
+        let rows = tree.rows(&topics);
+        assert_eq!(paths(&rows), vec!["site", "site/line1", "site/line2", "status"]);
+        assert_eq!(rows[2].message_count, 1);
+        assert_eq!(rows[2].depth, 1);
+    }
+
+    #[test]
+    fn test_tree_expand_all_and_collapse_all() {
+        let topics = vec![topic("a/b/c", &[]), topic("a/d", &[]), topic("/leading", &[])];
+        let mut tree = TopicTree::default();
+
+        tree.expand_all(&topics);
+        let rows = tree.rows(&topics);
+        assert_eq!(paths(&rows), vec!["", "/leading", "a", "a/b", "a/b/c", "a/d"]);
+
+        tree.selected = Some("a/b/c".into());
+        tree.collapse(&rows);
+        assert_eq!(tree.selected.as_deref(), Some("a/b"));
+
+        tree.collapse_all();
+        assert_eq!(tree.selected.as_deref(), Some("a"));
+        assert_eq!(paths(&tree.rows(&topics)), vec!["", "a"]);
+    }
+
+    #[test]
+    fn test_tree_reveal_expands_ancestors() {
+        let topics = vec![topic("a/b/c", &[]), topic("z", &[])];
+        let mut tree = TopicTree::default();
+
+        tree.reveal("a/b/c");
+        let rows = tree.rows(&topics);
+
+        assert_eq!(paths(&rows), vec!["a", "a/b", "a/b/c", "z"]);
+        assert_eq!(tree.selected_position(&rows), 2);
+
+        tree.next(&rows);
+        assert_eq!(tree.selected.as_deref(), Some("z"));
+        tree.next(&rows);
+        assert_eq!(tree.selected.as_deref(), Some("a"));
+    }
+}
@@ -0,0 +1,122 @@
+//! Capture files: received messages saved as JSON Lines, one message per line, to be
+//! attached to bug reports and analysed later.
+//! Payloads are stored in base64 so binary payloads survive the round trip, and the
+//! receive time keeps its full precision and UTC offset. Capture files can be replayed
+//! into the topic activity screen, or published to a broker.
+
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use time::OffsetDateTime;
+
+use crate::app::MessageProperties;
+use crate::mqtt::MQTTEvent;
+
+pub mod publisher;
+pub mod recorder;
+pub mod replay;
+
+pub use publisher::{PrefixRewrite, PublishProgress};
+pub use recorder::Recorder;
+pub use replay::{Replay, ReplaySpeed};
+
+/// A received message, as written to a line of a capture file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// When the message was received.
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub topic: String,
+    #[serde(serialize_with = "serialize_base64", deserialize_with = "deserialize_base64")]
+    pub payload: Bytes,
+    pub qos: u8,
+    pub retain: bool,
+    /// MQTT v5 publish properties, missing on v3.1.1 connections.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<MessageProperties>,
+}
+
+impl From<&MQTTEvent> for CaptureRecord {
+    fn from(event: &MQTTEvent) -> Self {
+        Self {
+            timestamp: event.timestamp,
+            topic: event.topic.clone(),
+            payload: event.payload.clone(),
+            qos: event.qos,
+            retain: event.retain,
+            properties: event.properties.clone(),
+        }
+    }
+}
+
+impl From<CaptureRecord> for MQTTEvent {
+    fn from(record: CaptureRecord) -> Self {
+        Self {
+            topic: record.topic,
+            payload: record.payload,
+            timestamp: record.timestamp,
+            properties: record.properties,
+            qos: record.qos,
+            retain: record.retain,
+        }
+    }
+}
+
+impl CaptureRecord {
+    /// The record as a line of a capture file, without the line break.
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("capture records always serialize")
+    }
+
+    /// Time between `first` and this record. Records out of order count as simultaneous.
+    pub fn offset_from(&self, first: &CaptureRecord) -> Duration {
+        (self.timestamp - first.timestamp).try_into().unwrap_or_default()
+    }
+
+    /// Parses a line of a capture file.
+    pub fn from_line(line: &str) -> Result<Self, String> {
+        serde_json::from_str(line).map_err(|e| e.to_string())
+    }
+}
+
+fn serialize_base64<S: Serializer>(payload: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(payload))
+}
+
+fn deserialize_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    STANDARD.decode(text).map(Bytes::from).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_record_round_trips_through_a_line() {
+        let record = CaptureRecord {
+            timestamp: datetime!(2025-03-01 12:30:05.123456789 +01:00),
+            topic: "plant/raw".into(),
+            payload: Bytes::from_static(&[0x00, 0xff, b'o', b'k']),
+            qos: 1,
+            retain: true,
+            properties: Some(MessageProperties {
+                content_type: Some("application/octet-stream".into()),
+                user_properties: vec![("line".into(), "3".into())],
+                ..Default::default()
+            }),
+        };
+
+        let line = record.to_line();
+
+        assert!(line.starts_with(concat!(
+            r#"{"timestamp":"2025-03-01T12:30:05.123456789+01:00","#,
+            r#""topic":"plant/raw","payload":"AP9vaw==","qos":1,"retain":true"#
+        )));
+        assert_eq!(CaptureRecord::from_line(&line).unwrap(), record);
+    }
+}
@@ -0,0 +1,236 @@
+//! Publishing of a capture file to a broker, to push a recorded session into a staging
+//! broker for regression tests. Every message keeps its topic, QoS, retain flag and v5
+//! properties, and the gaps between messages are kept, scaled by a speed factor.
+
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use tokio::time::Instant;
+
+use super::CaptureRecord;
+use crate::mqtt::{MQTTConfig, Notification, PublishRequest, client};
+
+/// Replaces a topic prefix, e.g. `plant1/=staging/plant1/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixRewrite {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for PrefixRewrite {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (from, to) = text
+            .split_once('=')
+            .ok_or_else(|| format!("Expected OLD=NEW, got '{}'", text))?;
+
+        Ok(Self {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+}
+
+impl PrefixRewrite {
+    /// `topic` with the prefix replaced. Topics without the prefix are unchanged, and
+    /// an empty prefix prepends the new one to every topic.
+    pub fn apply(&self, topic: &str) -> String {
+        match topic.strip_prefix(&self.from) {
+            Some(rest) => format!("{}{}", self.to, rest),
+            None => topic.to_string(),
+        }
+    }
+}
+
+/// Progress of the publication of a capture file.
+#[derive(Debug, Default)]
+pub struct PublishProgress {
+    /// Messages in the capture file.
+    pub total: usize,
+    /// Messages handed to the client so far.
+    pub queued: usize,
+    /// Messages sent at QoS 0, or acknowledged by the broker.
+    pub delivered: usize,
+    pub failed: usize,
+    /// Why the last message failed.
+    pub last_error: Option<String>,
+    /// The connection error that stopped the publication.
+    pub aborted: Option<String>,
+    /// QoS of the queued messages whose PUBLISH has not been sent yet, oldest first.
+    unsent: VecDeque<u8>,
+    /// QoS of the sent messages waiting for an acknowledgement, by packet id.
+    in_flight: HashMap<u16, u8>,
+}
+
+impl PublishProgress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            ..Default::default()
+        }
+    }
+
+    /// Whether every message has been delivered or has failed, or the connection failed.
+    pub fn is_finished(&self) -> bool {
+        self.aborted.is_some() || self.delivered + self.failed == self.total
+    }
+
+    /// Registers a message that is about to be queued at `qos`.
+    fn queue(&mut self, qos: u8) {
+        self.queued += 1;
+        self.unsent.push_back(qos);
+    }
+
+    /// Counts the message queued last as failed, when the client refused it.
+    fn refuse_last(&mut self, error: String) {
+        self.unsent.pop_back();
+        self.fail(error);
+    }
+
+    fn fail(&mut self, error: String) {
+        self.failed += 1;
+        self.last_error = Some(error);
+    }
+
+    /// Ends the exchange of packet `pkid` at `qos`, if it is in flight.
+    fn acknowledge(&mut self, pkid: u16, qos: u8, error: Option<String>) {
+        if self.in_flight.get(&pkid) != Some(&qos) {
+            return;
+        }
+
+        self.in_flight.remove(&pkid);
+        match error {
+            Some(error) => self.fail(error),
+            None => self.delivered += 1,
+        }
+    }
+
+    /// Applies a notification of the event loop. PUBLISH packets leave the client in
+    /// the order they were queued.
+    fn apply(&mut self, notification: Notification) {
+        match notification {
+            Notification::PublishSent(pkid) => match self.unsent.pop_front() {
+                Some(0) => self.delivered += 1,
+                Some(qos) => {
+                    self.in_flight.insert(pkid, qos);
+                }
+                None => {}
+            },
+            Notification::PubAck { pkid, error } => self.acknowledge(pkid, 1, error),
+            Notification::PubRec {
+                pkid,
+                error: Some(error),
+            } => self.acknowledge(pkid, 2, Some(error)),
+            Notification::PubComp { pkid, error } => self.acknowledge(pkid, 2, error),
+            _ => {}
+        }
+    }
+}
+
+/// Publishes `records` to the broker of `config` and reports to `progress` until every
+/// message is delivered or has failed, or the connection fails. A `speed` of 2 plays the
+/// capture twice as fast; 0 publishes the messages as fast as the broker accepts them.
+pub async fn publish_capture(
+    config: MQTTConfig,
+    records: Vec<CaptureRecord>,
+    rewrite: Option<PrefixRewrite>,
+    speed: f64,
+    progress: Arc<Mutex<PublishProgress>>,
+) {
+    let (client, mut event_loop) = match client::connect(&config) {
+        Ok(connection) => connection,
+        Err(e) => {
+            progress.lock().unwrap().aborted = Some(e.to_string());
+            return;
+        }
+    };
+
+    let publisher_progress = Arc::clone(&progress);
+    let publisher = tokio::spawn(async move {
+        let start = Instant::now();
+        let Some(first) = records.first().cloned() else {
+            return;
+        };
+
+        for record in records {
+            if speed > 0.0 {
+                let offset = record.offset_from(&first).div_f64(speed);
+                tokio::time::sleep_until(start + offset).await;
+            }
+
+            let request = PublishRequest {
+                topic: match &rewrite {
+                    Some(rewrite) => rewrite.apply(&record.topic),
+                    None => record.topic,
+                },
+                payload: record.payload,
+                qos: record.qos,
+                retain: record.retain,
+                properties: record.properties,
+            };
+
+            publisher_progress.lock().unwrap().queue(request.qos);
+            if let Err(error) = client.publish(&request).await {
+                publisher_progress.lock().unwrap().refuse_last(error);
+            }
+        }
+    });
+
+    while !progress.lock().unwrap().is_finished() {
+        match event_loop.poll().await {
+            Ok(notification) => progress.lock().unwrap().apply(notification),
+            Err(error) => progress.lock().unwrap().aborted = Some(error),
+        }
+    }
+
+    publisher.abort();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_rewrite() {
+        let rewrite: PrefixRewrite = "plant1/=staging/plant1/".parse().unwrap();
+        assert_eq!(rewrite.apply("plant1/line/temp"), "staging/plant1/line/temp");
+        assert_eq!(rewrite.apply("plant2/line/temp"), "plant2/line/temp");
+
+        let prepend: PrefixRewrite = "=replay/".parse().unwrap();
+        assert_eq!(prepend.apply("plant2/line/temp"), "replay/plant2/line/temp");
+        assert!("plant1".parse::<PrefixRewrite>().is_err());
+    }
+
+    #[test]
+    fn test_progress_counts_acknowledged_messages() {
+        let mut progress = PublishProgress::new(4);
+        for qos in [0, 1, 2, 1] {
+            progress.queue(qos);
+        }
+        progress.refuse_last("queue closed".into());
+
+        progress.apply(Notification::PublishSent(0));
+        progress.apply(Notification::PublishSent(1));
+        progress.apply(Notification::PublishSent(2));
+        progress.apply(Notification::PubAck {
+            pkid: 1,
+            error: None,
+        });
+        progress.apply(Notification::PubRec {
+            pkid: 2,
+            error: None,
+        });
+        assert!(!progress.is_finished());
+
+        progress.apply(Notification::PubComp {
+            pkid: 2,
+            error: Some("NotAuthorized".into()),
+        });
+
+        assert!(progress.is_finished());
+        assert_eq!((progress.delivered, progress.failed), (2, 2));
+        assert_eq!(progress.last_error.as_deref(), Some("NotAuthorized"));
+    }
+}
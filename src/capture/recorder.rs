@@ -0,0 +1,138 @@
+//! Writes received messages to a capture file. Once the file reaches its size limit it
+//! is renamed to `<path>.1`, `<path>.2`, ... and a new file is started, so older parts
+//! are never overwritten.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::CaptureRecord;
+
+/// Default size of a capture file before it is rotated.
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// An open capture file.
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    /// Size at which the file is rotated, `None` to never rotate.
+    max_file_bytes: Option<u64>,
+    file: File,
+    /// Bytes in the current file.
+    written: u64,
+    /// Messages recorded since the recorder was opened, across rotations.
+    records: u64,
+}
+
+impl Recorder {
+    /// Opens `path` for recording, appending to it when it already exists.
+    pub fn open(path: &Path, max_file_bytes: Option<u64>) -> Result<Self, String> {
+        let file = open_append(path)?;
+        let written = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_file_bytes,
+            file,
+            written,
+            records: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Messages recorded since the recorder was opened.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Appends a record, rotating the file first when the line would exceed its size.
+    /// Every line is written at once, so the file stays readable if the application
+    /// stops.
+    pub fn write(&mut self, record: &CaptureRecord) -> Result<(), String> {
+        let mut line = record.to_line();
+        line.push('\n');
+        let len = line.len() as u64;
+
+        if let Some(max_bytes) = self.max_file_bytes
+            && self.written > 0
+            && self.written + len > max_bytes
+        {
+            self.rotate()?;
+        }
+
+        self.file
+            .write_all(line.as_bytes())
+            .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        self.written += len;
+        self.records += 1;
+
+        Ok(())
+    }
+
+    /// Moves the current file to the first free `<path>.N` and starts a new one.
+    fn rotate(&mut self) -> Result<(), String> {
+        let rotated = (1..)
+            .map(|index| rotated_path(&self.path, index))
+            .find(|path| !path.exists())
+            .expect("some rotated path is free");
+
+        std::fs::rename(&self.path, &rotated)
+            .map_err(|e| format!("{}: {}", rotated.display(), e))?;
+        self.file = open_append(&self.path)?;
+        self.written = 0;
+
+        Ok(())
+    }
+}
+
+/// Path of the `index`th rotated part of a capture file.
+pub fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+fn open_append(path: &Path) -> Result<File, String> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn test_recorder_rotates_by_size() {
+        let dir = TempDir::new("capture");
+        let path = dir.path().join("session.jsonl");
+
+        let record = CaptureRecord {
+            timestamp: OffsetDateTime::UNIX_EPOCH,
+            topic: "sensors/t1".into(),
+            payload: "21.5".into(),
+            qos: 0,
+            retain: false,
+            properties: None,
+        };
+        let line_len = record.to_line().len() as u64 + 1;
+
+        let mut recorder = Recorder::open(&path, Some(line_len * 2)).unwrap();
+        for _ in 0..5 {
+            recorder.write(&record).unwrap();
+        }
+
+        let lines = |path: &Path| std::fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!(recorder.records(), 5);
+        assert_eq!(lines(&rotated_path(&path, 1)), 2);
+        assert_eq!(lines(&rotated_path(&path, 2)), 2);
+        assert_eq!(lines(&path), 1);
+    }
+}
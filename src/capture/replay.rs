@@ -0,0 +1,294 @@
+//! Offline replay of a capture file into the topic activity screen, without a broker.
+//! The messages keep the gaps between their receive times, scaled by the playback
+//! speed, and go through the same path as the messages received from a broker.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use time::OffsetDateTime;
+
+use super::CaptureRecord;
+use crate::app::TopicActivityMenuState;
+use crate::mqtt::{self, MQTTEvent};
+
+/// How often the replay checks for due messages.
+const REPLAY_TICK: Duration = Duration::from_millis(10);
+
+/// Messages delivered per tick at instant speed, so the screen keeps refreshing.
+const INSTANT_BATCH: usize = 1000;
+
+/// Reads every record of a capture file.
+pub fn read_capture(path: &Path) -> Result<Vec<CaptureRecord>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            CaptureRecord::from_line(line)
+                .map_err(|e| format!("{}:{}: {}", path.display(), index + 1, e))
+        })
+        .collect()
+}
+
+/// Playback speed of a replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplaySpeed {
+    /// The original gaps between messages.
+    #[default]
+    Realtime,
+    Double,
+    Tenfold,
+    /// Every message at once.
+    Instant,
+}
+
+impl ReplaySpeed {
+    pub fn label(self) -> &'static str {
+        match self {
+            ReplaySpeed::Realtime => "1x",
+            ReplaySpeed::Double => "2x",
+            ReplaySpeed::Tenfold => "10x",
+            ReplaySpeed::Instant => "instant",
+        }
+    }
+
+    /// The next faster speed, back to real time after instant.
+    pub fn next(self) -> Self {
+        match self {
+            ReplaySpeed::Realtime => ReplaySpeed::Double,
+            ReplaySpeed::Double => ReplaySpeed::Tenfold,
+            ReplaySpeed::Tenfold => ReplaySpeed::Instant,
+            ReplaySpeed::Instant => ReplaySpeed::Realtime,
+        }
+    }
+
+    /// How much faster than the capture the messages are played, `None` at instant speed.
+    fn factor(self) -> Option<u32> {
+        match self {
+            ReplaySpeed::Realtime => Some(1),
+            ReplaySpeed::Double => Some(2),
+            ReplaySpeed::Tenfold => Some(10),
+            ReplaySpeed::Instant => None,
+        }
+    }
+}
+
+impl std::str::FromStr for ReplaySpeed {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "1" | "1x" | "realtime" => Ok(ReplaySpeed::Realtime),
+            "2" | "2x" => Ok(ReplaySpeed::Double),
+            "10" | "10x" => Ok(ReplaySpeed::Tenfold),
+            "instant" => Ok(ReplaySpeed::Instant),
+            _ => Err(format!("Unknown speed '{}', expected 1x, 2x, 10x or instant", text)),
+        }
+    }
+}
+
+/// Progress of a replay.
+#[derive(Debug)]
+pub struct Replay {
+    pub path: PathBuf,
+    records: Vec<CaptureRecord>,
+    /// Index of the next record to play.
+    position: usize,
+    pub speed: ReplaySpeed,
+    pub paused: bool,
+    /// Capture time reached by the playback, since the first record.
+    clock: Duration,
+    /// When the clock last advanced.
+    last_tick: Option<Instant>,
+    /// Whether the next record should be played while paused.
+    step: bool,
+}
+
+impl Replay {
+    pub fn new(path: &Path, records: Vec<CaptureRecord>, speed: ReplaySpeed) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            records,
+            position: 0,
+            speed,
+            paused: false,
+            clock: Duration::ZERO,
+            last_tick: None,
+            step: false,
+        }
+    }
+
+    /// Number of records played so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position == self.records.len()
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Pauses the replay and plays the next record.
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.step = true;
+    }
+
+    pub fn cycle_speed(&mut self) {
+        self.speed = self.speed.next();
+    }
+
+    /// Capture time reached by the playback, which stands for the current time when
+    /// messages are discarded by age. `None` for an empty capture.
+    pub fn current_time(&self) -> Option<OffsetDateTime> {
+        self.records.first().map(|first| first.timestamp + self.clock)
+    }
+
+    /// Time of a record since the first one.
+    fn offset(&self, index: usize) -> Duration {
+        self.records[index].offset_from(&self.records[0])
+    }
+
+    /// Advances the playback to `now` and returns the records that became due.
+    pub fn due(&mut self, now: Instant) -> Vec<CaptureRecord> {
+        let elapsed = self.last_tick.map_or(Duration::ZERO, |last| now - last);
+        self.last_tick = Some(now);
+
+        if self.is_finished() {
+            return Vec::new();
+        }
+
+        let end = if std::mem::take(&mut self.step) {
+            self.clock = self.offset(self.position);
+            self.position + 1
+        } else if self.paused {
+            return Vec::new();
+        } else {
+            match self.speed.factor() {
+                Some(factor) => {
+                    self.clock += elapsed * factor;
+                    (self.position..self.records.len())
+                        .find(|index| self.offset(*index) > self.clock)
+                        .unwrap_or(self.records.len())
+                }
+                None => (self.position + INSTANT_BATCH).min(self.records.len()),
+            }
+        };
+
+        let due = self.records[self.position..end].to_vec();
+        self.position = end;
+        if let Some(last) = end.checked_sub(1) {
+            self.clock = self.clock.max(self.offset(last));
+        }
+
+        due
+    }
+}
+
+/// Spawn a task playing the replay of `menu_state` until it is finished.
+pub fn spawn_replay(menu_state: Arc<Mutex<TopicActivityMenuState>>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(REPLAY_TICK);
+
+        loop {
+            ticker.tick().await;
+
+            let due = {
+                let Ok(mut menu_lock) = menu_state.lock() else {
+                    return;
+                };
+                match menu_lock.replay.as_mut() {
+                    Some(replay) => replay.due(Instant::now()),
+                    None => return,
+                }
+            };
+
+            for record in due {
+                mqtt::push_message_into_topic(&menu_state, MQTTEvent::from(record));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay(offsets_ms: &[i64]) -> Replay {
+        let records = offsets_ms
+            .iter()
+            .map(|offset| CaptureRecord {
+                timestamp: OffsetDateTime::UNIX_EPOCH + time::Duration::milliseconds(*offset),
+                topic: "t".into(),
+                payload: offset.to_string().into(),
+                qos: 0,
+                retain: false,
+                properties: None,
+            })
+            .collect();
+
+        Replay::new(Path::new("capture.jsonl"), records, ReplaySpeed::Realtime)
+    }
+
+    fn payloads(records: Vec<CaptureRecord>) -> Vec<String> {
+        records
+            .into_iter()
+            .map(|r| String::from_utf8(r.payload.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_keeps_the_gaps_scaled_by_the_speed() {
+        let mut replay = replay(&[0, 100, 1000, 1100]);
+        let start = Instant::now();
+
+        assert_eq!(payloads(replay.due(start)), vec!["0"]);
+        assert_eq!(payloads(replay.due(start + Duration::from_millis(150))), vec!["100"]);
+        assert_eq!(
+            replay.current_time(),
+            Some(OffsetDateTime::UNIX_EPOCH + Duration::from_millis(150))
+        );
+
+        replay.cycle_speed();
+        assert_eq!(replay.speed, ReplaySpeed::Double);
+        assert!(replay.due(start + Duration::from_millis(500)).is_empty());
+        assert_eq!(payloads(replay.due(start + Duration::from_millis(600))), vec!["1000"]);
+
+        replay.speed = ReplaySpeed::Instant;
+        assert_eq!(payloads(replay.due(start + Duration::from_millis(610))), vec!["1100"]);
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn test_paused_replay_plays_one_record_per_step() {
+        let mut replay = replay(&[0, 5000, 9000]);
+        let start = Instant::now();
+
+        replay.toggle_pause();
+        assert!(replay.due(start).is_empty());
+
+        replay.step();
+        assert_eq!(payloads(replay.due(start)), vec!["0"]);
+        replay.step();
+        assert_eq!(payloads(replay.due(start)), vec!["5000"]);
+        assert!(replay.due(start + Duration::from_secs(60)).is_empty());
+
+        replay.toggle_pause();
+        assert!(replay.due(start + Duration::from_secs(61)).is_empty());
+        assert_eq!(payloads(replay.due(start + Duration::from_secs(65))), vec!["9000"]);
+        assert_eq!(replay.position(), 3);
+    }
+}
@@ -0,0 +1,410 @@
+//! Command-line interface for mqtt-ranger.
+//! Values given here prefill the configuration form and override the configuration file.
+//! With `--host`, the splash screen and the form are skipped and the application connects
+//! right away.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Parser;
+
+use crate::app::history::default_history_path;
+use crate::app::{ConfigFormState, FocusField, RecordSettings, RetentionPolicy, StorageBackend};
+use crate::capture::{PrefixRewrite, ReplaySpeed};
+use crate::config::ConfigFile;
+use crate::decoder::protobuf::{self, MessageMapping, ProtobufMessage};
+use crate::decoder::sparkplug;
+use crate::decoder::{DecoderOverride, Decoders};
+use crate::mqtt::{MQTTConfig, ProtocolVersion, ReconnectPolicy, SubscriptionFilter};
+
+/// A terminal-based MQTT client with TUI interface.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Broker host name or address. Connects without showing the form when given, unless
+    /// `--user` comes without `--password` or the broker rejects the credentials.
+    #[arg(short = 'H', long)]
+    pub host: Option<String>,
+
+    /// Broker port (default 1883, or 8883 with `--tls`).
+    #[arg(short, long)]
+    pub port: Option<u16>,
+
+    /// Username to authenticate with.
+    #[arg(short, long = "user", visible_alias = "username", value_name = "USER")]
+    pub user: Option<String>,
+
+    /// Password to authenticate with. It is visible to other users in the process list,
+    /// leave it out to type it in the form.
+    #[arg(short = 'P', long)]
+    pub password: Option<String>,
+
+    /// MQTT protocol version: 3.1.1 or 5.
+    #[arg(long, value_name = "VERSION", default_value = "3.1.1")]
+    pub protocol: ProtocolVersion,
+
+    /// Connect over TLS.
+    #[arg(long)]
+    pub tls: bool,
+
+    /// CA certificate (PEM) used to verify the broker instead of the system roots.
+    #[arg(long, value_name = "PATH", requires = "tls")]
+    pub ca: Option<PathBuf>,
+
+    /// Client certificate (PEM) for mutual TLS.
+    #[arg(long, value_name = "PATH", requires_all = ["tls", "key"])]
+    pub cert: Option<PathBuf>,
+
+    /// Private key (PEM) of the client certificate.
+    #[arg(long, value_name = "PATH", requires_all = ["tls", "cert"])]
+    pub key: Option<PathBuf>,
+
+    /// Skip the verification of the broker certificate (lab brokers only).
+    #[arg(long, requires = "tls")]
+    pub insecure: bool,
+
+    /// Server name sent as SNI and verified in the broker certificate, when it differs from
+    /// the host.
+    #[arg(long, value_name = "NAME", requires = "tls")]
+    pub server_name: Option<String>,
+
+    /// Topic filter to subscribe to, with an optional QoS suffix (e.g. `sensors/#:1`).
+    /// Can be given several times. Defaults to `#` at QoS 0.
+    #[arg(short = 't', long = "topic", value_name = "FILTER[:QOS]")]
+    pub topics: Vec<SubscriptionFilter>,
+
+    /// Seconds to wait before reconnecting after the connection drops.
+    /// The delay doubles after every failed attempt.
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub reconnect_delay: u64,
+
+    /// Upper bound for the delay between reconnection attempts, in seconds.
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    pub reconnect_max_delay: u64,
+
+    /// Configuration file to read instead of `~/.config/mqtt-ranger/config.toml`.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Messages kept per topic before the oldest are discarded (default 10000, 0 = no limit).
+    #[arg(long, value_name = "N")]
+    pub max_messages: Option<usize>,
+
+    /// Payload bytes kept across all topics before the oldest messages are discarded
+    /// (0 = no limit, the default).
+    #[arg(long, value_name = "BYTES")]
+    pub max_bytes: Option<usize>,
+
+    /// Seconds after which messages are discarded (0 = never, the default).
+    #[arg(long, value_name = "SECS")]
+    pub max_age: Option<u64>,
+
+    /// Payload decoder for the topics matching a filter, e.g. `plant/+/raw=cbor`.
+    /// One of auto, json, cbor, msgpack, base64, gzip, protobuf, sparkplug, text;
+    /// chain with `+`.
+    /// Can be given several times and takes precedence over the configuration file.
+    #[arg(long = "decoder", value_name = "FILTER=DECODER")]
+    pub decoders: Vec<DecoderOverride>,
+
+    /// `.proto` file or compiled `FileDescriptorSet` to load message types from.
+    /// Can be given several times.
+    #[arg(long = "proto", value_name = "PATH")]
+    pub proto_files: Vec<PathBuf>,
+
+    /// Import path for the `.proto` files. Defaults to the directory of each file.
+    #[arg(long = "proto-include", value_name = "DIR")]
+    pub proto_include_paths: Vec<PathBuf>,
+
+    /// Protobuf message type of the topics matching a filter, e.g.
+    /// `devices/+/telemetry=acme.Telemetry`. Can be given several times.
+    #[arg(long = "proto-message", value_name = "FILTER=MESSAGE")]
+    pub proto_messages: Vec<MessageMapping>,
+
+    /// Decode `spBv1.0` topics as Sparkplug B and track the online state of their
+    /// edge nodes and devices.
+    #[arg(long)]
+    pub sparkplug: bool,
+
+    /// Record every received message to this capture file (JSON Lines) from the start.
+    /// Recording can also be toggled with `w`, which writes to this file when given.
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+
+    /// Size of a capture file before it is renamed to `<PATH>.1`, `<PATH>.2`, ... and a
+    /// new one is started (default 64 MiB, 0 = never rotate).
+    #[arg(long, value_name = "BYTES")]
+    pub record_max_bytes: Option<u64>,
+
+    /// Where the message history is kept: `memory` (the default) keeps the messages
+    /// allowed by the retention limits, `sqlite` also writes every message to a database
+    /// and reads older ones back when scrolling up.
+    #[arg(long, value_name = "BACKEND")]
+    pub storage: Option<StorageBackend>,
+
+    /// Database of the `sqlite` storage backend
+    /// (default `~/.local/share/mqtt-ranger/history.db`).
+    #[arg(long, value_name = "PATH")]
+    pub storage_path: Option<PathBuf>,
+
+    /// Play a capture file instead of connecting to a broker.
+    #[arg(long, value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+
+    /// Initial playback speed of `--replay`: 1x, 2x, 10x or instant.
+    #[arg(long, value_name = "SPEED", default_value = "1x")]
+    pub replay_speed: ReplaySpeed,
+
+    /// Publish every message of a capture file to the broker from the connection form,
+    /// keeping its topic, QoS and retain flag, instead of opening the topic activity screen.
+    #[arg(long, value_name = "PATH")]
+    pub publish_capture: Option<PathBuf>,
+
+    /// Replace a topic prefix of `--publish-capture`, e.g. `plant1/=staging/plant1/`.
+    /// An empty OLD prefix prepends NEW to every topic.
+    #[arg(long, value_name = "OLD=NEW")]
+    pub rewrite_prefix: Option<PrefixRewrite>,
+
+    /// How much faster than recorded `--publish-capture` sends the messages
+    /// (0 = as fast as the broker accepts them).
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = speed_factor)]
+    pub publish_speed: f64,
+}
+
+impl Cli {
+    /// Builds the initial configuration form state from the command-line values.
+    pub fn form_state(&self) -> ConfigFormState {
+        let mut state = ConfigFormState::new();
+        let path = |path: &Option<PathBuf>| {
+            path.as_ref().map(|path| path.display().to_string()).unwrap_or_default()
+        };
+
+        state.host = self.host.clone().unwrap_or_default();
+        state.port = match (self.port, &self.host) {
+            (Some(port), _) => port.to_string(),
+            (None, Some(_)) if self.tls => "8883".to_string(),
+            (None, Some(_)) => "1883".to_string(),
+            (None, None) => String::new(),
+        };
+        state.username = self.user.clone().unwrap_or_default();
+        state.password = self.password.clone().unwrap_or_default();
+        state.protocol = self.protocol;
+        state.tls = self.tls;
+        state.tls_ca_path = path(&self.ca);
+        state.tls_client_cert = path(&self.cert);
+        state.tls_client_key = path(&self.key);
+        state.tls_insecure = self.insecure;
+        state.tls_server_name = self.server_name.clone().unwrap_or_default();
+        state.topics = self
+            .topics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        if self.host.is_some() && self.missing_password() {
+            state.focus = FocusField::Password;
+        }
+
+        state
+    }
+
+    /// Whether a username is given without the password, which is then typed in the form.
+    fn missing_password(&self) -> bool {
+        self.user.is_some() && self.password.is_none()
+    }
+
+    /// The connection described by the command line when `--host` is given, so the form
+    /// can be skipped.
+    pub fn connection_config(&self) -> Option<Result<MQTTConfig, String>> {
+        if self.host.is_none() || self.missing_password() {
+            return None;
+        }
+
+        Some(self.form_state().config())
+    }
+
+    /// Backoff used to reconnect to the broker.
+    pub fn reconnect_policy(&self) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_secs(self.reconnect_delay),
+            max_delay: Duration::from_secs(self.reconnect_max_delay.max(self.reconnect_delay)),
+        }
+    }
+
+    /// Retention policy from the command line, then the configuration file, then the
+    /// defaults. A limit of 0 disables it.
+    pub fn retention_policy(&self, file: &ConfigFile) -> RetentionPolicy {
+        let defaults = RetentionPolicy::default();
+
+        RetentionPolicy {
+            max_messages_per_topic: limit(
+                self.max_messages.or(file.retention.max_messages_per_topic),
+                defaults.max_messages_per_topic,
+            ),
+            max_total_bytes: limit(
+                self.max_bytes.or(file.retention.max_total_bytes),
+                defaults.max_total_bytes,
+            ),
+            max_age: limit(
+                self.max_age.or(file.retention.max_age_secs),
+                defaults.max_age.map(|age| age.as_secs()),
+            )
+            .map(Duration::from_secs),
+        }
+    }
+
+    /// Capture file and rotation size of the recordings.
+    pub fn record_settings(&self) -> RecordSettings {
+        let defaults = RecordSettings::default();
+
+        RecordSettings {
+            path: self.record.clone(),
+            max_file_bytes: limit(self.record_max_bytes, defaults.max_file_bytes),
+        }
+    }
+
+    /// Database of the message history with the SQLite storage backend, from the
+    /// command line, then the configuration file. `None` keeps the history in memory.
+    pub fn history_path(&self, file: &ConfigFile) -> Result<Option<PathBuf>, String> {
+        match self.storage.or(file.storage.backend).unwrap_or_default() {
+            StorageBackend::Memory => Ok(None),
+            StorageBackend::Sqlite => self
+                .storage_path
+                .clone()
+                .or_else(|| file.storage.path.clone())
+                .or_else(default_history_path)
+                .map(Some)
+                .ok_or_else(|| "No home directory for the history database".to_string()),
+        }
+    }
+
+    /// Whether the Sparkplug B mode is enabled on the command line or in the
+    /// configuration file.
+    pub fn sparkplug(&self, file: &ConfigFile) -> bool {
+        self.sparkplug || file.sparkplug.enabled
+    }
+
+    /// Payload decoders with the overrides from the command line, then the
+    /// configuration file, then the Sparkplug B topics when the mode is enabled,
+    /// and a decoder for every mapped protobuf message type.
+    pub fn decoders(&self, file: &ConfigFile) -> Result<Decoders, String> {
+        let mut decoders = Decoders::default();
+
+        for rule in self.decoders.iter().chain(&file.decoders) {
+            decoders.add_override(rule.clone())?;
+        }
+
+        if self.sparkplug(file) {
+            for message_type in sparkplug::MESSAGE_TYPES {
+                decoders.add_override(DecoderOverride {
+                    filter: format!("{}/+/{}/#", sparkplug::NAMESPACE, message_type),
+                    decoder: sparkplug::SPARKPLUG.to_string(),
+                })?;
+            }
+        }
+
+        let files: Vec<PathBuf> =
+            self.proto_files.iter().chain(&file.protobuf.files).cloned().collect();
+        let include_paths: Vec<PathBuf> = self
+            .proto_include_paths
+            .iter()
+            .chain(&file.protobuf.include_paths)
+            .cloned()
+            .collect();
+        let mappings: Vec<&MessageMapping> =
+            self.proto_messages.iter().chain(&file.protobuf.messages).collect();
+
+        if files.is_empty() && mappings.is_empty() {
+            return Ok(decoders);
+        }
+
+        let pool = protobuf::load_descriptors(&files, &include_paths)?;
+
+        for mapping in mappings {
+            let descriptor = pool
+                .get_message_by_name(&mapping.message)
+                .ok_or_else(|| format!("Unknown protobuf message '{}'", mapping.message))?;
+
+            decoders.register(Box::new(ProtobufMessage::new(descriptor)));
+            decoders.add_override(DecoderOverride {
+                filter: mapping.filter.clone(),
+                decoder: mapping.message.clone(),
+            })?;
+        }
+
+        Ok(decoders)
+    }
+}
+
+/// Parses a finite, non-negative speed factor.
+fn speed_factor(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(factor) if factor.is_finite() && factor >= 0.0 => Ok(factor),
+        _ => Err(format!("Expected a non-negative number, got '{}'", text)),
+    }
+}
+
+/// Resolves a configured limit: 0 disables it, no value keeps the default.
+fn limit<T: PartialEq + Default>(value: Option<T>, default: Option<T>) -> Option<T> {
+    match value {
+        Some(value) if value == T::default() => None,
+        Some(value) => Some(value),
+        None => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_options_skip_or_prefill_the_form() {
+        let cli = Cli::parse_from([
+            "mqtt-ranger", "--host", "broker.lan", "-u", "ops", "-P", "secret", "--tls",
+            "--insecure", "-t", "plant/#:1",
+        ]);
+
+        let config = cli.connection_config().unwrap().unwrap();
+        assert_eq!(config.host, "broker.lan");
+        assert_eq!(config.port, 8883);
+        assert_eq!(config.username.as_deref(), Some("ops"));
+        assert_eq!(config.password.as_deref(), Some("secret"));
+        assert!(config.tls.unwrap().insecure_skip_verify);
+        assert_eq!(config.subscriptions[0].filter, "plant/#");
+        assert_eq!(config.subscriptions[0].qos, 1);
+
+        let cli = Cli::parse_from(["mqtt-ranger", "--port", "1884", "--protocol", "5"]);
+        let state = cli.form_state();
+        assert!(cli.connection_config().is_none());
+        assert_eq!((state.host.as_str(), state.port.as_str()), ("", "1884"));
+        assert_eq!(state.protocol, ProtocolVersion::V5);
+
+        let cli = Cli::parse_from(["mqtt-ranger", "--host", "broker.lan", "-u", "ops"]);
+        assert!(cli.connection_config().is_none());
+        assert_eq!(cli.form_state().focus, FocusField::Password);
+        assert!(Cli::try_parse_from(["mqtt-ranger", "--insecure"]).is_err());
+    }
+
+    #[test]
+    fn test_retention_policy_prefers_command_line_over_file() {
+        let cli = Cli::parse_from(["mqtt-ranger", "--max-messages", "0", "--max-age", "60"]);
+        let file = ConfigFile::parse(
+            "[retention]\nmax_messages_per_topic = 50\nmax_total_bytes = 1024\n",
+        )
+        .unwrap();
+
+        let policy = cli.retention_policy(&file);
+
+        assert_eq!(policy.max_messages_per_topic, None);
+        assert_eq!(policy.max_total_bytes, Some(1024));
+        assert_eq!(policy.max_age, Some(Duration::from_secs(60)));
+        assert_eq!(
+            Cli::parse_from(["mqtt-ranger"]).retention_policy(&ConfigFile::default()),
+            RetentionPolicy::default()
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Optional TOML configuration file.
+//! Read from `--config <PATH>` or, when it exists, `$XDG_CONFIG_HOME/mqtt-ranger/config.toml`
+//! (`~/.config/mqtt-ranger/config.toml`). Command-line options take precedence.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::app::{PublishJob, StorageBackend};
+use crate::decoder::DecoderOverride;
+use crate::decoder::protobuf::MessageMapping;
+
+/// Contents of the configuration file. Every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub retention: RetentionSettings,
+    /// `[[decoder]]` entries forcing a payload decoder for a topic filter.
+    #[serde(rename = "decoder")]
+    pub decoders: Vec<DecoderOverride>,
+    pub protobuf: ProtobufSettings,
+    pub sparkplug: SparkplugSettings,
+    /// `[[publish_job]]` entries publishing a message on a schedule.
+    #[serde(rename = "publish_job")]
+    pub publish_jobs: Vec<PublishJobSettings>,
+    pub storage: StorageSettings,
+}
+
+/// `[retention]` section. A value of 0 disables the limit.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionSettings {
+    pub max_messages_per_topic: Option<usize>,
+    pub max_total_bytes: Option<usize>,
+    pub max_age_secs: Option<u64>,
+}
+
+/// `[protobuf]` section: schema files and the message type of each topic filter.
+/// Relative paths are resolved against the directory of the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtobufSettings {
+    /// `.proto` files or compiled `FileDescriptorSet` files.
+    pub files: Vec<PathBuf>,
+    /// Import paths for the `.proto` files.
+    pub include_paths: Vec<PathBuf>,
+    /// `[[protobuf.message]]` entries.
+    #[serde(rename = "message")]
+    pub messages: Vec<MessageMapping>,
+}
+
+/// `[sparkplug]` section.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SparkplugSettings {
+    /// Decode `spBv1.0` topics as Sparkplug B and track their edge nodes.
+    pub enabled: bool,
+}
+
+/// `[storage]` section: where the message history is kept.
+/// A relative path is resolved against the directory of the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSettings {
+    pub backend: Option<StorageBackend>,
+    /// Database of the `sqlite` backend.
+    pub path: Option<PathBuf>,
+}
+
+/// `[[publish_job]]` entry. The payload is a template, see `PayloadTemplate`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PublishJobSettings {
+    pub topic: String,
+    #[serde(default)]
+    pub payload: String,
+    pub interval_ms: u64,
+    /// Messages to publish, publishes until stopped when missing or 0.
+    pub count: Option<u64>,
+    #[serde(default)]
+    pub qos: u8,
+    #[serde(default)]
+    pub retain: bool,
+    /// Start the job as soon as the connection is up.
+    #[serde(default)]
+    pub start: bool,
+}
+
+impl PublishJobSettings {
+    /// The job described by the entry, or why it is invalid.
+    pub fn job(&self) -> Result<PublishJob, String> {
+        let job = PublishJob {
+            topic: self.topic.clone(),
+            template: self.payload.parse()?,
+            interval: Duration::from_millis(self.interval_ms),
+            count: self.count.filter(|count| *count > 0),
+            qos: self.qos,
+            retain: self.retain,
+        };
+        job.validate()?;
+
+        Ok(job)
+    }
+}
+
+impl ConfigFile {
+    /// Location of the configuration file when `--config` is not given.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(config_dir.join("mqtt-ranger").join("config.toml"))
+    }
+
+    /// Loads the file at `path`, or the default file when no path is given.
+    /// A missing default file yields an empty configuration.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let text =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut config = Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        if let Some(dir) = path.parent() {
+            let protobuf = &mut config.protobuf;
+            for file in protobuf.files.iter_mut().chain(&mut protobuf.include_paths) {
+                *file = dir.join(&*file);
+            }
+            if let Some(file) = config.storage.path.as_mut() {
+                *file = dir.join(&*file);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Parses the TOML text of a configuration file.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_retention_section() {
+        let config = ConfigFile::parse(
+            "[retention]\nmax_messages_per_topic = 500\nmax_age_secs = 3600\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.retention.max_messages_per_topic, Some(500));
+        assert_eq!(config.retention.max_total_bytes, None);
+        assert_eq!(config.retention.max_age_secs, Some(3600));
+    }
+
+    #[test]
+    fn test_parse_decoder_overrides() {
+        let config = ConfigFile::parse(
+            "[[decoder]]\nfilter = \"plant/+/cbor\"\ndecoder = \"cbor\"\n\n\
+             [[decoder]]\nfilter = \"legacy/#\"\ndecoder = \"base64+gzip\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.decoders.len(), 2);
+        assert_eq!(config.decoders[0].filter, "plant/+/cbor");
+        assert_eq!(config.decoders[1].decoder, "base64+gzip");
+    }
+
+    #[test]
+    fn test_parse_protobuf_section() {
+        let config = ConfigFile::parse(
+            "[protobuf]\nfiles = [\"telemetry.proto\"]\n\n\
+             [[protobuf.message]]\nfilter = \"devices/+/telemetry\"\n\
+             message = \"acme.Telemetry\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.protobuf.files, vec![PathBuf::from("telemetry.proto")]);
+        assert!(config.protobuf.include_paths.is_empty());
+        assert_eq!(config.protobuf.messages[0].message, "acme.Telemetry");
+    }
+
+    #[test]
+    fn test_parse_publish_jobs() {
+        let config = ConfigFile::parse(
+            "[[publish_job]]\ntopic = \"sim/heartbeat\"\npayload = \"{{counter}}\"\n\
+             interval_ms = 5000\nqos = 1\nstart = true\n",
+        )
+        .unwrap();
+
+        let settings = &config.publish_jobs[0];
+        let job = settings.job().unwrap();
+        assert!(settings.start);
+        assert_eq!(job.interval, Duration::from_secs(5));
+        assert_eq!(job.count, None);
+        assert_eq!(job.qos, 1);
+        assert_eq!(job.template.source(), "{{counter}}");
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_keys() {
+        assert!(ConfigFile::parse("[retention]\nmax_mesages = 1\n").is_err());
+        assert!(ConfigFile::parse("").is_ok());
+    }
+}
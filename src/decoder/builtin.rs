@@ -0,0 +1,297 @@
+//! Decoders shipped with mqtt-ranger: JSON, CBOR, MessagePack, base64, gzip and text,
+//! plus the schema-less protobuf dump from `protobuf` and Sparkplug B from `sparkplug`.
+
+use std::io::Read;
+
+use base64::Engine;
+
+use super::protobuf::RawProtobuf;
+use super::sparkplug::Sparkplug;
+use super::{Decoded, PayloadDecoder, Value};
+
+/// Label of payloads shown as text because no decoder recognized them.
+pub const TEXT: &str = "text";
+/// Label of payloads no decoder recognized that are not valid UTF-8.
+pub const BINARY: &str = "binary";
+
+/// Built-in decoders, in auto-detection order. Wrappers come first, then the
+/// self-describing text format, then the binary formats that accept more input.
+pub fn all() -> Vec<Box<dyn PayloadDecoder>> {
+    vec![
+        Box::new(Gzip),
+        Box::new(Json),
+        Box::new(Base64),
+        Box::new(Cbor),
+        Box::new(MessagePack),
+        Box::new(RawProtobuf),
+        Box::new(Sparkplug),
+        Box::new(Text),
+    ]
+}
+
+/// JSON documents. Only objects, arrays and strings are detected; bare numbers and
+/// literals are left as text.
+pub struct Json;
+
+impl PayloadDecoder for Json {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn detect(&self, payload: &[u8]) -> bool {
+        matches!(
+            payload.iter().find(|b| !b.is_ascii_whitespace()),
+            Some(b'{' | b'[' | b'"')
+        )
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String> {
+        let json: serde_json::Value = serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+        Ok(Decoded::Value(from_json(json)))
+    }
+}
+
+/// Converts a parsed JSON document, keeping the order of object keys.
+pub(crate) fn from_json(json: serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(value) => Value::Bool(value),
+        serde_json::Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(value), _) => Value::Integer(value.into()),
+            (None, Some(value)) => Value::Integer(value.into()),
+            _ => Value::Float(number.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(text) => Value::Text(text),
+        serde_json::Value::Array(items) => Value::Array(items.into_iter().map(from_json).collect()),
+        serde_json::Value::Object(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Value::Text(key), from_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// CBOR (RFC 8949). Detected when the payload is not text and holds exactly one
+/// map or array.
+pub struct Cbor;
+
+impl PayloadDecoder for Cbor {
+    fn name(&self) -> &str {
+        "cbor"
+    }
+
+    fn detect(&self, payload: &[u8]) -> bool {
+        // Major types 4 (array) and 5 (map), possibly behind a tag (6).
+        std::str::from_utf8(payload).is_err()
+            && payload.first().is_some_and(|b| matches!(b >> 5, 4..=6))
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String> {
+        let mut reader = payload;
+        let cbor: ciborium::Value =
+            ciborium::de::from_reader(&mut reader).map_err(|e| e.to_string())?;
+
+        if !reader.is_empty() {
+            return Err(format!("{} trailing bytes", reader.len()));
+        }
+
+        Ok(Decoded::Value(from_cbor(cbor)))
+    }
+}
+
+fn from_cbor(cbor: ciborium::Value) -> Value {
+    match cbor {
+        ciborium::Value::Null => Value::Null,
+        ciborium::Value::Bool(value) => Value::Bool(value),
+        ciborium::Value::Integer(value) => Value::Integer(value.into()),
+        ciborium::Value::Float(value) => Value::Float(value),
+        ciborium::Value::Text(text) => Value::Text(text),
+        ciborium::Value::Bytes(bytes) => Value::Bytes(bytes),
+        ciborium::Value::Tag(_, value) => from_cbor(*value),
+        ciborium::Value::Array(items) => Value::Array(items.into_iter().map(from_cbor).collect()),
+        ciborium::Value::Map(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (from_cbor(key), from_cbor(value)))
+                .collect(),
+        ),
+        _ => Value::Null,
+    }
+}
+
+/// MessagePack. Detected when the payload is not text and holds exactly one map
+/// or array.
+pub struct MessagePack;
+
+impl PayloadDecoder for MessagePack {
+    fn name(&self) -> &str {
+        "msgpack"
+    }
+
+    fn detect(&self, payload: &[u8]) -> bool {
+        // fixmap, fixarray, array 16/32 and map 16/32.
+        std::str::from_utf8(payload).is_err()
+            && payload
+                .first()
+                .is_some_and(|b| matches!(b, 0x80..=0x9f | 0xdc..=0xdf))
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String> {
+        let mut reader = payload;
+        let value = rmpv::decode::read_value(&mut reader).map_err(|e| e.to_string())?;
+
+        if !reader.is_empty() {
+            return Err(format!("{} trailing bytes", reader.len()));
+        }
+
+        Ok(Decoded::Value(from_msgpack(value)))
+    }
+}
+
+fn from_msgpack(value: rmpv::Value) -> Value {
+    match value {
+        rmpv::Value::Nil => Value::Null,
+        rmpv::Value::Boolean(value) => Value::Bool(value),
+        rmpv::Value::Integer(value) => match (value.as_i64(), value.as_u64()) {
+            (Some(value), _) => Value::Integer(value.into()),
+            (None, Some(value)) => Value::Integer(value.into()),
+            _ => Value::Null,
+        },
+        rmpv::Value::F32(value) => Value::Float(value.into()),
+        rmpv::Value::F64(value) => Value::Float(value),
+        rmpv::Value::String(text) => match text.into_str() {
+            Some(text) => Value::Text(text),
+            None => Value::Null,
+        },
+        rmpv::Value::Binary(bytes) => Value::Bytes(bytes),
+        rmpv::Value::Array(items) => Value::Array(items.into_iter().map(from_msgpack).collect()),
+        rmpv::Value::Map(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (from_msgpack(key), from_msgpack(value)))
+                .collect(),
+        ),
+        rmpv::Value::Ext(_, bytes) => Value::Bytes(bytes),
+    }
+}
+
+/// Standard base64, unwrapped into the encoded bytes. Only detected for payloads of
+/// at least 16 characters mixing cases and digits or symbols whose content is binary
+/// or JSON, so that ordinary words are not mistaken for base64.
+pub struct Base64;
+
+impl PayloadDecoder for Base64 {
+    fn name(&self) -> &str {
+        "base64"
+    }
+
+    fn detect(&self, payload: &[u8]) -> bool {
+        let payload = payload.trim_ascii();
+
+        payload.len() >= 16
+            && payload.len().is_multiple_of(4)
+            && payload
+                .iter()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'='))
+            && payload.iter().any(u8::is_ascii_uppercase)
+            && payload.iter().any(u8::is_ascii_lowercase)
+            && payload.iter().any(|b| !b.is_ascii_alphabetic())
+            && self.decode(payload).is_ok_and(|decoded| match decoded {
+                Decoded::Unwrapped(inner) => {
+                    std::str::from_utf8(&inner).is_err() || Json.detect(&inner)
+                }
+                Decoded::Value(_) => false,
+            })
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String> {
+        base64::engine::general_purpose::STANDARD
+            .decode(payload.trim_ascii())
+            .map(Decoded::Unwrapped)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Gzip, unwrapped into the decompressed bytes. Detected by its magic number.
+pub struct Gzip;
+
+impl PayloadDecoder for Gzip {
+    fn name(&self) -> &str {
+        "gzip"
+    }
+
+    fn detect(&self, payload: &[u8]) -> bool {
+        payload.starts_with(&[0x1f, 0x8b])
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String> {
+        let mut inflated = Vec::new();
+
+        flate2::read::GzDecoder::new(payload)
+            .read_to_end(&mut inflated)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Decoded::Unwrapped(inflated))
+    }
+}
+
+/// The payload as text, replacing invalid UTF-8. Never detected: it is what
+/// payloads no other decoder recognizes are shown as.
+pub struct Text;
+
+impl PayloadDecoder for Text {
+    fn name(&self) -> &str {
+        TEXT
+    }
+
+    fn detect(&self, _payload: &[u8]) -> bool {
+        false
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String> {
+        Ok(Decoded::Value(Value::Text(String::from_utf8_lossy(payload).into_owned())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(decoder: &dyn PayloadDecoder, payload: &[u8]) -> Value {
+        assert!(decoder.detect(payload), "{} should detect the payload", decoder.name());
+        match decoder.decode(payload).unwrap() {
+            Decoded::Value(value) => value,
+            Decoded::Unwrapped(bytes) => Value::Bytes(bytes),
+        }
+    }
+
+    #[test]
+    fn test_binary_formats_decode_to_the_same_value() {
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&serde_json::json!({ "id": 7, "ok": true }), &mut cbor).unwrap();
+
+        let msgpack = rmpv::Value::Map(vec![
+            (rmpv::Value::from("id"), rmpv::Value::from(7)),
+            (rmpv::Value::from("ok"), rmpv::Value::from(true)),
+        ]);
+        let mut msgpack_bytes = Vec::new();
+        rmpv::encode::write_value(&mut msgpack_bytes, &msgpack).unwrap();
+
+        let expected = value(&Json, br#"{"id": 7, "ok": true}"#);
+        assert_eq!(value(&Cbor, &cbor), expected);
+        assert_eq!(value(&MessagePack, &msgpack_bytes), expected);
+
+        cbor.push(0);
+        assert!(Cbor.decode(&cbor).is_err());
+    }
+
+    #[test]
+    fn test_detection_leaves_plain_text_alone() {
+        for payload in [&b"21.5"[..], b"ON", b"hello world", b"HelloWorldFooBar"] {
+            assert!(all().iter().all(|decoder| !decoder.detect(payload)));
+        }
+
+        assert!(Base64.detect(b"eyJ0ZW1wIjogMjEuNX0="));
+    }
+}
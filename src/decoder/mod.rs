@@ -0,0 +1,456 @@
+//! Payload decoders.
+//! A `PayloadDecoder` turns payload bytes into a `Value` tree, or unwraps them
+//! (base64, gzip) into bytes that are decoded again. `Decoders` picks the decoder
+//! of a message from the per-topic-filter overrides, or detects the format.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+pub mod builtin;
+pub mod protobuf;
+pub mod sparkplug;
+
+/// Maximum number of wrappers (base64, gzip) removed from a single payload.
+const MAX_UNWRAP_DEPTH: usize = 4;
+
+/// Characters of the decoded value kept for the single-line summary.
+const SUMMARY_LEN: usize = 256;
+
+/// Name of the pseudo decoder that detects the format.
+pub const AUTO: &str = "auto";
+
+/// Decoded payload, independent of the wire format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i128),
+    Float(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+/// Result of a single decoder.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decoded {
+    /// The payload was fully decoded.
+    Value(Value),
+    /// The payload was unwrapped into bytes that are decoded again.
+    Unwrapped(Vec<u8>),
+}
+
+/// Decodes payloads of one format.
+pub trait PayloadDecoder: Send + Sync {
+    /// Name used in overrides and shown next to each message.
+    fn name(&self) -> &str;
+
+    /// Whether auto-detection should pick this decoder for `payload`.
+    /// Should be cheap and conservative: a false positive hides the real format.
+    fn detect(&self, payload: &[u8]) -> bool;
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String>;
+}
+
+/// A payload after decoding, as shown on the activity screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedPayload {
+    /// Decoders applied, outermost first, e.g. `base64+gzip+json`.
+    pub decoder: String,
+    pub value: Value,
+    /// The value on a single line, shortened to `SUMMARY_LEN` characters.
+    pub summary: String,
+    /// Why the configured decoder could not decode the payload, in which case the
+    /// value holds the bytes the decoders before it unwrapped, as text, or a problem
+    /// found while decoding it.
+    pub error: Option<String>,
+}
+
+impl DecodedPayload {
+    pub fn new(decoder: String, value: Value, error: Option<String>) -> Self {
+        let summary = match &value {
+            Value::Text(text) => text.chars().take(SUMMARY_LEN).collect(),
+            value => value.to_string().chars().take(SUMMARY_LEN).collect(),
+        };
+
+        Self {
+            decoder,
+            value,
+            summary,
+            error,
+        }
+    }
+}
+
+/// Decoder forced for the topics matching a filter, e.g. `factory/+/raw=cbor`.
+/// Several decoders can be chained with `+`, e.g. `base64+gzip`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DecoderOverride {
+    pub filter: String,
+    pub decoder: String,
+}
+
+impl FromStr for DecoderOverride {
+    type Err = String;
+
+    /// Parses `filter=decoder`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (filter, decoder) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Expected FILTER=DECODER, got '{}'", s))?;
+
+        Ok(Self {
+            filter: filter.trim().to_string(),
+            decoder: decoder.trim().to_string(),
+        })
+    }
+}
+
+/// The available decoders and the overrides choosing between them.
+pub struct Decoders {
+    decoders: Vec<Box<dyn PayloadDecoder>>,
+    /// Checked in order; the first matching filter wins.
+    overrides: Vec<DecoderOverride>,
+}
+
+impl Default for Decoders {
+    fn default() -> Self {
+        Self::new(builtin::all())
+    }
+}
+
+impl Decoders {
+    /// Registry of `decoders`, tried in this order during auto-detection.
+    pub fn new(decoders: Vec<Box<dyn PayloadDecoder>>) -> Self {
+        Self {
+            decoders,
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Names accepted in overrides: `auto` followed by every decoder.
+    pub fn names(&self) -> Vec<&str> {
+        std::iter::once(AUTO)
+            .chain(self.decoders.iter().map(|decoder| decoder.name()))
+            .collect()
+    }
+
+    /// Adds a decoder, tried after the existing ones during auto-detection.
+    /// A decoder with the same name is not added twice.
+    pub fn register(&mut self, decoder: Box<dyn PayloadDecoder>) {
+        if self.find(decoder.name()).is_none() {
+            self.decoders.push(decoder);
+        }
+    }
+
+    /// Adds an override after the existing ones, checking its decoder names.
+    pub fn add_override(&mut self, rule: DecoderOverride) -> Result<(), String> {
+        if !rumqttc::valid_filter(&rule.filter) {
+            return Err(format!("Invalid topic filter '{}'", rule.filter));
+        }
+        for name in rule.decoder.split('+') {
+            if name != AUTO && self.find(name).is_none() {
+                return Err(format!(
+                    "Unknown decoder '{}' (expected one of: {})",
+                    name,
+                    self.names().join(", ")
+                ));
+            }
+        }
+
+        self.overrides.push(rule);
+        Ok(())
+    }
+
+    /// Forces `decoder` for exactly `topic`, taking precedence over every other
+    /// override. `auto` is kept as an override too, so detection is used even when a
+    /// wildcard override matches the topic.
+    pub fn set_topic_decoder(&mut self, topic: &str, decoder: &str) {
+        self.overrides.retain(|rule| rule.filter != topic);
+        self.overrides.insert(
+            0,
+            DecoderOverride {
+                filter: topic.to_string(),
+                decoder: decoder.to_string(),
+            },
+        );
+    }
+
+    /// The decoder configured for `topic`, `auto` when no override matches.
+    pub fn decoder_for(&self, topic: &str) -> &str {
+        self.overrides
+            .iter()
+            .find(|rule| rumqttc::matches(topic, &rule.filter))
+            .map_or(AUTO, |rule| rule.decoder.as_str())
+    }
+
+    fn find(&self, name: &str) -> Option<&dyn PayloadDecoder> {
+        self.decoders
+            .iter()
+            .find(|decoder| decoder.name() == name)
+            .map(|decoder| decoder.as_ref())
+    }
+
+    /// Decodes a payload received on `topic`.
+    pub fn decode(&self, topic: &str, payload: &[u8]) -> DecodedPayload {
+        let mut applied = Vec::new();
+        let mut bytes = payload.to_vec();
+
+        for name in self.decoder_for(topic).split('+').filter(|name| *name != AUTO) {
+            let Some(decoder) = self.find(name) else {
+                continue;
+            };
+
+            match decoder.decode(&bytes) {
+                Ok(Decoded::Value(value)) => {
+                    applied.push(name);
+                    return DecodedPayload::new(applied.join("+"), value, None);
+                }
+                Ok(Decoded::Unwrapped(inner)) => {
+                    applied.push(name);
+                    bytes = inner;
+                }
+                Err(e) => {
+                    applied.push(name);
+                    return DecodedPayload::new(
+                        format!("{}!", applied.join("+")),
+                        Value::Text(String::from_utf8_lossy(&bytes).into_owned()),
+                        Some(format!("{} decoder failed: {}", name, e)),
+                    );
+                }
+            }
+        }
+
+        let (detected, value) = self.detect(bytes);
+        applied.extend(detected);
+
+        DecodedPayload::new(applied.join("+"), value, None)
+    }
+
+    /// Detects the format of `payload`, unwrapping it as long as a wrapper is detected.
+    /// Payloads no decoder recognizes are kept as text, or bytes when not UTF-8.
+    fn detect(&self, mut payload: Vec<u8>) -> (Vec<&str>, Value) {
+        let mut applied = Vec::new();
+
+        for _ in 0..MAX_UNWRAP_DEPTH {
+            let decoded = self
+                .decoders
+                .iter()
+                .filter(|decoder| decoder.detect(&payload))
+                .find_map(|decoder| Some((decoder.name(), decoder.decode(&payload).ok()?)));
+
+            match decoded {
+                Some((name, Decoded::Value(value))) => {
+                    applied.push(name);
+                    return (applied, value);
+                }
+                Some((name, Decoded::Unwrapped(inner))) => {
+                    applied.push(name);
+                    payload = inner;
+                }
+                None => break,
+            }
+        }
+
+        match String::from_utf8(payload) {
+            Ok(text) => {
+                applied.push(builtin::TEXT);
+                (applied, Value::Text(text))
+            }
+            Err(e) => {
+                applied.push(builtin::BINARY);
+                (applied, Value::Bytes(e.into_bytes()))
+            }
+        }
+    }
+}
+
+/// A line of the indented view of a `Value`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeLine {
+    pub depth: usize,
+    /// Map key or array index, `None` for the root.
+    pub key: Option<String>,
+    /// Scalar value, or a summary such as `{3 entries}` for maps and arrays.
+    pub value: String,
+    pub is_container: bool,
+}
+
+impl Value {
+    /// The value as an indented tree, one line per entry.
+    pub fn tree_lines(&self) -> Vec<TreeLine> {
+        let mut lines = Vec::new();
+        self.push_tree_lines(None, 0, &mut lines);
+        lines
+    }
+
+    fn push_tree_lines(&self, key: Option<String>, depth: usize, lines: &mut Vec<TreeLine>) {
+        let (value, is_container) = match self {
+            Value::Array(items) => (format!("[{} items]", items.len()), true),
+            Value::Map(entries) => (format!("{{{} entries}}", entries.len()), true),
+            scalar => (scalar.to_string(), false),
+        };
+        lines.push(TreeLine {
+            depth,
+            key,
+            value,
+            is_container,
+        });
+
+        match self {
+            Value::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    item.push_tree_lines(Some(format!("[{}]", index)), depth + 1, lines);
+                }
+            }
+            Value::Map(entries) => {
+                for (key, value) in entries {
+                    value.push_tree_lines(Some(key.key_string()), depth + 1, lines);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Map keys are shown without quotes when they are text.
+    fn key_string(&self) -> String {
+        match self {
+            Value::Text(text) => text.clone(),
+            other => other.to_string(),
+        }
+    }
+}
+
+/// Compact single-line rendering, close to JSON.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Text(text) => write!(f, "{:?}", text),
+            Value::Bytes(bytes) => {
+                write!(f, "<{} bytes ", bytes.len())?;
+                for byte in bytes.iter().take(16) {
+                    write!(f, "{:02x}", byte)?;
+                }
+                if bytes.len() > 16 {
+                    write!(f, "…")?;
+                }
+                write!(f, ">")
+            }
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key.key_string(), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use base64::Engine;
+
+    #[test]
+    fn test_auto_detection_unwraps_nested_formats() {
+        let decoders = Decoders::default();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(br#"{"temp": 21.5}"#).unwrap();
+        let base64 = base64::engine::general_purpose::STANDARD.encode(encoder.finish().unwrap());
+
+        let decoded = decoders.decode("a", base64.as_bytes());
+        assert_eq!(decoded.decoder, "base64+gzip+json");
+        assert_eq!(decoded.value.to_string(), "{temp: 21.5}");
+
+        let decoded = decoders.decode("a", b"hello world");
+        assert_eq!(decoded.decoder, "text");
+        assert_eq!(decoded.value, Value::Text("hello world".into()));
+
+        let decoded = decoders.decode("a", &[0xff, 0x00, 0x13]);
+        assert_eq!(decoded.decoder, "binary");
+    }
+
+    #[test]
+    fn test_override_is_chosen_by_topic_filter() {
+        let mut decoders = Decoders::default();
+        decoders.add_override("raw/#=text".parse().unwrap()).unwrap();
+        decoders.add_override("+/cbor=cbor".parse().unwrap()).unwrap();
+        assert!(decoders.add_override("a=yaml".parse().unwrap()).is_err());
+
+        assert_eq!(decoders.decode("raw/x", b"[1, 2]").decoder, "text");
+        assert_eq!(decoders.decode("other/x", b"[1, 2]").decoder, "json");
+
+        let decoded = decoders.decode("dev/cbor", b"not cbor");
+        assert_eq!(decoded.decoder, "cbor!");
+        assert!(decoded.error.is_some());
+
+        decoders.set_topic_decoder("raw/x", "json");
+        assert_eq!(decoders.decoder_for("raw/x"), "json");
+        decoders.set_topic_decoder("raw/x", AUTO);
+        assert_eq!(decoders.decoder_for("raw/x"), AUTO);
+        assert_eq!(decoders.decode("raw/x", b"[1, 2]").decoder, "json");
+    }
+
+    #[test]
+    fn test_failed_chain_keeps_the_decoders_applied() {
+        let mut decoders = Decoders::default();
+        decoders.add_override("b64/#=base64+json".parse().unwrap()).unwrap();
+
+        let decoded = decoders.decode("b64/x", b"bm90IGpzb24=");
+        assert_eq!(decoded.decoder, "base64+json!");
+        assert_eq!(decoded.value, Value::Text("not json".into()));
+        assert!(decoded.error.unwrap().starts_with("json decoder failed"));
+    }
+
+    #[test]
+    fn test_tree_lines() {
+        let value = Value::Map(vec![
+            (Value::Text("id".into()), Value::Integer(7)),
+            (
+                Value::Text("tags".into()),
+                Value::Array(vec![Value::Text("a".into())]),
+            ),
+        ]);
+
+        let lines: Vec<(usize, Option<String>, String)> = value
+            .tree_lines()
+            .into_iter()
+            .map(|line| (line.depth, line.key, line.value))
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                (0, None, "{2 entries}".into()),
+                (1, Some("id".into()), "7".into()),
+                (1, Some("tags".into()), "[1 items]".into()),
+                (2, Some("[0]".into()), "\"a\"".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! Protobuf decoders.
+//! Message types come from `.proto` files or compiled `FileDescriptorSet`s loaded at
+//! startup and are mapped to topic filters. Payloads of other topics that look like
+//! protobuf are shown as a dump of their field numbers and wire values.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use prost_reflect::{DescriptorPool, DynamicMessage, Kind, MapKey, MessageDescriptor};
+use serde::Deserialize;
+
+use super::{Decoded, PayloadDecoder, Value};
+
+/// Name of the decoder dumping field numbers without a schema.
+pub const RAW: &str = "protobuf";
+
+/// Largest field number allowed by the protobuf specification.
+const MAX_FIELD_NUMBER: u64 = (1 << 29) - 1;
+
+/// Message type used for the topics matching a filter, e.g. `devices/+/telemetry`
+/// and `acme.Telemetry`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageMapping {
+    pub filter: String,
+    /// Fully qualified message name.
+    pub message: String,
+}
+
+impl FromStr for MessageMapping {
+    type Err = String;
+
+    /// Parses `filter=package.Message`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (filter, message) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Expected FILTER=MESSAGE, got '{}'", s))?;
+
+        Ok(Self {
+            filter: filter.trim().to_string(),
+            message: message.trim().to_string(),
+        })
+    }
+}
+
+/// Loads message descriptors from `.proto` files, compiled with `include_paths` (or
+/// the directory of each file when empty), and from `FileDescriptorSet` files.
+pub fn load_descriptors(
+    files: &[PathBuf],
+    include_paths: &[PathBuf],
+) -> Result<DescriptorPool, String> {
+    let (protos, descriptor_sets): (Vec<&PathBuf>, Vec<&PathBuf>) = files
+        .iter()
+        .partition(|file| file.extension().is_some_and(|ext| ext == "proto"));
+
+    let mut pool = DescriptorPool::new();
+
+    for file in descriptor_sets {
+        let bytes = std::fs::read(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+        pool.decode_file_descriptor_set(bytes.as_slice())
+            .map_err(|e| format!("{}: {}", file.display(), e))?;
+    }
+
+    if !protos.is_empty() {
+        let includes: Vec<&Path> = if include_paths.is_empty() {
+            let mut parents: Vec<&Path> = protos
+                .iter()
+                .map(|file| file.parent().unwrap_or(Path::new(".")))
+                .collect();
+            parents.dedup();
+            parents
+        } else {
+            include_paths.iter().map(PathBuf::as_path).collect()
+        };
+
+        let descriptor_set = protox::compile(&protos, includes).map_err(|e| e.to_string())?;
+        pool.add_file_descriptor_set(descriptor_set)
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(pool)
+}
+
+/// Decodes one message type. Never detected: it is chosen by a topic mapping.
+pub struct ProtobufMessage {
+    descriptor: MessageDescriptor,
+}
+
+impl ProtobufMessage {
+    pub fn new(descriptor: MessageDescriptor) -> Self {
+        Self { descriptor }
+    }
+}
+
+impl PayloadDecoder for ProtobufMessage {
+    fn name(&self) -> &str {
+        self.descriptor.full_name()
+    }
+
+    fn detect(&self, _payload: &[u8]) -> bool {
+        false
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String> {
+        let message =
+            DynamicMessage::decode(self.descriptor.clone(), payload).map_err(|e| e.to_string())?;
+
+        Ok(Decoded::Value(message_value(&message)))
+    }
+}
+
+/// Fields of a message in declaration order, followed by the fields unknown to the
+/// schema keyed by their number.
+fn message_value(message: &DynamicMessage) -> Value {
+    let mut entries: Vec<(Value, Value)> = message
+        .fields()
+        .map(|(field, value)| {
+            let name = Value::Text(field.name().to_string());
+            (name, field_value(&field.kind(), value))
+        })
+        .collect();
+
+    for field in message.unknown_fields() {
+        let mut encoded = Vec::new();
+        field.encode(&mut encoded);
+        entries.extend(parse_fields(&encoded).unwrap_or_default());
+    }
+
+    Value::Map(entries)
+}
+
+fn field_value(kind: &Kind, value: &prost_reflect::Value) -> Value {
+    use prost_reflect::Value as Field;
+
+    match value {
+        Field::Bool(value) => Value::Bool(*value),
+        Field::I32(value) => Value::Integer((*value).into()),
+        Field::I64(value) => Value::Integer((*value).into()),
+        Field::U32(value) => Value::Integer((*value).into()),
+        Field::U64(value) => Value::Integer((*value).into()),
+        Field::F32(value) => Value::Float((*value).into()),
+        Field::F64(value) => Value::Float(*value),
+        Field::String(text) => Value::Text(text.clone()),
+        Field::Bytes(bytes) => Value::Bytes(bytes.to_vec()),
+        Field::EnumNumber(number) => match kind {
+            Kind::Enum(descriptor) => match descriptor.get_value(*number) {
+                Some(value) => Value::Text(value.name().to_string()),
+                None => Value::Integer((*number).into()),
+            },
+            _ => Value::Integer((*number).into()),
+        },
+        Field::Message(message) => message_value(message),
+        Field::List(items) => {
+            Value::Array(items.iter().map(|item| field_value(kind, item)).collect())
+        }
+        Field::Map(entries) => {
+            let value_kind = match kind {
+                Kind::Message(entry) => entry.map_entry_value_field().kind(),
+                other => other.clone(),
+            };
+            let mut entries: Vec<(&MapKey, &prost_reflect::Value)> = entries.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+
+            Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (map_key(key), field_value(&value_kind, value)))
+                    .collect(),
+            )
+        }
+    }
+}
+
+fn map_key(key: &MapKey) -> Value {
+    match key {
+        MapKey::Bool(value) => Value::Bool(*value),
+        MapKey::I32(value) => Value::Integer((*value).into()),
+        MapKey::I64(value) => Value::Integer((*value).into()),
+        MapKey::U32(value) => Value::Integer((*value).into()),
+        MapKey::U64(value) => Value::Integer((*value).into()),
+        MapKey::String(text) => Value::Text(text.clone()),
+    }
+}
+
+/// Dumps protobuf payloads without a schema: every field keyed by its number, with
+/// its wire value. Detected for binary payloads that are entirely valid protobuf.
+pub struct RawProtobuf;
+
+impl PayloadDecoder for RawProtobuf {
+    fn name(&self) -> &str {
+        RAW
+    }
+
+    fn detect(&self, payload: &[u8]) -> bool {
+        !is_text(payload) && parse_fields(payload).is_some_and(|fields| !fields.is_empty())
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String> {
+        parse_fields(payload)
+            .map(|fields| Decoded::Value(Value::Map(fields)))
+            .ok_or_else(|| "not a valid protobuf message".to_string())
+    }
+}
+
+/// Whether `payload` is printable UTF-8 text.
+fn is_text(payload: &[u8]) -> bool {
+    std::str::from_utf8(payload)
+        .is_ok_and(|text| text.chars().all(|c| !c.is_control() || c.is_whitespace()))
+}
+
+/// Parses the fields of a message from the wire format, `None` when `bytes` is not
+/// a valid message. Length-delimited fields are shown as text when printable, as a
+/// nested message when they parse as one, and as bytes otherwise.
+fn parse_fields(mut bytes: &[u8]) -> Option<Vec<(Value, Value)>> {
+    let mut fields = Vec::new();
+
+    while !bytes.is_empty() {
+        let tag = read_varint(&mut bytes)?;
+        let number = tag >> 3;
+
+        if number == 0 || number > MAX_FIELD_NUMBER {
+            return None;
+        }
+
+        let value = match tag & 7 {
+            0 => Value::Integer(read_varint(&mut bytes)?.into()),
+            1 => Value::Integer(u64::from_le_bytes(take(&mut bytes, 8)?.try_into().ok()?).into()),
+            2 => {
+                let len = usize::try_from(read_varint(&mut bytes)?).ok()?;
+                let data = take(&mut bytes, len)?;
+
+                if is_text(data) {
+                    Value::Text(String::from_utf8_lossy(data).into_owned())
+                } else {
+                    match parse_fields(data) {
+                        Some(nested) if !nested.is_empty() => Value::Map(nested),
+                        _ => Value::Bytes(data.to_vec()),
+                    }
+                }
+            }
+            5 => Value::Integer(u32::from_le_bytes(take(&mut bytes, 4)?.try_into().ok()?).into()),
+            // Groups are deprecated and 6 and 7 are not wire types.
+            _ => return None,
+        };
+
+        fields.push((Value::Text(number.to_string()), value));
+    }
+
+    Some(fields)
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if bytes.len() < len {
+        return None;
+    }
+
+    let (data, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    const PROTO: &str = r#"
+        syntax = "proto3";
+        package acme;
+
+        enum Status { UNKNOWN = 0; OK = 1; }
+
+        message Telemetry {
+          string device = 1;
+          double temperature = 2;
+          Status status = 3;
+          repeated uint32 samples = 4;
+        }
+    "#;
+
+    // device = "d1", temperature = 21.5, status = OK, samples = [1, 300], field 9 = 7
+    const PAYLOAD: &[u8] = &[
+        0x0a, 0x02, b'd', b'1', 0x11, 0, 0, 0, 0, 0, 0x80, 0x35, 0x40, 0x18, 0x01, 0x22, 0x03,
+        0x01, 0xac, 0x02, 0x48, 0x07,
+    ];
+
+    fn telemetry() -> ProtobufMessage {
+        let dir = TempDir::new("proto");
+        let file = dir.path().join("telemetry.proto");
+        std::fs::write(&file, PROTO).unwrap();
+
+        let pool = load_descriptors(&[file], &[]).unwrap();
+
+        ProtobufMessage::new(pool.get_message_by_name("acme.Telemetry").unwrap())
+    }
+
+    #[test]
+    fn test_message_type_decodes_into_named_fields() {
+        let Decoded::Value(value) = telemetry().decode(PAYLOAD).unwrap() else {
+            panic!("expected a value");
+        };
+
+        assert_eq!(
+            value.to_string(),
+            r#"{device: "d1", temperature: 21.5, status: "OK", samples: [1, 300], 9: 7}"#
+        );
+    }
+
+    #[test]
+    fn test_raw_dump_uses_field_numbers() {
+        assert!(RawProtobuf.detect(PAYLOAD));
+        let Decoded::Value(value) = RawProtobuf.decode(PAYLOAD).unwrap() else {
+            panic!("expected a value");
+        };
+
+        assert_eq!(
+            value.to_string(),
+            format!(
+                r#"{{1: "d1", 2: {}, 3: 1, 4: <3 bytes 01ac02>, 9: 7}}"#,
+                21.5f64.to_bits()
+            )
+        );
+
+        assert!(!RawProtobuf.detect(b"hello"));
+        assert!(!RawProtobuf.detect(&[0x0a, 0x05, 0x01]));
+    }
+}
@@ -0,0 +1,222 @@
+//! Sparkplug B payloads.
+//! The subset of the Sparkplug B protobuf schema needed to read metrics: datasets,
+//! templates, metadata and properties are skipped.
+
+use std::collections::HashMap;
+
+use prost::Message;
+
+use super::{Decoded, PayloadDecoder, Value};
+
+/// Name of the Sparkplug B decoder.
+pub const SPARKPLUG: &str = "sparkplug";
+
+/// Topic namespace of Sparkplug B.
+pub const NAMESPACE: &str = "spBv1.0";
+
+/// Message types carrying a protobuf payload.
+pub const MESSAGE_TYPES: [&str; 8] = [
+    "NBIRTH", "NDEATH", "DBIRTH", "DDEATH", "NDATA", "DDATA", "NCMD", "DCMD",
+];
+
+/// Sparkplug B `Payload` message.
+#[derive(Clone, PartialEq, Message)]
+pub struct Payload {
+    #[prost(uint64, optional, tag = "1")]
+    pub timestamp: Option<u64>,
+    #[prost(message, repeated, tag = "2")]
+    pub metrics: Vec<Metric>,
+    #[prost(uint64, optional, tag = "3")]
+    pub seq: Option<u64>,
+    #[prost(string, optional, tag = "4")]
+    pub uuid: Option<String>,
+    #[prost(bytes = "vec", optional, tag = "5")]
+    pub body: Option<Vec<u8>>,
+}
+
+/// Sparkplug B `Payload.Metric` message.
+#[derive(Clone, PartialEq, Message)]
+pub struct Metric {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(uint64, optional, tag = "2")]
+    pub alias: Option<u64>,
+    #[prost(uint64, optional, tag = "3")]
+    pub timestamp: Option<u64>,
+    #[prost(uint32, optional, tag = "4")]
+    pub datatype: Option<u32>,
+    #[prost(bool, optional, tag = "5")]
+    pub is_historical: Option<bool>,
+    #[prost(bool, optional, tag = "6")]
+    pub is_transient: Option<bool>,
+    #[prost(bool, optional, tag = "7")]
+    pub is_null: Option<bool>,
+    #[prost(oneof = "MetricValue", tags = "10, 11, 12, 13, 14, 15, 16")]
+    pub value: Option<MetricValue>,
+}
+
+/// Value of a metric. Dataset, template and extension values are not decoded.
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum MetricValue {
+    #[prost(uint32, tag = "10")]
+    IntValue(u32),
+    #[prost(uint64, tag = "11")]
+    LongValue(u64),
+    #[prost(float, tag = "12")]
+    FloatValue(f32),
+    #[prost(double, tag = "13")]
+    DoubleValue(f64),
+    #[prost(bool, tag = "14")]
+    BooleanValue(bool),
+    #[prost(string, tag = "15")]
+    StringValue(String),
+    #[prost(bytes, tag = "16")]
+    BytesValue(Vec<u8>),
+}
+
+/// Name and data type of a metric, announced in a BIRTH message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricDefinition {
+    pub name: String,
+    pub datatype: Option<u32>,
+}
+
+/// Metric definitions of an edge node or device by alias.
+pub type Aliases = HashMap<u64, MetricDefinition>;
+
+/// Decodes a Sparkplug B payload.
+pub fn decode_payload(payload: &[u8]) -> Result<Payload, String> {
+    Payload::decode(payload).map_err(|e| e.to_string())
+}
+
+impl Metric {
+    /// Name of the metric, looked up in `aliases` when the message only has the alias.
+    pub fn definition(&self, aliases: &Aliases) -> MetricDefinition {
+        let birth = self.alias.and_then(|alias| aliases.get(&alias));
+
+        MetricDefinition {
+            name: match (&self.name, birth, self.alias) {
+                (Some(name), _, _) => name.clone(),
+                (None, Some(birth), _) => birth.name.clone(),
+                (None, None, Some(alias)) => format!("alias {}", alias),
+                (None, None, None) => "(unnamed)".to_string(),
+            },
+            datatype: self.datatype.or(birth.and_then(|birth| birth.datatype)),
+        }
+    }
+
+    /// The value of the metric. Signed integers are stored as unsigned on the wire
+    /// and are converted back using the data type.
+    pub fn value(&self, datatype: Option<u32>) -> Value {
+        if self.is_null == Some(true) {
+            return Value::Null;
+        }
+
+        match (&self.value, datatype) {
+            (Some(MetricValue::IntValue(value)), Some(1)) => Value::Integer((*value as i8).into()),
+            (Some(MetricValue::IntValue(value)), Some(2)) => Value::Integer((*value as i16).into()),
+            (Some(MetricValue::IntValue(value)), Some(3)) => Value::Integer((*value as i32).into()),
+            (Some(MetricValue::IntValue(value)), _) => Value::Integer((*value).into()),
+            (Some(MetricValue::LongValue(value)), Some(4)) => {
+                Value::Integer((*value as i64).into())
+            }
+            (Some(MetricValue::LongValue(value)), _) => Value::Integer((*value).into()),
+            (Some(MetricValue::FloatValue(value)), _) => Value::Float((*value).into()),
+            (Some(MetricValue::DoubleValue(value)), _) => Value::Float(*value),
+            (Some(MetricValue::BooleanValue(value)), _) => Value::Bool(*value),
+            (Some(MetricValue::StringValue(text)), _) => Value::Text(text.clone()),
+            (Some(MetricValue::BytesValue(bytes)), _) => Value::Bytes(bytes.clone()),
+            (None, _) => Value::Null,
+        }
+    }
+}
+
+/// The payload as a tree, with the metrics keyed by their name.
+pub fn payload_value(payload: &Payload, aliases: &Aliases) -> Value {
+    let mut entries = Vec::new();
+
+    if let Some(timestamp) = payload.timestamp {
+        entries.push((Value::Text("timestamp".into()), Value::Integer(timestamp.into())));
+    }
+    if let Some(seq) = payload.seq {
+        entries.push((Value::Text("seq".into()), Value::Integer(seq.into())));
+    }
+
+    let metrics = payload
+        .metrics
+        .iter()
+        .map(|metric| {
+            let definition = metric.definition(aliases);
+            (Value::Text(definition.name), metric.value(definition.datatype))
+        })
+        .collect();
+    entries.push((Value::Text("metrics".into()), Value::Map(metrics)));
+
+    if let Some(uuid) = &payload.uuid {
+        entries.push((Value::Text("uuid".into()), Value::Text(uuid.clone())));
+    }
+    if let Some(body) = &payload.body {
+        entries.push((Value::Text("body".into()), Value::Bytes(body.clone())));
+    }
+
+    Value::Map(entries)
+}
+
+/// Decodes Sparkplug B payloads on their own. Metrics only sent by alias are named
+/// by the Sparkplug mode, which knows the BIRTH messages.
+pub struct Sparkplug;
+
+impl PayloadDecoder for Sparkplug {
+    fn name(&self) -> &str {
+        SPARKPLUG
+    }
+
+    fn detect(&self, _payload: &[u8]) -> bool {
+        false
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Decoded, String> {
+        let payload = decode_payload(payload)?;
+        Ok(Decoded::Value(payload_value(&payload, &Aliases::new())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_are_named_from_aliases() {
+        let payload = Payload {
+            seq: Some(4),
+            metrics: vec![
+                Metric {
+                    alias: Some(1),
+                    value: Some(MetricValue::IntValue(-5i32 as u32)),
+                    ..Default::default()
+                },
+                Metric {
+                    alias: Some(2),
+                    value: Some(MetricValue::BooleanValue(true)),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let bytes = payload.encode_to_vec();
+        let aliases = Aliases::from([(
+            1,
+            MetricDefinition {
+                name: "Temperature".into(),
+                datatype: Some(3),
+            },
+        )]);
+
+        let decoded = decode_payload(&bytes).unwrap();
+
+        assert_eq!(
+            payload_value(&decoded, &aliases).to_string(),
+            "{seq: 4, metrics: {Temperature: -5, alias 2: true}}"
+        );
+    }
+}
@@ -1,53 +1,142 @@
-///! mqtt-ranger: A terminal-based MQTT client with TUI interface.
-///! Connects to an MQTT broker, subscribes to topics,
-///! and displays incoming messages in a user-friendly terminal UI.
+//! mqtt-ranger: A terminal-based MQTT client with TUI interface.
+//! Connects to an MQTT broker, subscribes to topics,
+//! and displays incoming messages in a user-friendly terminal UI.
 
+use std::io::Stdout;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use clap::Parser;
+use ratatui::Terminal;
+use ratatui::prelude::CrosstermBackend;
 
 pub mod app;
+pub mod capture;
+pub mod cli;
+pub mod config;
+pub mod decoder;
 pub mod mqtt;
 pub mod tui;
+#[cfg(test)]
+mod test_util;
 
-use app::{TopicActivityMenuState};
-use crate::tui::config_form::ConfigFormScreen;
+use app::{FocusField, JobState, SparkplugState, TopicActivityMenuState};
+use crate::capture::{CaptureRecord, PublishProgress, Replay};
+use crate::cli::Cli;
+use crate::config::ConfigFile;
+use crate::mqtt::{BrokerError, ClientHandle, MQTTConfig};
+use crate::tui::capture_publish::CapturePublishScreen;
+use crate::tui::config_form::{self, ConfigFormScreen};
 use crate::tui::splash::SplashScreen;
 use crate::tui::Screen;
 use crate::tui::topic_activity::TopicActivityScreen;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let topic_activity_menu_state = Arc::new(Mutex::new(TopicActivityMenuState::new()));
+    let cli = Cli::parse();
 
-    let mut terminal = tui::init_terminal()?;
+    let config_file = match ConfigFile::load(cli.config.as_deref()) {
+        Ok(config_file) => config_file,
+        Err(e) => {
+            eprintln!("Config file error: {}", e);
+            return Ok(());
+        }
+    };
 
-    let mut splash_screen = SplashScreen::new(&mut terminal);
-    splash_screen.run()?;
-    
-    let mut config_screen = ConfigFormScreen::new(&mut terminal);
-    if let Err(e) = config_screen.run() {
-        let _ = tui::restore_terminal(&mut terminal);
-        eprintln!("Config form cancelled: {}", e);
-        return Ok(());
+    let mut menu_state = TopicActivityMenuState::new();
+    menu_state.retention = cli.retention_policy(&config_file);
+    menu_state.decoders = match cli.decoders(&config_file) {
+        Ok(decoders) => decoders,
+        Err(e) => {
+            eprintln!("Decoder error: {}", e);
+            return Ok(());
+        }
+    };
+    if cli.sparkplug(&config_file) {
+        menu_state.sparkplug = Some(SparkplugState::default());
     }
-
-    let config = match config_screen.into_config() {
-        Some(cfg) => cfg,
-        None => {
-            let _ = tui::restore_terminal(&mut terminal);
-            eprintln!("No config produced");
+    match cli.history_path(&config_file) {
+        Ok(Some(path)) => {
+            if let Err(e) = menu_state.open_history(&path) {
+                eprintln!("History error: {}", e);
+                return Ok(());
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("History error: {}", e);
             return Ok(());
         }
+    }
+    menu_state.record_settings = cli.record_settings();
+    if cli.record.is_some()
+        && let Err(e) = menu_state.start_recording()
+    {
+        eprintln!("Recording error: {}", e);
+        return Ok(());
+    }
+    for settings in &config_file.publish_jobs {
+        let mut state = match settings.job() {
+            Ok(job) => JobState::new(job),
+            Err(e) => {
+                eprintln!("Publish job error: {}", e);
+                return Ok(());
+            }
+        };
+        if settings.start {
+            state.start(Instant::now());
+        }
+        menu_state.publish_jobs.push(state);
+    }
+
+    if let Some(path) = &cli.replay {
+        let records = match capture::replay::read_capture(path) {
+            Ok(records) => records,
+            Err(e) => {
+                eprintln!("Replay error: {}", e);
+                return Ok(());
+            }
+        };
+        menu_state.replay = Some(Replay::new(path, records, cli.replay_speed));
+    }
+    let capture_to_publish = match &cli.publish_capture {
+        Some(path) => match capture::replay::read_capture(path) {
+            Ok(records) => Some((path.clone(), records)),
+            Err(e) => {
+                eprintln!("Capture error: {}", e);
+                return Ok(());
+            }
+        },
+        None => None,
     };
+    let replaying = menu_state.replay.is_some();
+    let topic_activity_menu_state = Arc::new(Mutex::new(menu_state));
 
-    if let Err(e) = mqtt::run(topic_activity_menu_state.clone(), config).await {
-        let _ = tui::restore_terminal(&mut terminal);
+    let mut terminal = tui::init_terminal()?;
 
-        eprintln!("MQTT Error: {}", e);
+    if cli.host.is_none() {
+        let mut splash_screen = SplashScreen::new(&mut terminal);
+        splash_screen.run()?;
+    }
 
+    if let Some((path, records)) = capture_to_publish {
+        publish_capture(&mut terminal, &cli, &path, records).await;
         return Ok(());
     }
 
-    let mut topic_activity_screen = TopicActivityScreen::new(&mut terminal, topic_activity_menu_state);
+    let client = if replaying {
+        capture::replay::spawn_replay(topic_activity_menu_state.clone());
+        None
+    } else {
+        match connect(&mut terminal, &cli, &topic_activity_menu_state).await {
+            Some(client) => Some(client),
+            None => return Ok(()),
+        }
+    };
+
+    let mut topic_activity_screen =
+        TopicActivityScreen::new(&mut terminal, topic_activity_menu_state, client);
     let res = topic_activity_screen.run();
 
     let _ = tui::restore_terminal(&mut terminal);
@@ -58,3 +147,136 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// The connection given on the command line once the broker accepted it, or else the one
+/// entered in the connection form. The form opens prefilled, on the password, when the
+/// command line lacks the password or the broker rejects the credentials. Returns `None`
+/// after restoring the terminal and reporting why when the form is cancelled or the broker
+/// cannot be used.
+async fn ask_config(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    cli: &Cli,
+) -> Option<MQTTConfig> {
+    let mut state = cli.form_state();
+
+    if let Some(config) = cli.connection_config() {
+        let config = match config {
+            Ok(config) => config,
+            Err(e) => {
+                let _ = tui::restore_terminal(terminal);
+                eprintln!("Invalid connection options: {}", e);
+                return None;
+            }
+        };
+
+        let _ = config_form::draw_connecting(terminal, &config.host, config.port);
+        let probe_config = config.clone();
+        let probe =
+            tokio::task::spawn_blocking(move || mqtt::validate_broker(&probe_config, 5)).await;
+
+        match probe {
+            Ok(Ok(())) => return Some(config),
+            Ok(Err(e @ (BrokerError::BadCredentials | BrokerError::NotAuthorized))) => {
+                state.focus = FocusField::Password;
+                state.error = Some(e.to_string());
+            }
+            Ok(Err(e)) => {
+                let _ = tui::restore_terminal(terminal);
+                eprintln!("MQTT Error: {}", e);
+                return None;
+            }
+            Err(e) => {
+                let _ = tui::restore_terminal(terminal);
+                eprintln!("MQTT Error: {}", e);
+                return None;
+            }
+        }
+    }
+
+    let mut config_screen = ConfigFormScreen::new(terminal, state);
+    if let Err(e) = config_screen.run() {
+        let _ = tui::restore_terminal(terminal);
+        eprintln!("Config form cancelled: {}", e);
+        return None;
+    }
+
+    let config = config_screen.into_config();
+    if config.is_none() {
+        let _ = tui::restore_terminal(terminal);
+        eprintln!("No config produced");
+    }
+
+    config
+}
+
+/// Shows the connection form and connects to the broker it describes. Returns `None`
+/// after restoring the terminal and reporting why when the form is cancelled or the
+/// connection fails.
+async fn connect(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    cli: &Cli,
+    menu_state: &Arc<Mutex<TopicActivityMenuState>>,
+) -> Option<ClientHandle> {
+    let mut config = ask_config(terminal, cli).await?;
+
+    config.reconnect = cli.reconnect_policy();
+    menu_state.lock().unwrap().protocol = config.protocol;
+
+    match mqtt::run(menu_state.clone(), config).await {
+        Ok(client) => Some(client),
+        Err(e) => {
+            let _ = tui::restore_terminal(terminal);
+
+            eprintln!("MQTT Error: {}", e);
+
+            None
+        }
+    }
+}
+
+/// Publishes a capture file to the broker from the connection form while showing the
+/// progress, then prints a summary once the terminal is restored.
+async fn publish_capture(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    cli: &Cli,
+    path: &Path,
+    records: Vec<CaptureRecord>,
+) {
+    let Some(config) = ask_config(terminal, cli).await else {
+        return;
+    };
+
+    let progress = Arc::new(Mutex::new(PublishProgress::new(records.len())));
+    let task = tokio::spawn(capture::publisher::publish_capture(
+        config,
+        records,
+        cli.rewrite_prefix.clone(),
+        cli.publish_speed,
+        progress.clone(),
+    ));
+
+    let res = CapturePublishScreen::new(terminal, path.to_path_buf(), progress.clone()).run();
+    task.abort();
+
+    let _ = tui::restore_terminal(terminal);
+
+    if let Err(e) = res {
+        eprintln!("Application error: {}", e);
+    }
+
+    let progress = progress.lock().unwrap();
+    println!(
+        "Sent {} of {} messages from {}: {} delivered, {} failed",
+        progress.queued,
+        progress.total,
+        path.display(),
+        progress.delivered,
+        progress.failed
+    );
+    if let Some(error) = &progress.last_error {
+        println!("Last error: {}", error);
+    }
+    if let Some(error) = &progress.aborted {
+        println!("Connection lost: {}", error);
+    }
+}
@@ -0,0 +1,465 @@
+//! Protocol-independent wrapper around the rumqttc v3.1.1 and v5 clients.
+//! The rest of the application talks to `ClientHandle` and `MqttEventLoop`
+//! and never needs to know which protocol version is in use.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use rumqttc::{TlsConfiguration, Transport, v5};
+
+use super::{BrokerError, MQTTConfig, PublishRequest, tls};
+use crate::app::MessageProperties;
+
+/// Client id used by the long-lived connection.
+const CLIENT_ID: &str = "mqtt-ranger";
+const KEEP_ALIVE: Duration = Duration::from_secs(5);
+
+/// MQTT protocol version used to talk to the broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolVersion {
+    #[default]
+    V311,
+    V5,
+}
+
+impl ProtocolVersion {
+    /// Human readable name shown in the UI.
+    pub fn label(self) -> &'static str {
+        match self {
+            ProtocolVersion::V311 => "MQTT 3.1.1",
+            ProtocolVersion::V5 => "MQTT 5",
+        }
+    }
+}
+
+impl std::str::FromStr for ProtocolVersion {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "3" | "3.1.1" | "v3" | "v311" => Ok(ProtocolVersion::V311),
+            "5" | "v5" => Ok(ProtocolVersion::V5),
+            _ => Err(format!("Unknown protocol version '{}', expected 3.1.1 or 5", text)),
+        }
+    }
+}
+
+/// Handle used to send requests to the broker. Cheap to clone.
+#[derive(Clone)]
+pub enum ClientHandle {
+    V311(rumqttc::AsyncClient),
+    V5(v5::AsyncClient),
+}
+
+/// The event loop driving the connection, for either protocol version.
+pub enum MqttEventLoop {
+    V311(Box<rumqttc::EventLoop>),
+    V5(Box<v5::EventLoop>),
+}
+
+/// Publish packet received from the broker, independent of the protocol version.
+pub struct ReceivedPublish {
+    pub topic: String,
+    pub payload: Bytes,
+    /// MQTT v5 properties. Always `None` on v3.1.1 connections.
+    pub properties: Option<MessageProperties>,
+    pub qos: u8,
+    pub retain: bool,
+}
+
+/// Outcome of a single filter in a SUBACK: the granted QoS, or why it was rejected.
+pub type SubscribeResult = Result<u8, String>;
+
+/// Notifications produced by `MqttEventLoop::poll`.
+pub enum Notification {
+    /// The broker accepted the connection (initial or after a reconnect).
+    ConnAck,
+    Publish(ReceivedPublish),
+    /// A SUBSCRIBE packet left the client with this packet id.
+    SubscribeSent(u16),
+    /// The broker acknowledged a SUBSCRIBE, one result per filter.
+    SubAck {
+        pkid: u16,
+        results: Vec<SubscribeResult>,
+    },
+    /// An UNSUBSCRIBE packet left the client with this packet id.
+    UnsubscribeSent(u16),
+    /// The broker acknowledged an UNSUBSCRIBE. `error` is set when a v5 broker refused it.
+    UnsubAck { pkid: u16, error: Option<String> },
+    /// A PUBLISH packet left the client with this packet id (0 at QoS 0).
+    PublishSent(u16),
+    /// The broker acknowledged a QoS 1 PUBLISH. `error` is set when a v5 broker refused it.
+    PubAck { pkid: u16, error: Option<String> },
+    /// The broker received a QoS 2 PUBLISH. `error` is set when a v5 broker refused it.
+    PubRec { pkid: u16, error: Option<String> },
+    /// The broker completed a QoS 2 PUBLISH.
+    PubComp { pkid: u16, error: Option<String> },
+    /// Any other packet or outgoing event the application does not handle yet.
+    Other,
+}
+
+/// Builds the address and the transport (plain TCP or TLS) shared by both protocol
+/// versions. With a TLS server name, rumqttc connects to the relay sending it as SNI.
+fn endpoint(config: &MQTTConfig) -> Result<(String, u16, Transport), BrokerError> {
+    match &config.tls {
+        Some(tls_settings) if tls_settings.server_name.is_some() => {
+            let relay =
+                tls::sni_relay(&config.host, config.port, tls_settings).map_err(BrokerError::Tls)?;
+            Ok((relay.ip().to_string(), relay.port(), Transport::tcp()))
+        }
+        Some(tls_settings) => {
+            let client_config = tls::client_config(tls_settings).map_err(BrokerError::Tls)?;
+            let transport = Transport::tls_with_config(TlsConfiguration::from(client_config));
+            Ok((config.host.clone(), config.port, transport))
+        }
+        None => Ok((config.host.clone(), config.port, Transport::tcp())),
+    }
+}
+
+/// Builds the rumqttc v3.1.1 options for the given configuration.
+pub(crate) fn mqtt_options_v311(
+    config: &MQTTConfig,
+    client_id: String,
+) -> Result<rumqttc::MqttOptions, BrokerError> {
+    let (host, port, transport) = endpoint(config)?;
+    let mut mqttoptions = rumqttc::MqttOptions::new(client_id, host, port);
+    mqttoptions.set_keep_alive(KEEP_ALIVE);
+    mqttoptions.set_transport(transport);
+
+    if let Some(username) = &config.username {
+        mqttoptions.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+
+    Ok(mqttoptions)
+}
+
+/// Builds the rumqttc v5 options for the given configuration.
+pub(crate) fn mqtt_options_v5(
+    config: &MQTTConfig,
+    client_id: String,
+) -> Result<v5::MqttOptions, BrokerError> {
+    let (host, port, transport) = endpoint(config)?;
+    let mut mqttoptions = v5::MqttOptions::new(client_id, host, port);
+    mqttoptions.set_keep_alive(KEEP_ALIVE);
+    mqttoptions.set_transport(transport);
+
+    if let Some(username) = &config.username {
+        mqttoptions.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+
+    Ok(mqttoptions)
+}
+
+/// Creates the client handle and event loop for the configured protocol version.
+pub fn connect(config: &MQTTConfig) -> Result<(ClientHandle, MqttEventLoop), BrokerError> {
+    match config.protocol {
+        ProtocolVersion::V311 => {
+            let options = mqtt_options_v311(config, CLIENT_ID.into())?;
+            let (client, event_loop) = rumqttc::AsyncClient::new(options, 10);
+            Ok((ClientHandle::V311(client), MqttEventLoop::V311(Box::new(event_loop))))
+        }
+        ProtocolVersion::V5 => {
+            let options = mqtt_options_v5(config, CLIENT_ID.into())?;
+            let (client, event_loop) = v5::AsyncClient::new(options, 10);
+            Ok((ClientHandle::V5(client), MqttEventLoop::V5(Box::new(event_loop))))
+        }
+    }
+}
+
+impl ClientHandle {
+    /// Subscribes to `filter` with the given QoS level (0, 1 or 2).
+    pub async fn subscribe(&self, filter: &str, qos: u8) -> Result<(), String> {
+        match self {
+            ClientHandle::V311(client) => client
+                .subscribe(filter, rumqttc::qos(qos).map_err(|e| e.to_string())?)
+                .await
+                .map_err(|e| e.to_string()),
+            ClientHandle::V5(client) => client
+                .subscribe(filter, v5_qos(qos)?)
+                .await
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Queues a SUBSCRIBE without waiting. Meant for the UI thread, which has no runtime.
+    pub fn try_subscribe(&self, filter: &str, qos: u8) -> Result<(), String> {
+        match self {
+            ClientHandle::V311(client) => client
+                .try_subscribe(filter, rumqttc::qos(qos).map_err(|e| e.to_string())?)
+                .map_err(|e| e.to_string()),
+            ClientHandle::V5(client) => client
+                .try_subscribe(filter, v5_qos(qos)?)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Queues an UNSUBSCRIBE for `filter` without waiting.
+    pub fn try_unsubscribe(&self, filter: &str) -> Result<(), String> {
+        match self {
+            ClientHandle::V311(client) => client.try_unsubscribe(filter).map_err(|e| e.to_string()),
+            ClientHandle::V5(client) => client.try_unsubscribe(filter).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Queues a PUBLISH, waiting for room in the request queue. The properties are only
+    /// sent over MQTT v5.
+    pub async fn publish(&self, request: &PublishRequest) -> Result<(), String> {
+        match self {
+            ClientHandle::V311(client) => client
+                .publish(
+                    request.topic.as_str(),
+                    rumqttc::qos(request.qos).map_err(|e| e.to_string())?,
+                    request.retain,
+                    request.payload.to_vec(),
+                )
+                .await
+                .map_err(|e| e.to_string()),
+            ClientHandle::V5(client) => client
+                .publish_with_properties(
+                    request.topic.as_str(),
+                    v5_qos(request.qos)?,
+                    request.retain,
+                    request.payload.clone(),
+                    request
+                        .properties
+                        .as_ref()
+                        .map(v5::mqttbytes::v5::PublishProperties::from)
+                        .unwrap_or_default(),
+                )
+                .await
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Queues a PUBLISH without waiting. The properties are only sent over MQTT v5.
+    pub fn try_publish(&self, request: &PublishRequest) -> Result<(), String> {
+        match self {
+            ClientHandle::V311(client) => client
+                .try_publish(
+                    request.topic.as_str(),
+                    rumqttc::qos(request.qos).map_err(|e| e.to_string())?,
+                    request.retain,
+                    request.payload.to_vec(),
+                )
+                .map_err(|e| e.to_string()),
+            ClientHandle::V5(client) => client
+                .try_publish_with_properties(
+                    request.topic.as_str(),
+                    v5_qos(request.qos)?,
+                    request.retain,
+                    request.payload.clone(),
+                    request
+                        .properties
+                        .as_ref()
+                        .map(v5::mqttbytes::v5::PublishProperties::from)
+                        .unwrap_or_default(),
+                )
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl MqttEventLoop {
+    /// Polls the underlying event loop for the next notification.
+    pub async fn poll(&mut self) -> Result<Notification, String> {
+        match self {
+            MqttEventLoop::V311(event_loop) => {
+                let event = event_loop.poll().await.map_err(|e| e.to_string())?;
+
+                Ok(match event {
+                    rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_)) => Notification::ConnAck,
+                    rumqttc::Event::Incoming(rumqttc::Packet::Publish(publish)) => {
+                        Notification::Publish(ReceivedPublish {
+                            topic: publish.topic,
+                            payload: publish.payload,
+                            properties: None,
+                            qos: publish.qos as u8,
+                            retain: publish.retain,
+                        })
+                    }
+                    rumqttc::Event::Outgoing(rumqttc::Outgoing::Subscribe(pkid)) => {
+                        Notification::SubscribeSent(pkid)
+                    }
+                    rumqttc::Event::Incoming(rumqttc::Packet::SubAck(suback)) => {
+                        Notification::SubAck {
+                            pkid: suback.pkid,
+                            results: suback
+                                .return_codes
+                                .into_iter()
+                                .map(|code| match code {
+                                    rumqttc::SubscribeReasonCode::Success(qos) => Ok(qos as u8),
+                                    rumqttc::SubscribeReasonCode::Failure => {
+                                        Err("rejected (0x80)".into())
+                                    }
+                                })
+                                .collect(),
+                        }
+                    }
+                    rumqttc::Event::Outgoing(rumqttc::Outgoing::Unsubscribe(pkid)) => {
+                        Notification::UnsubscribeSent(pkid)
+                    }
+                    rumqttc::Event::Incoming(rumqttc::Packet::UnsubAck(unsuback)) => {
+                        Notification::UnsubAck {
+                            pkid: unsuback.pkid,
+                            error: None,
+                        }
+                    }
+                    rumqttc::Event::Outgoing(rumqttc::Outgoing::Publish(pkid)) => {
+                        Notification::PublishSent(pkid)
+                    }
+                    rumqttc::Event::Incoming(rumqttc::Packet::PubAck(puback)) => {
+                        Notification::PubAck {
+                            pkid: puback.pkid,
+                            error: None,
+                        }
+                    }
+                    rumqttc::Event::Incoming(rumqttc::Packet::PubRec(pubrec)) => {
+                        Notification::PubRec {
+                            pkid: pubrec.pkid,
+                            error: None,
+                        }
+                    }
+                    rumqttc::Event::Incoming(rumqttc::Packet::PubComp(pubcomp)) => {
+                        Notification::PubComp {
+                            pkid: pubcomp.pkid,
+                            error: None,
+                        }
+                    }
+                    _ => Notification::Other,
+                })
+            }
+            MqttEventLoop::V5(event_loop) => {
+                let event = event_loop.poll().await.map_err(|e| e.to_string())?;
+
+                Ok(match event {
+                    v5::Event::Incoming(v5::Incoming::ConnAck(_)) => Notification::ConnAck,
+                    v5::Event::Incoming(v5::Incoming::Publish(publish)) => {
+                        Notification::Publish(ReceivedPublish {
+                            topic: String::from_utf8_lossy(&publish.topic).to_string(),
+                            payload: publish.payload,
+                            properties: Some(
+                                publish
+                                    .properties
+                                    .map(MessageProperties::from)
+                                    .unwrap_or_default(),
+                            ),
+                            qos: publish.qos as u8,
+                            retain: publish.retain,
+                        })
+                    }
+                    v5::Event::Outgoing(rumqttc::Outgoing::Subscribe(pkid)) => {
+                        Notification::SubscribeSent(pkid)
+                    }
+                    v5::Event::Incoming(v5::Incoming::SubAck(suback)) => Notification::SubAck {
+                        pkid: suback.pkid,
+                        results: suback
+                            .return_codes
+                            .into_iter()
+                            .map(|code| match code {
+                                v5::mqttbytes::v5::SubscribeReasonCode::Success(qos) => {
+                                    Ok(qos as u8)
+                                }
+                                code => Err(format!("{:?}", code)),
+                            })
+                            .collect(),
+                    },
+                    v5::Event::Outgoing(rumqttc::Outgoing::Unsubscribe(pkid)) => {
+                        Notification::UnsubscribeSent(pkid)
+                    }
+                    v5::Event::Incoming(v5::Incoming::UnsubAck(unsuback)) => {
+                        use v5::mqttbytes::v5::UnsubAckReason;
+
+                        Notification::UnsubAck {
+                            pkid: unsuback.pkid,
+                            error: unsuback
+                                .reasons
+                                .into_iter()
+                                .find(|reason| {
+                                    !matches!(
+                                        reason,
+                                        UnsubAckReason::Success
+                                            | UnsubAckReason::NoSubscriptionExisted
+                                    )
+                                })
+                                .map(|reason| format!("{:?}", reason)),
+                        }
+                    }
+                    v5::Event::Outgoing(rumqttc::Outgoing::Publish(pkid)) => {
+                        Notification::PublishSent(pkid)
+                    }
+                    v5::Event::Incoming(v5::Incoming::PubAck(puback)) => {
+                        use v5::mqttbytes::v5::PubAckReason;
+
+                        Notification::PubAck {
+                            pkid: puback.pkid,
+                            error: (!matches!(
+                                puback.reason,
+                                PubAckReason::Success | PubAckReason::NoMatchingSubscribers
+                            ))
+                            .then(|| format!("{:?}", puback.reason)),
+                        }
+                    }
+                    v5::Event::Incoming(v5::Incoming::PubRec(pubrec)) => {
+                        use v5::mqttbytes::v5::PubRecReason;
+
+                        Notification::PubRec {
+                            pkid: pubrec.pkid,
+                            error: (!matches!(
+                                pubrec.reason,
+                                PubRecReason::Success | PubRecReason::NoMatchingSubscribers
+                            ))
+                            .then(|| format!("{:?}", pubrec.reason)),
+                        }
+                    }
+                    v5::Event::Incoming(v5::Incoming::PubComp(pubcomp)) => {
+                        use v5::mqttbytes::v5::PubCompReason;
+
+                        Notification::PubComp {
+                            pkid: pubcomp.pkid,
+                            error: (pubcomp.reason != PubCompReason::Success)
+                                .then(|| format!("{:?}", pubcomp.reason)),
+                        }
+                    }
+                    _ => Notification::Other,
+                })
+            }
+        }
+    }
+}
+
+/// Converts a numeric QoS level to the v5 QoS type.
+fn v5_qos(qos: u8) -> Result<v5::mqttbytes::QoS, String> {
+    v5::mqttbytes::qos(qos).ok_or_else(|| format!("Invalid QoS {}", qos))
+}
+
+impl From<v5::mqttbytes::v5::PublishProperties> for MessageProperties {
+    fn from(props: v5::mqttbytes::v5::PublishProperties) -> Self {
+        Self {
+            payload_format_indicator: props.payload_format_indicator,
+            message_expiry_interval: props.message_expiry_interval,
+            topic_alias: props.topic_alias,
+            response_topic: props.response_topic,
+            correlation_data: props.correlation_data.map(|data| data.to_vec()),
+            user_properties: props.user_properties,
+            subscription_identifiers: props.subscription_identifiers,
+            content_type: props.content_type,
+        }
+    }
+}
+
+impl From<&MessageProperties> for v5::mqttbytes::v5::PublishProperties {
+    /// Properties set by a publisher. The topic alias and subscription identifiers of a
+    /// received message only make sense on the connection it arrived on and are dropped.
+    fn from(props: &MessageProperties) -> Self {
+        Self {
+            payload_format_indicator: props.payload_format_indicator,
+            message_expiry_interval: props.message_expiry_interval,
+            topic_alias: None,
+            response_topic: props.response_topic.clone(),
+            correlation_data: props.correlation_data.clone().map(Bytes::from),
+            user_properties: props.user_properties.clone(),
+            subscription_identifiers: Vec::new(),
+            content_type: props.content_type.clone(),
+        }
+    }
+}
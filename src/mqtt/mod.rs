@@ -0,0 +1,631 @@
+//! MQTT client module for connecting and handling MQTT events.
+//! This module provides functionality to connect to an MQTT broker
+//! and process incoming messages.
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use time::{OffsetDateTime, UtcOffset, format_description::parse};
+use tokio::sync::mpsc;
+
+use crate::app::{self, ConnectionState, MessageProperties, TopicActivityMenuState};
+
+pub mod client;
+pub mod probe;
+pub mod tls;
+
+pub use client::{ClientHandle, MqttEventLoop, Notification, ProtocolVersion, SubscribeResult};
+pub use probe::{BrokerError, validate_broker};
+pub use tls::TlsSettings;
+
+const MQTT_TIMESTAMP_FORMAT: &str = "[year]-[month]-[day] [hour]:[minute]:[second]";
+
+/// How often the publish jobs are checked for due messages.
+const PUBLISH_JOB_TICK: Duration = Duration::from_millis(10);
+
+/// Represents an MQTT event containing a topic and its associated payload.
+#[derive(Debug)]
+pub struct MQTTEvent {
+    pub(crate) topic: String,
+    /// Payload bytes exactly as received.
+    pub(crate) payload: Bytes,
+    pub(crate) timestamp: time::OffsetDateTime,
+    /// MQTT v5 publish properties, `None` on v3.1.1 connections.
+    pub(crate) properties: Option<MessageProperties>,
+    pub(crate) qos: u8,
+    pub(crate) retain: bool,
+}
+
+/// Everything the network task reports to the menu updater.
+#[derive(Debug)]
+pub(crate) enum ClientEvent {
+    /// The broker accepted the connection. `resumed` is set after a reconnect.
+    Connected { resumed: bool },
+    /// The connection failed or dropped; attempt number `attempt` starts after `retry_in`.
+    ConnectionLost {
+        attempt: u32,
+        error: String,
+        retry_in: Duration,
+    },
+    /// A message was published on a subscribed topic.
+    Message(MQTTEvent),
+    /// A SUBSCRIBE packet was sent with the given packet id.
+    SubscribeSent(u16),
+    /// The broker answered a SUBSCRIBE packet.
+    SubAck {
+        pkid: u16,
+        results: Vec<SubscribeResult>,
+    },
+    /// An UNSUBSCRIBE packet was sent with the given packet id.
+    UnsubscribeSent(u16),
+    /// The broker answered an UNSUBSCRIBE packet.
+    UnsubAck { pkid: u16, error: Option<String> },
+    /// A PUBLISH packet was sent with the given packet id (0 at QoS 0).
+    PublishSent(u16),
+    /// The broker acknowledged a QoS 1 PUBLISH.
+    PubAck { pkid: u16, error: Option<String> },
+    /// The broker received a QoS 2 PUBLISH.
+    PubRec { pkid: u16, error: Option<String> },
+    /// The broker completed a QoS 2 PUBLISH.
+    PubComp { pkid: u16, error: Option<String> },
+}
+
+/// Wrapper struct that represents an MQTT client with its associated event loop.
+pub struct MQTTClient {
+    pub(crate) client: ClientHandle,
+    pub(crate) event_loop: MqttEventLoop,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MQTTConfig {
+    pub host: String,
+    pub port: u16,
+    /// Username sent in the CONNECT packet. Credentials are only sent when this is set.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Connect over TLS when set, plain TCP otherwise.
+    pub tls: Option<TlsSettings>,
+    pub protocol: ProtocolVersion,
+    /// Topic filters to subscribe to after connecting.
+    pub subscriptions: Vec<SubscriptionFilter>,
+    pub reconnect: ReconnectPolicy,
+}
+
+/// Exponential backoff used to re-establish a dropped connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first reconnection attempt. Doubles after every failed attempt.
+    pub initial_delay: Duration,
+    /// Upper bound for the delay between attempts.
+    pub max_delay: Duration,
+}
+
+impl ReconnectPolicy {
+    /// Delay to wait before reconnection attempt number `attempt` (starting at 1).
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A topic filter together with the QoS requested for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriptionFilter {
+    pub filter: String,
+    pub qos: u8,
+}
+
+impl SubscriptionFilter {
+    /// The `#` filter at QoS 0 used when no filters are configured.
+    pub fn all_topics() -> Self {
+        Self {
+            filter: "#".into(),
+            qos: 0,
+        }
+    }
+
+    /// Parses a comma separated list of `filter[:qos]` entries.
+    /// An empty list subscribes to every topic.
+    pub fn parse_list(text: &str) -> Result<Vec<Self>, String> {
+        let filters = text
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<Self>, String>>()?;
+
+        if filters.is_empty() {
+            Ok(vec![Self::all_topics()])
+        } else {
+            Ok(filters)
+        }
+    }
+}
+
+impl FromStr for SubscriptionFilter {
+    type Err = String;
+
+    /// Parses `filter[:qos]`. The suffix is only treated as QoS when it is 0, 1 or 2,
+    /// so filters containing colons (e.g. `urn:dev/#`) still work.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (filter, qos) = match s.rsplit_once(':') {
+            Some((filter, qos @ ("0" | "1" | "2"))) => (filter, qos.parse().unwrap_or(0)),
+            _ => (s, 0),
+        };
+
+        if !rumqttc::valid_filter(filter) {
+            return Err(format!("Invalid topic filter '{}'", filter));
+        }
+
+        Ok(Self {
+            filter: filter.to_string(),
+            qos,
+        })
+    }
+}
+
+impl fmt::Display for SubscriptionFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.filter, self.qos)
+    }
+}
+
+/// A message to publish.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublishRequest {
+    pub topic: String,
+    pub payload: Bytes,
+    pub qos: u8,
+    pub retain: bool,
+    /// MQTT v5 publish properties. Ignored on v3.1.1 connections.
+    pub properties: Option<MessageProperties>,
+}
+
+/// Connects to an MQTT broker and returns an MQTTClient instance.
+pub fn create_mqtt_client(config: &MQTTConfig) -> Result<MQTTClient, BrokerError> {
+    let (client, event_loop) = client::connect(config)?;
+
+    Ok(MQTTClient { client, event_loop })
+}
+
+/// Runs the MQTT client, subscribes to the configured topic filters, and processes
+/// incoming messages. Returns the client handle so the UI can change subscriptions
+/// while the event loop keeps running in the background.
+pub async fn run(
+    menu_state: Arc<Mutex<app::TopicActivityMenuState>>,
+    config: MQTTConfig,
+) -> Result<ClientHandle, Box<dyn std::error::Error>> {
+    let MQTTClient { client, event_loop } = create_mqtt_client(&config)?;
+
+    subscribe_to_filters(&client, &menu_state, &config.subscriptions).await?;
+
+    let (tx, rx) = mpsc::channel::<ClientEvent>(100);
+
+    spawn_message_handler(event_loop, config.reconnect, tx);
+
+    spawn_menu_updater(Arc::clone(&menu_state), client.clone(), rx);
+
+    spawn_publish_scheduler(menu_state, client.clone());
+
+    Ok(client)
+}
+
+/// Sends one SUBSCRIBE per filter, registering each one as pending in the menu state
+/// so its SUBACK can be matched later.
+async fn subscribe_to_filters(
+    client: &ClientHandle,
+    menu_state: &Arc<Mutex<TopicActivityMenuState>>,
+    filters: &[SubscriptionFilter],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for filter in filters {
+        menu_state
+            .lock()
+            .unwrap()
+            .add_pending_subscription(&filter.filter, filter.qos);
+
+        client.subscribe(&filter.filter, filter.qos).await?;
+    }
+
+    Ok(())
+}
+
+/// Spawn a task to handle incoming MQTT messages.
+fn spawn_message_handler(
+    event_loop: MqttEventLoop,
+    reconnect: ReconnectPolicy,
+    tx: mpsc::Sender<ClientEvent>,
+) {
+    tokio::spawn(async move { handle_incoming_messages(event_loop, reconnect, tx).await });
+}
+
+/// Handles incoming MQTT messages and sends them through a channel.
+/// Connection errors are reported and the event loop is polled again after the backoff
+/// delay, which makes rumqttc reconnect. Stops once the receiving side is gone.
+async fn handle_incoming_messages(
+    mut event_loop: MqttEventLoop,
+    reconnect: ReconnectPolicy,
+    tx: mpsc::Sender<ClientEvent>,
+) {
+    let mut attempt = 0;
+
+    loop {
+        let notification = match event_loop.poll().await {
+            Ok(notification) => notification,
+            Err(error) => {
+                attempt += 1;
+                let retry_in = reconnect.delay(attempt);
+                let event = ClientEvent::ConnectionLost {
+                    attempt,
+                    error,
+                    retry_in,
+                };
+
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+
+                tokio::time::sleep(retry_in).await;
+                continue;
+            }
+        };
+
+        let event = match notification {
+            Notification::ConnAck => {
+                let resumed = attempt > 0;
+                attempt = 0;
+
+                ClientEvent::Connected { resumed }
+            }
+            Notification::Publish(publish) => {
+                let topic = publish.topic;
+                let timestamp = OffsetDateTime::now_local().unwrap_or(
+                    OffsetDateTime::now_utc()
+                        .to_offset(UtcOffset::current_local_offset().unwrap()),
+                );
+
+                ClientEvent::Message(MQTTEvent {
+                    topic,
+                    payload: publish.payload,
+                    timestamp,
+                    properties: publish.properties,
+                    qos: publish.qos,
+                    retain: publish.retain,
+                })
+            }
+            Notification::SubscribeSent(pkid) => ClientEvent::SubscribeSent(pkid),
+            Notification::SubAck { pkid, results } => ClientEvent::SubAck { pkid, results },
+            Notification::UnsubscribeSent(pkid) => ClientEvent::UnsubscribeSent(pkid),
+            Notification::UnsubAck { pkid, error } => ClientEvent::UnsubAck { pkid, error },
+            Notification::PublishSent(pkid) => ClientEvent::PublishSent(pkid),
+            Notification::PubAck { pkid, error } => ClientEvent::PubAck { pkid, error },
+            Notification::PubRec { pkid, error } => ClientEvent::PubRec { pkid, error },
+            Notification::PubComp { pkid, error } => ClientEvent::PubComp { pkid, error },
+            Notification::Other => continue,
+        };
+
+        if tx.send(event).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Spawn a task to update the application state with incoming MQTT messages.
+fn spawn_menu_updater(
+    app: Arc<Mutex<app::TopicActivityMenuState>>,
+    client: ClientHandle,
+    rx: mpsc::Receiver<ClientEvent>,
+) {
+    tokio::spawn(async move {
+        update_topic_menu_state(app, client, rx).await;
+    });
+}
+
+/// Spawn a task publishing the due messages of the running publish jobs. The menu state
+/// stays locked while a message is queued, so its PUBLISH packet is matched to the right
+/// publication.
+fn spawn_publish_scheduler(menu_state: Arc<Mutex<TopicActivityMenuState>>, client: ClientHandle) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PUBLISH_JOB_TICK);
+
+        loop {
+            ticker.tick().await;
+
+            let Ok(mut menu_lock) = menu_state.lock() else {
+                return;
+            };
+            menu_lock.run_due_jobs(Instant::now(), OffsetDateTime::now_utc(), |request| {
+                client.try_publish(request)
+            });
+        }
+    });
+}
+
+/// Updates the application state with incoming MQTT messages received through a channel.
+async fn update_topic_menu_state(
+    menu_state: Arc<Mutex<app::TopicActivityMenuState>>,
+    client: ClientHandle,
+    mut rx: mpsc::Receiver<ClientEvent>,
+) {
+    while let Some(client_event) = rx.recv().await {
+        match client_event {
+            ClientEvent::Connected { resumed } => {
+                let filters = {
+                    let mut menu_lock = menu_state.lock().unwrap();
+                    menu_lock.connection = ConnectionState::Connected;
+
+                    if resumed {
+                        menu_lock.fail_unsent_publications();
+                        menu_lock.prepare_resubscribe()
+                    } else {
+                        Vec::new()
+                    }
+                };
+
+                resubscribe(&client, &menu_state, filters).await;
+            }
+            ClientEvent::ConnectionLost {
+                attempt,
+                error,
+                retry_in,
+            } => {
+                menu_state.lock().unwrap().connection = ConnectionState::Reconnecting {
+                    attempt,
+                    last_error: error,
+                    retry_in,
+                };
+            }
+            ClientEvent::Message(mqtt_event) => {
+                menu_state.lock().unwrap().record(&mqtt_event);
+                push_message_into_topic(&menu_state, mqtt_event);
+            }
+            ClientEvent::SubscribeSent(pkid) => {
+                menu_state.lock().unwrap().assign_subscribe_pkid(pkid);
+            }
+            ClientEvent::SubAck { pkid, results } => {
+                menu_state.lock().unwrap().apply_suback(pkid, &results);
+            }
+            ClientEvent::UnsubscribeSent(pkid) => {
+                menu_state.lock().unwrap().assign_unsubscribe_pkid(pkid);
+            }
+            ClientEvent::UnsubAck { pkid, error } => {
+                menu_state.lock().unwrap().apply_unsuback(pkid, error);
+            }
+            ClientEvent::PublishSent(pkid) => {
+                menu_state.lock().unwrap().assign_publish_pkid(pkid);
+            }
+            ClientEvent::PubAck { pkid, error } => {
+                menu_state.lock().unwrap().apply_puback(pkid, error);
+            }
+            ClientEvent::PubRec { pkid, error } => {
+                menu_state.lock().unwrap().apply_pubrec(pkid, error);
+            }
+            ClientEvent::PubComp { pkid, error } => {
+                menu_state.lock().unwrap().apply_pubcomp(pkid, error);
+            }
+        }
+    }
+}
+
+/// Subscribes again to the filters that were active before the connection dropped.
+/// The broker starts a clean session, so it no longer knows about them.
+async fn resubscribe(
+    client: &ClientHandle,
+    menu_state: &Arc<Mutex<TopicActivityMenuState>>,
+    filters: Vec<SubscriptionFilter>,
+) {
+    for filter in filters {
+        if let Err(e) = client.subscribe(&filter.filter, filter.qos).await {
+            let mut menu_lock = menu_state.lock().unwrap();
+
+            if let Some(subscription) =
+                menu_lock.subscriptions.iter_mut().find(|s| s.filter == filter.filter)
+            {
+                subscription.status = app::SubscriptionStatus::Failed(e);
+            }
+        }
+    }
+}
+
+/// The receive time of a message as shown in the activity panel.
+pub(crate) fn format_timestamp(timestamp: OffsetDateTime) -> String {
+    let date_format: Vec<time::format_description::BorrowedFormatItem<'_>> =
+        parse(MQTT_TIMESTAMP_FORMAT).unwrap();
+    timestamp.format(&date_format).unwrap()
+}
+
+/// Receives a MQTTEvent, transforms it into a TopicActivity and pushes it into the topics
+/// list of the MenuState.
+pub(crate) fn push_message_into_topic(
+    menu_state: &Arc<Mutex<TopicActivityMenuState>>,
+    mqtt_event: MQTTEvent,
+) {
+    let topic_name = mqtt_event.topic;
+    let payload = mqtt_event.payload;
+    let properties = mqtt_event.properties;
+
+    let message = app::MessageActivity {
+        payload,
+        timestamp: format_timestamp(mqtt_event.timestamp),
+        received_at: mqtt_event.timestamp,
+        properties,
+        qos: mqtt_event.qos,
+        retain: mqtt_event.retain,
+        decoded: Default::default(),
+    };
+
+    let mut menu_state = menu_state.lock().unwrap();
+
+    // Sparkplug payloads are decoded on arrival: aliases are named by the BIRTH
+    // messages received until then.
+    if let Some(sparkplug) = menu_state.sparkplug.as_mut()
+        && let Some(decoded) = sparkplug.process(&topic_name, &message.payload)
+    {
+        let _ = message.decoded.set(decoded);
+    }
+
+    menu_state.add_message(&topic_name, message);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_add_topic_inserts_into_topics() {
+        let topic_menu_state = Arc::new(Mutex::new(app::TopicActivityMenuState::new()));
+
+        let menu_guard: std::sync::MutexGuard<'_, TopicActivityMenuState> =
+            topic_menu_state.lock().unwrap();
+            
+        assert_eq!(menu_guard.topics.len(), 0);
+        drop(menu_guard);
+
+        let mqtt_event = MQTTEvent {
+            topic: "Topic1".into(),
+            payload: "Payload 1".into(),
+            timestamp: OffsetDateTime::now_utc(),
+            properties: None,
+            qos: 0,
+            retain: false,
+        };
+
+        push_message_into_topic(&topic_menu_state, mqtt_event);
+        let menu_guard = topic_menu_state.lock().unwrap();
+
+        assert_eq!(menu_guard.topics.len(), 1);
+    }
+
+    #[test]
+    fn test_subscription_filter_parsing() {
+        let filters =
+            SubscriptionFilter::parse_list("sensors/#:1, alerts/+ , urn:dev/#:2").unwrap();
+
+        assert_eq!(
+            filters,
+            vec![
+                SubscriptionFilter { filter: "sensors/#".into(), qos: 1 },
+                SubscriptionFilter { filter: "alerts/+".into(), qos: 0 },
+                SubscriptionFilter { filter: "urn:dev/#".into(), qos: 2 },
+            ]
+        );
+
+        assert_eq!(
+            SubscriptionFilter::parse_list(" ").unwrap(),
+            vec![SubscriptionFilter::all_topics()]
+        );
+        assert!(SubscriptionFilter::parse_list("a/#/b").is_err());
+    }
+
+    #[test]
+    fn test_reconnect_delay_doubles_up_to_the_maximum() {
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(4), Duration::from_secs(8));
+        assert_eq!(policy.delay(5), Duration::from_secs(10));
+        assert_eq!(policy.delay(100), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_v5_properties_and_flags_are_kept_with_the_message() {
+        let topic_menu_state = Arc::new(Mutex::new(app::TopicActivityMenuState::new()));
+
+        let properties = MessageProperties {
+            content_type: Some("application/json".into()),
+            user_properties: vec![("site".into(), "plant-1".into())],
+            ..Default::default()
+        };
+
+        push_message_into_topic(
+            &topic_menu_state,
+            MQTTEvent {
+                topic: "devices/1".into(),
+                payload: "{}".into(),
+                timestamp: OffsetDateTime::now_utc(),
+                properties: Some(properties.clone()),
+                qos: 1,
+                retain: true,
+            },
+        );
+
+        let menu_guard = topic_menu_state.lock().unwrap();
+        let message = menu_guard.selected_message().unwrap();
+        assert_eq!(message.properties, Some(properties));
+        assert_eq!((message.qos, message.retain), (1, true));
+    }
+
+    #[test]
+    fn test_message_is_stored_in_correct_topic() {
+        let topic_menu_state = Arc::new(Mutex::new(app::TopicActivityMenuState::new()));
+
+        let mqtt_event_1 = MQTTEvent {
+            topic: "test/topic1".into(),
+            payload: "Payload 1!".into(),
+            timestamp: OffsetDateTime::now_utc(),
+            properties: None,
+            qos: 0,
+            retain: false,
+        };
+
+        let mqtt_event_2 = MQTTEvent {
+            topic: "test/topic2".into(),
+            payload: "Payload 2!".into(),
+            timestamp: OffsetDateTime::now_utc(),
+            properties: None,
+            qos: 0,
+            retain: false,
+        };
+
+        let mqtt_event_3 = MQTTEvent {
+            topic: "topic3".into(),
+            payload: "Payload 3!".into(),
+            timestamp: OffsetDateTime::now_utc(),
+            properties: None,
+            qos: 0,
+            retain: false,
+        };
+
+        let mqtt_event_4 = MQTTEvent {
+            topic: "topic3".into(),
+            payload: "Payload 4!".into(),
+            timestamp: OffsetDateTime::now_utc(),
+            properties: None,
+            qos: 0,
+            retain: false,
+        };
+
+        push_message_into_topic(&topic_menu_state, mqtt_event_1);
+        push_message_into_topic(&topic_menu_state, mqtt_event_2);
+        push_message_into_topic(&topic_menu_state, mqtt_event_3);
+        push_message_into_topic(&topic_menu_state, mqtt_event_4);
+
+        let menu_guard = topic_menu_state.lock().unwrap();
+
+        assert_eq!(menu_guard.topics[0].messages.len(), 1);
+        assert_eq!(menu_guard.topics[1].messages.len(), 1);
+        assert_eq!(menu_guard.topics[2].messages.len(), 2);
+
+        assert_eq!(menu_guard.topics[0].messages[0].payload, "Payload 1!");
+        assert_eq!(menu_guard.topics[1].messages[0].payload, "Payload 2!");
+        assert_eq!(menu_guard.topics[2].messages[0].payload, "Payload 3!");
+        assert_eq!(menu_guard.topics[2].messages[1].payload, "Payload 4!");
+    }
+}
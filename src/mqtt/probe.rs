@@ -0,0 +1,294 @@
+//! Broker probe run by the configuration form before the TUI starts.
+//! Performs the full CONNECT/CONNACK handshake (including TLS) and classifies
+//! every failure into a `BrokerError` the form can show to the user.
+
+use std::fmt;
+use std::net::{TcpStream as StdTcpStream, ToSocketAddrs};
+use std::time::Duration as StdDuration;
+
+use rumqttc::{ConnectReturnCode, ConnectionError, Event, Packet, RecvTimeoutError, v5};
+
+use super::{MQTTConfig, ProtocolVersion, client, tls};
+
+/// Outcome of a failed broker probe, reported in the configuration form.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BrokerError {
+    /// Nothing accepted a TCP connection on `host:port`.
+    Unreachable(String),
+    /// Something is listening, but it did not answer CONNECT with a CONNACK.
+    NotMqttBroker(String),
+    /// The broker does not support the requested MQTT protocol version.
+    UnsupportedProtocolVersion,
+    /// The broker rejected the username or password.
+    BadCredentials,
+    /// The client is not authorized to connect.
+    NotAuthorized,
+    /// The broker is up but currently unavailable.
+    ServerUnavailable,
+    /// The broker refused the connection for another reason (e.g. client id rejected).
+    Refused(String),
+    /// The TLS configuration could not be loaded or the TLS handshake failed.
+    Tls(String),
+}
+
+impl fmt::Display for BrokerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrokerError::Unreachable(e) => write!(f, "Host unreachable: {}", e),
+            BrokerError::NotMqttBroker(e) => write!(f, "Not an MQTT broker: {}", e),
+            BrokerError::UnsupportedProtocolVersion => {
+                write!(f, "Broker does not support this MQTT protocol version")
+            }
+            BrokerError::BadCredentials => write!(f, "Bad username or password"),
+            BrokerError::NotAuthorized => write!(f, "Not authorized to connect"),
+            BrokerError::ServerUnavailable => write!(f, "Broker unavailable, try again later"),
+            BrokerError::Refused(reason) => write!(f, "Connection refused: {}", reason),
+            BrokerError::Tls(e) => write!(f, "TLS error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BrokerError {}
+
+impl From<ConnectReturnCode> for BrokerError {
+    fn from(code: ConnectReturnCode) -> Self {
+        match code {
+            ConnectReturnCode::RefusedProtocolVersion => BrokerError::UnsupportedProtocolVersion,
+            ConnectReturnCode::BadUserNamePassword => BrokerError::BadCredentials,
+            ConnectReturnCode::NotAuthorized => BrokerError::NotAuthorized,
+            ConnectReturnCode::ServiceUnavailable => BrokerError::ServerUnavailable,
+            ConnectReturnCode::BadClientId => BrokerError::Refused("client id rejected".into()),
+            ConnectReturnCode::Success => BrokerError::Refused("unexpected success code".into()),
+        }
+    }
+}
+
+impl From<v5::mqttbytes::v5::ConnectReturnCode> for BrokerError {
+    fn from(code: v5::mqttbytes::v5::ConnectReturnCode) -> Self {
+        use v5::mqttbytes::v5::ConnectReturnCode as Code;
+
+        match code {
+            Code::RefusedProtocolVersion | Code::UnsupportedProtocolVersion => {
+                BrokerError::UnsupportedProtocolVersion
+            }
+            Code::BadUserNamePassword | Code::BadAuthenticationMethod => {
+                BrokerError::BadCredentials
+            }
+            Code::NotAuthorized | Code::Banned => BrokerError::NotAuthorized,
+            Code::ServiceUnavailable | Code::ServerUnavailable | Code::ServerBusy => {
+                BrokerError::ServerUnavailable
+            }
+            code => BrokerError::Refused(format!("{:?}", code)),
+        }
+    }
+}
+
+impl From<ConnectionError> for BrokerError {
+    /// Classifies a failure that happened after the TCP connection was accepted.
+    fn from(error: ConnectionError) -> Self {
+        match error {
+            ConnectionError::ConnectionRefused(code) => code.into(),
+            ConnectionError::Tls(e) => BrokerError::Tls(e.to_string()),
+            ConnectionError::NotConnAck(packet) => {
+                BrokerError::NotMqttBroker(format!("expected CONNACK, got {:?}", packet))
+            }
+            ConnectionError::NetworkTimeout => {
+                BrokerError::NotMqttBroker("no CONNACK received".into())
+            }
+            e => BrokerError::NotMqttBroker(e.to_string()),
+        }
+    }
+}
+
+impl From<v5::ConnectionError> for BrokerError {
+    /// Classifies a failure that happened after the TCP connection was accepted.
+    fn from(error: v5::ConnectionError) -> Self {
+        match error {
+            v5::ConnectionError::ConnectionRefused(code) => code.into(),
+            v5::ConnectionError::Tls(e) => BrokerError::Tls(e.to_string()),
+            v5::ConnectionError::NotConnAck(packet) => {
+                BrokerError::NotMqttBroker(format!("expected CONNACK, got {:?}", packet))
+            }
+            v5::ConnectionError::Timeout(_) => {
+                BrokerError::NotMqttBroker("no CONNACK received".into())
+            }
+            e => BrokerError::NotMqttBroker(e.to_string()),
+        }
+    }
+}
+
+/// Probes the broker before the TUI starts. First checks that `host:port` accepts a TCP
+/// connection within `timeout_secs` seconds, then performs the TLS handshake (if enabled)
+/// and sends CONNECT with the configured credentials and protocol version, waiting for the
+/// CONNACK. Each kind of failure is reported as its own `BrokerError` variant.
+pub fn validate_broker(config: &MQTTConfig, timeout_secs: u64) -> Result<(), BrokerError> {
+    let timeout = StdDuration::from_secs(timeout_secs);
+    let client_id = format!("mqtt-ranger-probe-{}", std::process::id());
+
+    match config.protocol {
+        ProtocolVersion::V311 => {
+            let options = client::mqtt_options_v311(config, client_id)?;
+            check_tcp_reachable(&config.host, config.port, timeout)?;
+            check_sni_handshake(config, timeout)?;
+
+            let (client, mut connection) = rumqttc::Client::new(options, 10);
+
+            let result = match connection.recv_timeout(timeout) {
+                Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => Ok(()),
+                Ok(Ok(event)) => Err(unexpected_event(event)),
+                Ok(Err(e)) => Err(e.into()),
+                Err(RecvTimeoutError::Timeout) => Err(no_connack()),
+                Err(RecvTimeoutError::Disconnected) => Err(connection_closed()),
+            };
+
+            let _ = client.try_disconnect();
+            result
+        }
+        ProtocolVersion::V5 => {
+            let options = client::mqtt_options_v5(config, client_id)?;
+            check_tcp_reachable(&config.host, config.port, timeout)?;
+            check_sni_handshake(config, timeout)?;
+
+            let (client, mut connection) = v5::Client::new(options, 10);
+
+            let result = match connection.recv_timeout(timeout) {
+                Ok(Ok(v5::Event::Incoming(v5::Incoming::ConnAck(_)))) => Ok(()),
+                Ok(Ok(event)) => Err(unexpected_event(event)),
+                Ok(Err(e)) => Err(e.into()),
+                Err(v5::RecvTimeoutError::Timeout) => Err(no_connack()),
+                Err(v5::RecvTimeoutError::Disconnected) => Err(connection_closed()),
+            };
+
+            let _ = client.try_disconnect();
+            result
+        }
+    }
+}
+
+fn unexpected_event(event: impl fmt::Debug) -> BrokerError {
+    BrokerError::NotMqttBroker(format!("unexpected event {:?}", event))
+}
+
+fn no_connack() -> BrokerError {
+    BrokerError::NotMqttBroker("no CONNACK received".into())
+}
+
+fn connection_closed() -> BrokerError {
+    BrokerError::NotMqttBroker("connection closed".into())
+}
+
+/// Opens a plain TCP connection to `host:port` to check that something is listening.
+fn check_tcp_reachable(host: &str, port: u16, timeout: StdDuration) -> Result<(), BrokerError> {
+    let addr_str = format!("{}:{}", host, port);
+
+    let addrs_iter = addr_str
+        .to_socket_addrs()
+        .map_err(|e| BrokerError::Unreachable(e.to_string()))?;
+
+    let mut last_err: Option<std::io::Error> = None;
+    for addr in addrs_iter {
+        match StdTcpStream::connect_timeout(&addr, timeout) {
+            Ok(_stream) => return Ok(()),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    Err(BrokerError::Unreachable(
+        last_err.map_or(addr_str, |e| e.to_string()),
+    ))
+}
+
+/// The relay sending the TLS server name as SNI closes the connection when the handshake
+/// fails, so with a server name the handshake is checked on its own first.
+fn check_sni_handshake(config: &MQTTConfig, timeout: StdDuration) -> Result<(), BrokerError> {
+    match &config.tls {
+        Some(settings) if settings.server_name.is_some() => {
+            tls::check_handshake(&config.host, config.port, settings, timeout)
+                .map_err(BrokerError::Tls)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Starts a one-shot TCP server that reads the CONNECT packet and answers with `response`.
+    fn spawn_fake_broker(response: &'static [u8], protocol: ProtocolVersion) -> MQTTConfig {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        std::thread::spawn(move || {
+            // The first connection is the reachability check, the second one the MQTT probe.
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut buf = [0u8; 256];
+                if stream.read(&mut buf).unwrap_or(0) > 0 {
+                    let _ = stream.write_all(response);
+                }
+            }
+        });
+
+        MQTTConfig {
+            host: "127.0.0.1".into(),
+            port,
+            username: Some("user".into()),
+            password: Some("wrong".into()),
+            protocol,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_broker_accepts_connack() {
+        let config = spawn_fake_broker(&[0x20, 0x02, 0x00, 0x00], ProtocolVersion::V311);
+
+        assert_eq!(validate_broker(&config, 2), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_broker_reports_bad_credentials() {
+        let config = spawn_fake_broker(&[0x20, 0x02, 0x00, 0x04], ProtocolVersion::V311);
+
+        assert_eq!(validate_broker(&config, 2), Err(BrokerError::BadCredentials));
+    }
+
+    #[test]
+    fn test_validate_broker_reports_v5_reason_codes() {
+        // CONNACK with reason code 0x87 (Not authorized) and an empty property list.
+        let config = spawn_fake_broker(&[0x20, 0x03, 0x00, 0x87, 0x00], ProtocolVersion::V5);
+
+        assert_eq!(validate_broker(&config, 2), Err(BrokerError::NotAuthorized));
+    }
+
+    #[test]
+    fn test_validate_broker_detects_non_mqtt_server() {
+        let config = spawn_fake_broker(b"HTTP/1.1 400 Bad Request\r\n\r\n", ProtocolVersion::V311);
+
+        assert!(matches!(
+            validate_broker(&config, 2),
+            Err(BrokerError::NotMqttBroker(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_broker_reports_unreachable_host() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let config = MQTTConfig {
+            host: "127.0.0.1".into(),
+            port,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            validate_broker(&config, 2),
+            Err(BrokerError::Unreachable(_))
+        ));
+    }
+}
@@ -0,0 +1,239 @@
+//! TLS support for MQTT connections.
+//! Builds the rustls client configuration handed to rumqttc from the
+//! CA bundle, client certificate/key and verification options in `TlsSettings`,
+//! and relays connections whose SNI differs from the broker host.
+
+use std::net::{Ipv4Addr, SocketAddr, TcpListener as StdTcpListener, TcpStream as StdTcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rumqttc::tokio_rustls::TlsConnector;
+use rumqttc::tokio_rustls::rustls::{
+    self, ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime, pem::PemObject},
+};
+use tokio::net::{TcpListener, TcpStream};
+
+/// TLS options for connecting to a broker (usually on port 8883).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsSettings {
+    /// PEM bundle with the CA certificates to trust. The platform roots are used when unset.
+    pub ca_path: Option<PathBuf>,
+    /// PEM client certificate for mutual TLS. Must be given together with `client_key_path`.
+    pub client_cert_path: Option<PathBuf>,
+    /// PEM private key matching `client_cert_path`.
+    pub client_key_path: Option<PathBuf>,
+    /// Accept any server certificate. Only meant for lab brokers.
+    pub insecure_skip_verify: bool,
+    /// Server name sent as SNI and verified in the certificate instead of the broker host,
+    /// e.g. when connecting by IP address to a broker or a TLS router that picks the backend
+    /// by SNI. See `sni_relay`.
+    pub server_name: Option<String>,
+}
+
+/// Builds the rustls client configuration for the given TLS settings.
+pub fn client_config(settings: &TlsSettings) -> Result<ClientConfig, String> {
+    let builder = ClientConfig::builder();
+    let provider = builder.crypto_provider().clone();
+
+    let builder = if settings.insecure_skip_verify {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoCertificateVerification { provider }))
+    } else {
+        builder.with_root_certificates(load_root_store(settings.ca_path.as_deref())?)
+    };
+
+    match (&settings.client_cert_path, &settings.client_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = load_certs(cert_path)?;
+            let key = PrivateKeyDer::from_pem_file(key_path)
+                .map_err(|e| format!("{}: {}", key_path.display(), e))?;
+
+            builder
+                .with_client_auth_cert(certs, key)
+                .map_err(|e| e.to_string())
+        }
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => Err("Client certificate and key must be given together".into()),
+    }
+}
+
+/// Loads the trusted roots from a PEM bundle, or from the platform store when no path is given.
+fn load_root_store(ca_path: Option<&Path>) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+
+    match ca_path {
+        Some(path) => {
+            roots.add_parsable_certificates(load_certs(path)?);
+        }
+        None => {
+            roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+        }
+    }
+
+    if roots.is_empty() {
+        return Err("No valid CA certificate found".into());
+    }
+
+    Ok(roots)
+}
+
+/// Reads every certificate from a PEM file.
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", path.display()));
+    }
+
+    Ok(certs)
+}
+
+/// Parses the name sent as SNI.
+fn server_name(name: &str) -> Result<ServerName<'static>, String> {
+    ServerName::try_from(name.to_string())
+        .map_err(|e| format!("Invalid server name '{}': {}", name, e))
+}
+
+/// Relays started by `sni_relay`, with the broker and settings they connect with.
+static SNI_RELAYS: Mutex<Vec<(String, u16, TlsSettings, SocketAddr)>> = Mutex::new(Vec::new());
+
+/// Address of a loopback relay that forwards each connection to `host:port` over TLS,
+/// sending `settings.server_name` as SNI. rumqttc always sends the broker host, so it
+/// talks plain MQTT to the relay instead when a server name is set. One relay is started
+/// per broker and settings, and lives as long as the process.
+pub fn sni_relay(host: &str, port: u16, settings: &TlsSettings) -> Result<SocketAddr, String> {
+    let mut relays = SNI_RELAYS.lock().map_err(|_| "SNI relays poisoned".to_string())?;
+    if let Some((.., addr)) = relays
+        .iter()
+        .find(|(h, p, s, _)| h == host && *p == port && s == settings)
+    {
+        return Ok(*addr);
+    }
+
+    let name = server_name(settings.server_name.as_deref().unwrap_or(host))?;
+    let connector = TlsConnector::from(Arc::new(client_config(settings)?));
+    let listener = StdTcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(|e| e.to_string())?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let broker = (host.to_string(), port);
+    std::thread::spawn(move || runtime.block_on(relay(listener, broker, name, connector)));
+
+    relays.push((host.to_string(), port, settings.clone(), addr));
+    Ok(addr)
+}
+
+/// Accepts loopback connections and pipes each one through its own TLS connection to the
+/// broker. A connection whose handshake fails is closed, which rumqttc reports as a
+/// dropped connection; `check_handshake` gives the reason before connecting.
+async fn relay(
+    listener: StdTcpListener,
+    broker: (String, u16),
+    name: ServerName<'static>,
+    connector: TlsConnector,
+) {
+    let Ok(listener) = TcpListener::from_std(listener) else {
+        return;
+    };
+
+    while let Ok((mut local, _)) = listener.accept().await {
+        let broker = broker.clone();
+        let name = name.clone();
+        let connector = connector.clone();
+
+        tokio::spawn(async move {
+            let Ok(tcp) = TcpStream::connect((broker.0.as_str(), broker.1)).await else {
+                return;
+            };
+            if let Ok(mut tls) = connector.connect(name, tcp).await {
+                let _ = tokio::io::copy_bidirectional(&mut local, &mut tls).await;
+            }
+        });
+    }
+}
+
+/// Performs a TLS handshake with `host:port` sending `settings.server_name` as SNI, so
+/// that certificate errors are reported even though the relay hides them from rumqttc.
+pub fn check_handshake(
+    host: &str,
+    port: u16,
+    settings: &TlsSettings,
+    timeout: Duration,
+) -> Result<(), String> {
+    let name = server_name(settings.server_name.as_deref().unwrap_or(host))?;
+    let mut connection = ClientConnection::new(Arc::new(client_config(settings)?), name)
+        .map_err(|e| e.to_string())?;
+    let mut tcp = StdTcpStream::connect((host, port)).map_err(|e| e.to_string())?;
+    tcp.set_read_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+    tcp.set_write_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+
+    while connection.is_handshaking() {
+        connection.complete_io(&mut tcp).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Accepts any server certificate while still checking handshake signatures.
+#[derive(Debug)]
+struct NoCertificateVerification {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
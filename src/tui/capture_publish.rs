@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode};
+use ratatui::{
+    Terminal,
+    layout::{Constraint, Direction, Layout},
+    prelude::CrosstermBackend,
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, BorderType, Borders, Gauge, Paragraph},
+};
+
+use crate::capture::PublishProgress;
+use crate::tui::{Screen, centered_rect};
+
+/// Progress of the publication of a capture file to a broker.
+pub struct CapturePublishScreen<'a> {
+    terminal: &'a mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    path: PathBuf,
+    progress: Arc<Mutex<PublishProgress>>,
+}
+
+impl<'a> CapturePublishScreen<'a> {
+    pub fn new(
+        terminal: &'a mut Terminal<CrosstermBackend<std::io::Stdout>>,
+        path: PathBuf,
+        progress: Arc<Mutex<PublishProgress>>,
+    ) -> Self {
+        Self {
+            terminal,
+            path,
+            progress,
+        }
+    }
+
+    /// Renders the progress bar and the publish counts.
+    fn render_capture_publish_ui(f: &mut ratatui::Frame, path: &str, progress: &PublishProgress) {
+        let area = centered_rect(72, 11, f.area());
+        let done = progress.delivered + progress.failed;
+        let finished = progress.is_finished();
+
+        let hint = if finished { " Finished, press any key " } else { " q: stop " };
+        let block = Block::default()
+            .title(format!(" Publishing {} ", path))
+            .title_bottom(Line::styled(hint, Style::default().fg(Color::DarkGray)))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints([Constraint::Length(1), Constraint::Length(1), Constraint::Min(0)])
+            .split(inner);
+
+        let ratio = if progress.total == 0 { 1.0 } else { done as f64 / progress.total as f64 };
+        f.render_widget(
+            Gauge::default()
+                .gauge_style(Style::default().fg(Color::Green))
+                .ratio(ratio)
+                .label(format!("{}/{}", done, progress.total)),
+            layout[0],
+        );
+
+        let mut lines = vec![
+            Line::from(format!("Queued:    {}", progress.queued)),
+            Line::styled(
+                format!("Delivered: {}", progress.delivered),
+                Style::default().fg(Color::Green),
+            ),
+            Line::styled(
+                format!("Failed:    {}", progress.failed),
+                Style::default().fg(if progress.failed > 0 { Color::Red } else { Color::White }),
+            ),
+        ];
+        if let Some(error) = &progress.last_error {
+            lines.push(Line::styled(
+                format!("Last error: {}", error),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        if let Some(error) = &progress.aborted {
+            lines.push(Line::styled(
+                format!("Connection lost: {}", error),
+                Style::default().fg(Color::Red),
+            ));
+        }
+
+        f.render_widget(Paragraph::new(lines), layout[2]);
+    }
+}
+
+impl Screen for CapturePublishScreen<'_> {
+    fn run(&mut self) -> std::io::Result<()> {
+        let path = self.path.display().to_string();
+
+        loop {
+            {
+                let progress = self
+                    .progress
+                    .lock()
+                    .map_err(|_| std::io::Error::other("Publish progress mutex poisoned"))?;
+                self.terminal.draw(|f| {
+                    Self::render_capture_publish_ui(f, &path, &progress);
+                })?;
+            }
+
+            if self.handle_input()? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn handle_input(&mut self) -> std::io::Result<bool> {
+        if event::poll(Duration::from_millis(100))?
+            && let Event::Key(key) = event::read()?
+        {
+            let finished = self.progress.lock().is_ok_and(|progress| progress.is_finished());
+            return Ok(finished || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc));
+        }
+        Ok(false)
+    }
+}
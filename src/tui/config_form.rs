@@ -2,7 +2,7 @@ use std::time::Duration;
 
 use crate::{
     app::{ConfigFormState, FocusField},
-    mqtt::MQTTConfig,
+    mqtt::{BrokerError, MQTTConfig},
     tui::{Screen, centered_rect},
 };
 use std::sync::mpsc::{self, Receiver};
@@ -23,17 +23,23 @@ pub struct ConfigFormScreen<'a> {
     terminal: &'a mut Terminal<CrosstermBackend<std::io::Stdout>>,
     state: ConfigFormState,
     result: Option<MQTTConfig>,
-    pending_conn: Option<Receiver<Result<(), String>>>,
+    pending_conn: Option<Receiver<Result<(), BrokerError>>>,
+    /// Configuration being validated by the background probe.
+    pending_config: Option<MQTTConfig>,
     last_spinner_tick: Instant,
 }
 
 impl<'a> ConfigFormScreen<'a> {
-    pub fn new(terminal: &'a mut Terminal<CrosstermBackend<std::io::Stdout>>) -> Self {
+    pub fn new(
+        terminal: &'a mut Terminal<CrosstermBackend<std::io::Stdout>>,
+        state: ConfigFormState,
+    ) -> Self {
         Self {
             terminal,
-            state: ConfigFormState::new(),
+            state,
             result: None,
             pending_conn: None,
+            pending_config: None,
             last_spinner_tick: Instant::now(),
         }
     }
@@ -47,16 +53,17 @@ impl<'a> ConfigFormScreen<'a> {
     }
 
     // Start a background thread to validate the broker and store the receiver
-    fn spawn_validation_thread(&mut self, host: String, port: u16, timeout_secs: u64) {
-        let (tx, rx): (mpsc::Sender<Result<(), String>>, Receiver<Result<(), String>>) = mpsc::channel();
+    fn spawn_validation_thread(&mut self, config: MQTTConfig, timeout_secs: u64) {
+        let (tx, rx) = mpsc::channel();
+        let probe_config = config.clone();
 
         thread::spawn(move || {
-            let res = crate::mqtt::validate_broker(&host, port, timeout_secs)
-                .map_err(|e| e.to_string());
+            let res = crate::mqtt::validate_broker(&probe_config, timeout_secs);
             let _ = tx.send(res);
         });
 
         self.pending_conn = Some(rx);
+        self.pending_config = Some(config);
     }
 
     // Process any pending connection result and update state accordingly.
@@ -64,19 +71,19 @@ impl<'a> ConfigFormScreen<'a> {
         if let Some(rx) = &self.pending_conn {
             match rx.try_recv() {
                 Ok(Ok(())) => {
-                    // success: complete form
-                    if let Ok(port) = self.state.port.parse::<u16>() {
-                        self.result = Some(MQTTConfig {
-                            host: self.state.host.clone(),
-                            port,
-                        });
-                    } else {
-                        self.state.error = Some("Port must be a valid number".into());
-                    }
+                    // success: complete form with the configuration that was validated
+                    self.result = self.pending_config.take();
                     self.pending_conn = None;
                 }
-                Ok(Err(_)) => {
-                    self.state.error = Some(format!("Host unreachable: {}", self.state.host));
+                Ok(Err(BrokerError::Unreachable(e))) => {
+                    self.state.error =
+                        Some(format!("Host unreachable: {} ({})", self.state.host, e));
+                    self.state.connecting = false;
+                    self.state.spinner_idx = 0;
+                    self.pending_conn = None;
+                }
+                Ok(Err(e)) => {
+                    self.state.error = Some(e.to_string());
                     self.state.connecting = false;
                     self.state.spinner_idx = 0;
                     self.pending_conn = None;
@@ -94,22 +101,45 @@ impl<'a> ConfigFormScreen<'a> {
 
     // Handle the Enter key press: start validation or ignore if already connecting
     fn on_enter_pressed(&mut self) {
-        if let Ok(port) = self.state.port.parse::<u16>() {
-            if self.state.connecting {
-                return;
-            }
+        if self.state.connecting {
+            return;
+        }
 
-            self.state.error = None;
-            self.state.connecting = true;
-            self.state.spinner_idx = 0;
+        match self.state.config() {
+            Ok(config) => {
+                self.state.error = None;
+                self.state.connecting = true;
+                self.state.spinner_idx = 0;
 
-            let host = self.state.host.clone();
-            self.spawn_validation_thread(host, port, 5);
-        } else {
-            self.state.error = Some("Port must be a valid number".into());
+                self.spawn_validation_thread(config, 5);
+            }
+            Err(e) => self.state.error = Some(e),
         }
     }
 
+    /// Renders a single bordered form field, highlighted when focused.
+    fn render_field(
+        f: &mut ratatui::Frame,
+        area: ratatui::layout::Rect,
+        title: &str,
+        value: impl Into<String>,
+        focused: bool,
+        enabled: bool,
+    ) {
+        let style = if focused {
+            Style::default().fg(Color::Black).bg(Color::White)
+        } else if enabled {
+            Style::default()
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+
+        let field = Paragraph::new(value.into())
+            .style(style)
+            .block(Block::default().title(title.to_string()).borders(Borders::ALL));
+        f.render_widget(field, area);
+    }
+
     /// The final result of the form (if completed).
     pub fn into_config(self) -> Option<MQTTConfig> {
         self.result
@@ -118,12 +148,12 @@ impl<'a> ConfigFormScreen<'a> {
     /// Renders the configuration form UI.
     fn render_config_screen_ui(f: &mut ratatui::Frame, state: &ConfigFormState) {
         let size = f.area();
-        let total_area = centered_rect(40, 17, size);
+        let total_area = centered_rect(84, 24, size);
 
         let layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Length(15),
+                Constraint::Length(22),
                 Constraint::Length(2),
             ])
             .split(total_area);
@@ -141,37 +171,116 @@ impl<'a> ConfigFormScreen<'a> {
 
         let inner = block.inner(form_area);
 
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .horizontal_margin(6)
-            .vertical_margin(3)
-            .constraints([
-                Constraint::Length(3), // Host
-                Constraint::Length(3), // Port
-            ])
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .horizontal_margin(2)
+            .vertical_margin(1)
+            .spacing(2)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
             .split(inner);
 
-        // HOST FIELD
-        let host_style = match state.focus {
-            FocusField::Host => Style::default().fg(Color::Black).bg(Color::White),
-            _ => Style::default(),
-        };
-
-        let host = Paragraph::new(state.host.as_str())
-            .style(host_style)
-            .block(Block::default().title("Host").borders(Borders::ALL));
-        f.render_widget(host, chunks[0]);
-
-        // PORT FIELD
-        let port_style = match state.focus {
-            FocusField::Port => Style::default().fg(Color::Black).bg(Color::White),
-            _ => Style::default(),
+        let field_rows = |area| {
+            Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(3); 6])
+                .split(area)
         };
-
-        let port = Paragraph::new(state.port.as_str())
-            .style(port_style)
-            .block(Block::default().title("Port").borders(Borders::ALL));
-        f.render_widget(port, chunks[1]);
+        let left = field_rows(columns[0]);
+        let right = field_rows(columns[1]);
+
+        // CONNECTION FIELDS
+        Self::render_field(f, left[0], "Host", &state.host, state.focus == FocusField::Host, true);
+        Self::render_field(f, left[1], "Port", &state.port, state.focus == FocusField::Port, true);
+        Self::render_field(
+            f,
+            left[2],
+            "Username (optional)",
+            &state.username,
+            state.focus == FocusField::Username,
+            true,
+        );
+
+        // PASSWORD FIELD (masked)
+        let masked_password = "*".repeat(state.password.chars().count());
+        Self::render_field(
+            f,
+            left[3],
+            "Password",
+            &masked_password,
+            state.focus == FocusField::Password,
+            true,
+        );
+
+        Self::render_field(
+            f,
+            left[4],
+            "Protocol (space to toggle)",
+            state.protocol.label(),
+            state.focus == FocusField::Protocol,
+            true,
+        );
+        Self::render_field(
+            f,
+            left[5],
+            "Topic filters (filter[:qos], ...)",
+            if state.topics.is_empty() && state.focus != FocusField::Topics {
+                "# (all topics)"
+            } else {
+                state.topics.as_str()
+            },
+            state.focus == FocusField::Topics,
+            true,
+        );
+
+        // TLS FIELDS (dimmed while TLS is disabled)
+        Self::render_field(
+            f,
+            right[0],
+            "TLS (space to toggle)",
+            checkbox(state.tls, "Enabled"),
+            state.focus == FocusField::Tls,
+            true,
+        );
+        Self::render_field(
+            f,
+            right[1],
+            "CA bundle (empty: system roots)",
+            &state.tls_ca_path,
+            state.focus == FocusField::TlsCaPath,
+            state.tls,
+        );
+        Self::render_field(
+            f,
+            right[2],
+            "Client certificate",
+            &state.tls_client_cert,
+            state.focus == FocusField::TlsClientCert,
+            state.tls,
+        );
+        Self::render_field(
+            f,
+            right[3],
+            "Client key",
+            &state.tls_client_key,
+            state.focus == FocusField::TlsClientKey,
+            state.tls,
+        );
+        Self::render_field(
+            f,
+            right[4],
+            "Verification",
+            checkbox(state.tls_insecure, "Insecure: skip verify"),
+            state.focus == FocusField::TlsInsecure,
+            state.tls,
+        );
+        Self::render_field(
+            f,
+            right[5],
+            "Server name override (SNI)",
+            &state.tls_server_name,
+            state.focus == FocusField::TlsServerName,
+            state.tls,
+        );
 
         // ERROR / CONNECTING MESSAGE
         if state.connecting {
@@ -192,6 +301,28 @@ impl<'a> ConfigFormScreen<'a> {
 }
 
 
+/// Shows that the broker given on the command line is being checked, in place of the form.
+pub fn draw_connecting(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    host: &str,
+    port: u16,
+) -> std::io::Result<()> {
+    terminal.draw(|f| {
+        let area = centered_rect(60, 3, f.area());
+        let connecting = Paragraph::new(format!("Connecting to {}:{}...", host, port))
+            .style(Style::default().fg(Color::Yellow))
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).border_type(BorderType::Thick));
+        f.render_widget(connecting, area);
+    })?;
+    Ok(())
+}
+
+/// Text shown inside toggle fields.
+fn checkbox(checked: bool, label: &str) -> String {
+    format!("[{}] {}", if checked { "x" } else { " " }, label)
+}
+
 impl Screen for ConfigFormScreen<'_> {
 
     fn run(&mut self) -> std::io::Result<()> {
@@ -246,10 +377,7 @@ impl Screen for ConfigFormScreen<'_> {
                     self.on_enter_pressed();
                 }
                 KeyCode::Esc => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        "User cancelled config form",
-                    ));
+                    return Err(std::io::Error::other("User cancelled config form"));
                 }
                 _ => {}
             }
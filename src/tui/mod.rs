@@ -1,7 +1,7 @@
-///! TUI module for mqtt-ranger: Handles terminal initialization, splash screen,
-///! configuration form, and main event loop for displaying MQTT topic activity.
-///! This module uses the ratatui and crossterm crates to create a user-friendly
-///! terminal interface.
+//! TUI module for mqtt-ranger: Handles terminal initialization, splash screen,
+//! configuration form, and main event loop for displaying MQTT topic activity.
+//! This module uses the ratatui and crossterm crates to create a user-friendly
+//! terminal interface.
 
 use crossterm::{
     execute,
@@ -17,6 +17,7 @@ use ratatui::{
 pub mod splash;
 pub mod config_form;
 pub mod topic_activity;
+pub mod capture_publish;
 
 
 /// Initializes the terminal in raw mode and sets up the alternate screen for the TUI application.
@@ -93,10 +93,10 @@ impl Screen for SplashScreen<'_> {
     }
 
     fn handle_input(&mut self) -> std::io::Result<bool> {
-        if crossterm::event::poll(Duration::from_millis(100))? {
-            if let crossterm::event::Event::Key(_) = crossterm::event::read()? {
-                return Ok(true);
-            }
+        if crossterm::event::poll(Duration::from_millis(100))?
+            && let crossterm::event::Event::Key(_) = crossterm::event::read()?
+        {
+            return Ok(true);
         }
         Ok(false)
     }
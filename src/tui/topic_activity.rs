@@ -4,24 +4,45 @@ use std::{
 };
 
 use crate::{
-    app::TopicActivityMenuState,
-    tui::{Screen, make_list_state},
+    app::{
+        ClearRetained, ConnectionState, DetailPanel, ExportField, ExportForm, ExportStatus,
+        JobState, JobsPanel, JsonView, MessageProperties, Pane, Publication, PublishComposer,
+        PublishStatus, SparkplugState, SparkplugView, Subscription, SubscriptionStatus,
+        SubscriptionsPanel, TopicActivityMenuState, TopicView, TreeRow,
+        composer::ComposerField,
+        hex_dump,
+        json_view::TokenKind,
+        publish_job::{JobField, JobForm},
+        sparkplug::SparkplugRow,
+    },
+    capture::Replay,
+    decoder::TreeLine,
+    mqtt::{ClientHandle, SubscriptionFilter},
+    tui::{Screen, centered_rect, make_list_state},
 };
 
-use crossterm::event::{self, Event, KeyCode};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use time::OffsetDateTime;
 use ratatui::{
     Terminal,
     layout::{Constraint, Direction, Layout},
     prelude::CrosstermBackend,
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
 };
 
 /// Screen for displaying topic activity.
 pub struct TopicActivityScreen<'a> {
     terminal: &'a mut Terminal<CrosstermBackend<std::io::Stdout>>,
     menu_state: Arc<Mutex<TopicActivityMenuState>>,
+    /// Used to subscribe, unsubscribe and publish while the screen is running.
+    /// `None` while replaying a capture file.
+    client: Option<ClientHandle>,
+    /// Scroll position of the message list, kept between frames.
+    message_list: ListState,
+    /// Number of visible messages, used as the PageUp/PageDown step.
+    page_size: usize,
     tick_rate: Duration,
     last_tick: Instant,
 }
@@ -30,18 +51,38 @@ impl<'a> TopicActivityScreen<'a> {
     pub fn new(
         terminal: &'a mut Terminal<CrosstermBackend<std::io::Stdout>>,
         menu_state: Arc<Mutex<TopicActivityMenuState>>,
+        client: Option<ClientHandle>,
     ) -> Self {
         Self {
             terminal,
             menu_state,
+            client,
+            message_list: ListState::default(),
+            page_size: 10,
             tick_rate: Duration::from_millis(250),
             last_tick: Instant::now(),
         }
     }
 
-    /// Renders the topic activity screen UI.
-    fn render_topic_activity_screen_ui(f: &mut ratatui::Frame, app: &TopicActivityMenuState) {
-        let size = f.area();
+    /// The client connected to the broker, or why there is none.
+    fn client(&self) -> Result<&ClientHandle, String> {
+        self.client
+            .as_ref()
+            .ok_or_else(|| "Not connected to a broker while replaying a capture".to_string())
+    }
+
+    /// Renders the topic activity screen UI. Returns the number of visible messages.
+    fn render_topic_activity_screen_ui(
+        f: &mut ratatui::Frame,
+        app: &TopicActivityMenuState,
+        message_list: &mut ListState,
+    ) -> usize {
+        let outer = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)])
+            .split(f.area());
+
+        Self::render_status_bar(f, outer[1], app);
 
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -52,81 +93,1568 @@ impl<'a> TopicActivityScreen<'a> {
                 ]
                 .as_ref(),
             )
-            .split(size);
+            .split(outer[0]);
 
         // --- Topic list ---
-        let items: Vec<ListItem> = app
-            .topics
-            .iter()
-            .map(|t| ListItem::new(Line::from(Span::raw(t.name.clone()))))
-            .collect();
+        let tree_rows = match app.view {
+            TopicView::Flat => Vec::new(),
+            TopicView::Tree => app.tree.rows(&app.topics),
+        };
+
+        let (items, selected, title): (Vec<ListItem>, usize, &str) = match app.view {
+            TopicView::Flat => (
+                app.topics
+                    .iter()
+                    .map(|t| {
+                        let mut spans = vec![Span::raw(t.name.clone())];
+                        if t.retained {
+                            spans.push(retained_marker());
+                        }
+                        ListItem::new(Line::from(spans))
+                    })
+                    .collect(),
+                app.selected_index,
+                "Topics",
+            ),
+            TopicView::Tree => (
+                tree_rows.iter().map(tree_row_item).collect(),
+                app.tree.selected_position(&tree_rows),
+                "Topics (tree)",
+            ),
+        };
 
         let topics_list = List::new(items)
-            .block(Block::default().title("Topics").borders(Borders::ALL))
+            .block(Block::default().title(title).borders(Borders::ALL))
             .highlight_style(
                 Style::default()
                     .add_modifier(Modifier::BOLD)
                     .add_modifier(Modifier::REVERSED),
             );
 
+        f.render_stateful_widget(topics_list, chunks[0], &mut make_list_state(selected));
+
+        // --- Activity panel ---
+        let activity_area = match app.detail_panel {
+            Some(panel) => {
+                let right = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+                    .split(chunks[1]);
+
+                match panel {
+                    DetailPanel::Properties => Self::render_properties_panel(f, right[1], app),
+                    DetailPanel::HexDump => Self::render_hex_dump_panel(f, right[1], app),
+                    DetailPanel::Decoded => Self::render_decoded_panel(f, right[1], app),
+                }
+                right[0]
+            }
+            None => chunks[1],
+        };
+
+        let page_size =
+            Self::render_activity_panel(f, activity_area, app, &tree_rows, message_list);
+
+        if let Some(panel) = &app.subscriptions_panel {
+            Self::render_subscriptions_panel(f, panel, &app.subscriptions);
+        }
+
+        if let Some(view) = &app.json_view {
+            Self::render_json_view(f, view);
+        }
+
+        if let Some(view) = &app.sparkplug_view {
+            Self::render_sparkplug_view(f, view, app.sparkplug.as_ref());
+        }
+
+        if let Some(composer) = &app.composer {
+            Self::render_composer(f, composer, app.publications.back());
+        }
+
+        if let Some(panel) = &app.jobs_panel {
+            Self::render_jobs_panel(f, panel, &app.publish_jobs);
+        }
+
+        if let Some(clear) = &app.clear_retained {
+            Self::render_clear_retained(f, clear);
+        }
+
+        if let Some(form) = &app.export_form {
+            Self::render_export_form(f, form);
+        }
+
+        page_size
+    }
+
+    /// Renders the messages of the selected topic as a scrollable list with a cursor,
+    /// or a summary when a tree node without messages is selected.
+    /// Returns the number of visible rows.
+    fn render_activity_panel(
+        f: &mut ratatui::Frame,
+        area: ratatui::layout::Rect,
+        app: &TopicActivityMenuState,
+        tree_rows: &[TreeRow],
+        message_list: &mut ListState,
+    ) -> usize {
+        let focused = app.focused_pane == Pane::Messages;
+        let border_style = if focused {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        let page_size = area.height.saturating_sub(2).max(1) as usize;
+
+        let Some(topic) = app.selected_topic() else {
+            let lines = match tree_rows.get(app.tree.selected_position(tree_rows)) {
+                Some(row) => subtree_summary_lines(row),
+                None => vec![Line::from("No topics")],
+            };
+            let block = Block::default()
+                .title("Activity")
+                .borders(Borders::ALL)
+                .border_style(border_style);
+
+            f.render_widget(Paragraph::new(lines).block(block), area);
+            return page_size;
+        };
+
+        let mode = match app.selected_position() {
+            Some(position) if !app.is_following() => {
+                let number = position - app.first_position(topic) + 1;
+                format!("paused at {}/{}, End to follow", number, app.message_count())
+            }
+            _ => "following".to_string(),
+        };
+        let mut block = Block::default()
+            .title(format!(
+                "Activity [{}] ({}, decoder: {})",
+                topic.name,
+                mode,
+                app.decoders.decoder_for(&topic.name)
+            ))
+            .borders(Borders::ALL)
+            .border_style(border_style);
+
+        if topic.dropped > 0 {
+            let older = if app.history.is_some() { "on disk" } else { "discarded" };
+            block = block.title_bottom(Line::styled(
+                format!(" {} older messages {} ", format_count(topic.dropped), older),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+
+        let window = app.shown_messages(page_size);
+        if window.messages.is_empty() {
+            f.render_widget(Paragraph::new("No messages yet...").block(block), area);
+            return page_size;
+        }
+
+        let items: Vec<ListItem> = window
+            .messages
+            .into_iter()
+            .map(|msg| {
+                let timestamp_span = Span::styled(
+                    format!("<{}>: ", msg.timestamp),
+                    Style::default()
+                        .fg(Color::LightRed)
+                        .add_modifier(Modifier::BOLD),
+                );
+
+                let decoded = app.decoded(&topic.name, msg);
+                let decoder_color = if decoded.error.is_some() {
+                    Color::Red
+                } else {
+                    Color::Magenta
+                };
+                let decoder_span = Span::styled(
+                    format!("[{}] ", decoded.decoder),
+                    Style::default().fg(decoder_color),
+                );
+
+                ListItem::new(Line::from(vec![
+                    timestamp_span,
+                    decoder_span,
+                    Span::raw(decoded.summary.as_str()),
+                ]))
+            })
+            .collect();
+
+        let highlight_style = if focused {
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD)
+        };
+
+        // Between frames the list state keeps the offset among all the listed messages;
+        // the list itself only sees the window around the cursor.
+        message_list.select(window.selected);
+        *message_list.offset_mut() = message_list.offset().saturating_sub(window.first);
         f.render_stateful_widget(
-            topics_list,
-            chunks[0],
-            &mut make_list_state(app.selected_index),
+            List::new(items).block(block).highlight_style(highlight_style),
+            area,
+            message_list,
         );
+        *message_list.offset_mut() += window.first;
 
-        // --- Activity panel ---
-        let activity_text = if let Some(topic) = app.topics.get(app.selected_index) {
-            let mut lines = vec![Line::from(Span::styled(
-                format!("[{}]", topic.name),
-                Style::default().add_modifier(Modifier::BOLD),
+        page_size
+    }
+
+    /// Renders the subscriptions overlay: the subscribed filters, the input for a new
+    /// filter and the last error.
+    fn render_subscriptions_panel(
+        f: &mut ratatui::Frame,
+        panel: &SubscriptionsPanel,
+        subscriptions: &[Subscription],
+    ) {
+        let area = centered_rect(70, 20, f.area());
+        f.render_widget(Clear, area);
+
+        let block = Block::default()
+            .title("Subscriptions (Enter: subscribe, Del: unsubscribe, Esc: close)")
+            .borders(Borders::ALL);
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(3), Constraint::Length(1)])
+            .split(inner);
+
+        let items: Vec<ListItem> = subscriptions
+            .iter()
+            .map(|subscription| {
+                let (status, color) = match &subscription.status {
+                    SubscriptionStatus::Pending => ("pending".to_string(), Color::Yellow),
+                    SubscriptionStatus::Granted(qos) => (format!("QoS {}", qos), Color::Green),
+                    SubscriptionStatus::Failed(reason) => {
+                        (format!("FAILED: {}", reason), Color::Red)
+                    }
+                    SubscriptionStatus::Unsubscribing => {
+                        ("unsubscribing".to_string(), Color::Yellow)
+                    }
+                };
+
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{:<40} ", subscription.filter)),
+                    Span::styled(status, Style::default().fg(color)),
+                ]))
+            })
+            .collect();
+
+        let list = List::new(items).highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .add_modifier(Modifier::REVERSED),
+        );
+
+        if subscriptions.is_empty() {
+            f.render_widget(Paragraph::new("No subscriptions"), rows[0]);
+        } else {
+            f.render_stateful_widget(list, rows[0], &mut make_list_state(panel.selected));
+        }
+
+        let input = Paragraph::new(format!("{}_", panel.input)).block(
+            Block::default()
+                .title("New filter (filter[:qos])")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow)),
+        );
+        f.render_widget(input, rows[1]);
+
+        if let Some(error) = &panel.error {
+            f.render_widget(
+                Paragraph::new(error.as_str()).style(Style::default().fg(Color::Red)),
+                rows[2],
+            );
+        }
+    }
+
+    /// Renders the JSON popup: the pretty-printed document with its syntax colored,
+    /// or the payload text with the parse error.
+    fn render_json_view(f: &mut ratatui::Frame, view: &JsonView) {
+        let screen = f.area();
+        let area = centered_rect(screen.width * 4 / 5, screen.height * 4 / 5, screen);
+        f.render_widget(Clear, area);
+
+        let items: Vec<ListItem> = view
+            .lines()
+            .into_iter()
+            .map(|line| {
+                let mut spans = vec![Span::raw("  ".repeat(line.depth))];
+                spans.extend(
+                    line.tokens
+                        .into_iter()
+                        .map(|(kind, text)| Span::styled(text, json_token_style(kind))),
+                );
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+
+        let block = Block::default()
+            .title(format!("JSON [{}]", view.title))
+            .title_bottom(Line::styled(
+                " Enter/Left/Right: fold, e/c: unfold/fold all, Esc: close ",
+                Style::default().fg(Color::DarkGray),
+            ))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow));
+
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().bg(Color::DarkGray));
+
+        f.render_stateful_widget(list, area, &mut make_list_state(view.cursor));
+    }
+
+    /// Renders the Sparkplug view: edge nodes and their devices with their online state
+    /// and sequence numbers, and the last metric values of the highlighted one.
+    fn render_sparkplug_view(
+        f: &mut ratatui::Frame,
+        view: &SparkplugView,
+        state: Option<&SparkplugState>,
+    ) {
+        let screen = f.area();
+        let area = centered_rect(screen.width * 4 / 5, screen.height * 4 / 5, screen);
+        f.render_widget(Clear, area);
+
+        let block = Block::default()
+            .title("Sparkplug B (Esc: close)")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let Some(state) = state else {
+            let text = "Sparkplug mode is off (start with --sparkplug or set [sparkplug] enabled)";
+            f.render_widget(Paragraph::new(text), inner);
+            return;
+        };
+
+        let rows = state.rows();
+        if rows.is_empty() {
+            f.render_widget(Paragraph::new("No edge nodes seen yet"), inner);
+            return;
+        }
+
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+            .split(inner);
+
+        let items: Vec<ListItem> = rows.iter().map(|row| sparkplug_row_item(state, row)).collect();
+        let list = List::new(items)
+            .block(Block::default().title("Nodes and devices").borders(Borders::ALL))
+            .highlight_style(
+                Style::default()
+                    .add_modifier(Modifier::BOLD)
+                    .add_modifier(Modifier::REVERSED),
+            );
+        let selected = view.selected.min(rows.len() - 1);
+        f.render_stateful_widget(list, columns[0], &mut make_list_state(selected));
+
+        let row = &rows[selected];
+        let lines: Vec<Line> = state
+            .metrics(row)
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| {
+                Line::from(vec![
+                    Span::styled(
+                        format!("{}: ", name),
+                        Style::default().fg(Color::LightBlue).add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(value.to_string()),
+                ])
+            })
+            .collect();
+
+        let title = match &row.device {
+            Some(device) => format!("Metrics [{}/{}]", row.node, device),
+            None => format!("Metrics [{}]", row.node),
+        };
+        let metrics = if lines.is_empty() {
+            Paragraph::new("No metrics")
+        } else {
+            Paragraph::new(lines)
+        };
+        f.render_widget(
+            metrics.block(Block::default().title(title).borders(Borders::ALL)),
+            columns[1],
+        );
+    }
+
+    /// Renders the publish jobs overlay: every job with its progress, or the form
+    /// defining a new one.
+    fn render_jobs_panel(f: &mut ratatui::Frame, panel: &JobsPanel, jobs: &[JobState]) {
+        let screen = f.area();
+        let area = centered_rect(screen.width * 4 / 5, screen.height * 4 / 5, screen);
+        f.render_widget(Clear, area);
+
+        let hint = if panel.form.is_some() {
+            " Tab: next field, Enter: add, Esc: back "
+        } else {
+            " a: add, Enter: start/stop, Del: remove, Esc: close "
+        };
+        let block = Block::default()
+            .title("Publish jobs")
+            .title_bottom(Line::styled(hint, Style::default().fg(Color::DarkGray)))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        if let Some(form) = &panel.form {
+            Self::render_job_form(f, inner, form);
+            return;
+        }
+
+        if jobs.is_empty() {
+            f.render_widget(Paragraph::new("No publish jobs, press a to add one"), inner);
+            return;
+        }
+
+        let items: Vec<ListItem> = jobs.iter().map(job_item).collect();
+        let list = List::new(items).highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .add_modifier(Modifier::REVERSED),
+        );
+        let selected = panel.selected.min(jobs.len() - 1);
+        f.render_stateful_widget(list, inner, &mut make_list_state(selected));
+    }
+
+    /// Renders the form defining a new publish job.
+    fn render_job_form(f: &mut ratatui::Frame, area: ratatui::layout::Rect, form: &JobForm) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(1),
+                Constraint::Min(0),
+            ])
+            .split(area);
+        let focus = form.focus;
+
+        f.render_widget(
+            composer_field("Topic", &form.topic, focus == JobField::Topic),
+            rows[0],
+        );
+        f.render_widget(
+            composer_field(
+                "Payload ({{counter}} {{timestamp}} {{uuid}} {{random:MIN:MAX}})",
+                &form.payload,
+                focus == JobField::Payload,
+            ),
+            rows[1],
+        );
+
+        let options = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, 4); 4])
+            .split(rows[2]);
+        f.render_widget(
+            composer_field("Interval (ms)", &form.interval, focus == JobField::Interval),
+            options[0],
+        );
+        f.render_widget(
+            composer_field("Count (0: no limit)", &form.count, focus == JobField::Count),
+            options[1],
+        );
+        f.render_widget(
+            composer_field(
+                "QoS (space: cycle)",
+                &form.qos.to_string(),
+                focus == JobField::Qos,
+            ),
+            options[2],
+        );
+        f.render_widget(
+            composer_field(
+                "Retain (space)",
+                &format!("[{}] Retain", if form.retain { "x" } else { " " }),
+                focus == JobField::Retain,
+            ),
+            options[3],
+        );
+
+        if let Some(error) = &form.error {
+            f.render_widget(
+                Paragraph::new(error.as_str()).style(Style::default().fg(Color::Red)),
+                rows[3],
+            );
+        }
+    }
+
+    /// Renders the export form: the file, the format, which topics and the CSV flattening.
+    fn render_export_form(f: &mut ratatui::Frame, form: &ExportForm) {
+        let screen = f.area();
+        let area = centered_rect(screen.width * 4 / 5, 9, screen);
+        f.render_widget(Clear, area);
+
+        let block = Block::default()
+            .title("Export messages")
+            .title_bottom(Line::styled(
+                " Tab: next field, Enter: export, Esc: cancel ",
+                Style::default().fg(Color::DarkGray),
+            ))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Length(3), Constraint::Length(1)])
+            .split(inner);
+        let focus = form.focus;
+
+        f.render_widget(composer_field("Path", &form.path, focus == ExportField::Path), rows[0]);
+
+        let options = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, 3); 3])
+            .split(rows[1]);
+        f.render_widget(
+            composer_field(
+                "Format (space: cycle)",
+                form.format.label(),
+                focus == ExportField::Format,
+            ),
+            options[0],
+        );
+        f.render_widget(
+            composer_field(
+                "Topics (space: cycle)",
+                form.scope.label(),
+                focus == ExportField::Scope,
+            ),
+            options[1],
+        );
+        f.render_widget(
+            composer_field(
+                "CSV columns (space)",
+                &format!("[{}] Flatten JSON", if form.flatten { "x" } else { " " }),
+                focus == ExportField::Flatten,
+            ),
+            options[2],
+        );
+
+        if let Some(error) = &form.error {
+            f.render_widget(
+                Paragraph::new(error.as_str()).style(Style::default().fg(Color::Red)),
+                rows[2],
+            );
+        }
+    }
+
+    /// Renders the confirmation listing the topics whose retained message is cleared.
+    fn render_clear_retained(f: &mut ratatui::Frame, clear: &ClearRetained) {
+        let screen = f.area();
+        let height = (clear.topics.len() as u16 + 4).min(screen.height * 4 / 5);
+        let area = centered_rect(screen.width * 4 / 5, height, screen);
+        f.render_widget(Clear, area);
+
+        let block = Block::default()
+            .title(format!("Clear retained messages [{}]", clear.scope))
+            .title_bottom(" Enter/y: clear, Esc/n: cancel ")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let mut lines = if clear.topics.is_empty() {
+            vec![Line::from("No retained messages known for these topics")]
+        } else {
+            let mut lines = vec![Line::from(format!(
+                "An empty retained message will be published to {} topic(s):",
+                clear.topics.len()
             ))];
+            lines.extend(clear.topics.iter().map(|topic| Line::from(format!("  {}", topic))));
+            lines
+        };
+        if let Some(error) = &clear.error {
+            lines.push(Line::from(Span::styled(error.clone(), Style::default().fg(Color::Red))));
+        }
 
-            lines.push(Line::from(""));
-
-            if topic.messages.is_empty() {
-                lines.push(Line::from("No messages yet..."));
-            } else {
-                for msg in &topic.messages {
-                    let timestamp_span = Span::styled(
-                        format!("<{}>: ", msg.timestamp),
-                        Style::default()
-                            .fg(Color::LightRed)
-                            .add_modifier(Modifier::BOLD),
-                    );
+        f.render_widget(Paragraph::new(lines), inner);
+    }
+
+    /// Renders the publish composer with the outcome of the last publication.
+    fn render_composer(
+        f: &mut ratatui::Frame,
+        composer: &PublishComposer,
+        last: Option<&Publication>,
+    ) {
+        let screen = f.area();
+        let area = centered_rect(screen.width * 4 / 5, screen.height * 4 / 5, screen);
+        f.render_widget(Clear, area);
+
+        let block = Block::default()
+            .title("Publish")
+            .title_bottom(Line::styled(
+                " Tab: next field, Ctrl+S: send, Esc: close ",
+                Style::default().fg(Color::DarkGray),
+            ))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let mut constraints =
+            vec![Constraint::Length(3), Constraint::Min(3), Constraint::Length(3)];
+        if composer.v5 {
+            constraints.extend([Constraint::Length(3); 3]);
+        }
+        constraints.push(Constraint::Length(1));
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(inner);
+        let focus = composer.focus;
+
+        f.render_widget(
+            composer_field("Topic", &composer.topic, focus == ComposerField::Topic),
+            rows[0],
+        );
+
+        // Payload editor, scrolled to keep the cursor visible.
+        let payload_focused = focus == ComposerField::Payload;
+        let height = rows[1].height.saturating_sub(2) as usize;
+        let first = composer.cursor.0.saturating_sub(height.saturating_sub(1));
+        let lines: Vec<Line> = composer
+            .payload
+            .iter()
+            .enumerate()
+            .skip(first)
+            .map(|(row, line)| {
+                if payload_focused && row == composer.cursor.0 {
+                    cursor_line(line, composer.cursor.1)
+                } else {
+                    Line::from(line.clone())
+                }
+            })
+            .collect();
+        let border_style = if payload_focused {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        f.render_widget(
+            Paragraph::new(lines).block(
+                Block::default()
+                    .title(if composer.hex {
+                        "Payload (hex bytes)"
+                    } else {
+                        "Payload"
+                    })
+                    .borders(Borders::ALL)
+                    .border_style(border_style),
+            ),
+            rows[1],
+        );
+
+        let options = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, 3); 3])
+            .split(rows[2]);
+        f.render_widget(
+            composer_field(
+                "QoS (0-2, space to cycle)",
+                &composer.qos.to_string(),
+                focus == ComposerField::Qos,
+            ),
+            options[0],
+        );
+        f.render_widget(
+            composer_field(
+                "Retain (space to toggle)",
+                &format!("[{}] Retain", if composer.retain { "x" } else { " " }),
+                focus == ComposerField::Retain,
+            ),
+            options[1],
+        );
+        f.render_widget(
+            composer_field(
+                "Payload format (space to toggle)",
+                if composer.hex { "hex" } else { "text" },
+                focus == ComposerField::Format,
+            ),
+            options[2],
+        );
+
+        if composer.v5 {
+            f.render_widget(
+                composer_field(
+                    "Content type",
+                    &composer.content_type,
+                    focus == ComposerField::ContentType,
+                ),
+                rows[3],
+            );
+            f.render_widget(
+                composer_field(
+                    "Response topic",
+                    &composer.response_topic,
+                    focus == ComposerField::ResponseTopic,
+                ),
+                rows[4],
+            );
+            f.render_widget(
+                composer_field(
+                    "User properties (key=value, ...)",
+                    &composer.user_properties,
+                    focus == ComposerField::UserProperties,
+                ),
+                rows[5],
+            );
+        }
+
+        let status = match (&composer.error, last) {
+            (Some(error), _) => Line::styled(error.clone(), Style::default().fg(Color::Red)),
+            (None, Some(publication)) => Line::from(publication_span(publication)),
+            (None, None) => Line::default(),
+        };
+        f.render_widget(Paragraph::new(status), rows[rows.len() - 1]);
+    }
+
+    /// Handles a key press while the publish composer is open. Enter sends the message,
+    /// except in the payload where it starts a new line; Ctrl+S sends from any field.
+    fn handle_composer_key(&self, key: KeyEvent) {
+        let Ok(mut guard) = self.menu_state.lock() else {
+            return;
+        };
+        let menu_state = &mut *guard;
+        let Some(composer) = menu_state.composer.as_mut() else {
+            return;
+        };
+        let in_payload = composer.focus == ComposerField::Payload;
+
+        match key.code {
+            KeyCode::Esc => {
+                menu_state.composer = None;
+                return;
+            }
+            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {}
+            KeyCode::Enter if !in_payload => {}
+            KeyCode::Enter => {
+                composer.insert_newline();
+                return;
+            }
+            KeyCode::Tab => {
+                composer.next_field();
+                return;
+            }
+            KeyCode::BackTab => {
+                composer.prev_field();
+                return;
+            }
+            KeyCode::Up if in_payload => {
+                composer.move_cursor(-1, 0);
+                return;
+            }
+            KeyCode::Down if in_payload => {
+                composer.move_cursor(1, 0);
+                return;
+            }
+            KeyCode::Left if in_payload => {
+                composer.move_cursor(0, -1);
+                return;
+            }
+            KeyCode::Right if in_payload => {
+                composer.move_cursor(0, 1);
+                return;
+            }
+            KeyCode::Up => {
+                composer.prev_field();
+                return;
+            }
+            KeyCode::Down => {
+                composer.next_field();
+                return;
+            }
+            KeyCode::Backspace => {
+                composer.delete_char();
+                return;
+            }
+            KeyCode::Char(c) => {
+                composer.insert_char(c);
+                return;
+            }
+            _ => return,
+        }
+
+        let result = composer.request().and_then(|request| {
+            self.client()?.try_publish(&request)?;
+            Ok(request)
+        });
+
+        match result {
+            Ok(request) => {
+                composer.error = None;
+                menu_state.add_pending_publication(&request.topic, request.qos);
+            }
+            Err(error) => composer.error = Some(error),
+        }
+    }
 
-                    let payload_span = Span::raw(&msg.payload);
-                    lines.push(Line::from(vec![timestamp_span, payload_span]));
+    /// Handles a key press while the retained message confirmation is open. Confirming
+    /// publishes an empty retained message to every listed topic; a topic that cannot
+    /// be cleared stays listed with the error.
+    fn handle_clear_retained_key(&self, code: KeyCode) {
+        let Ok(mut menu_state) = self.menu_state.lock() else {
+            return;
+        };
+        let Some(clear) = menu_state.clear_retained.as_ref() else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc | KeyCode::Char('n') => menu_state.clear_retained = None,
+            KeyCode::Enter | KeyCode::Char('y') => {
+                for request in clear.requests() {
+                    if let Err(error) = self.client().and_then(|c| c.try_publish(&request)) {
+                        if let Some(clear) = menu_state.clear_retained.as_mut() {
+                            clear.error = Some(error);
+                        }
+                        return;
+                    }
+                    menu_state.add_pending_publication(&request.topic, request.qos);
+                    menu_state.mark_retained_cleared(&request.topic);
                 }
+                menu_state.clear_retained = None;
             }
-            lines
+            _ => {}
+        }
+    }
+
+    /// Handles a key press while the publish jobs overlay is open, or its form.
+    fn handle_jobs_panel_key(&self, code: KeyCode) {
+        let Ok(mut guard) = self.menu_state.lock() else {
+            return;
+        };
+        let menu_state = &mut *guard;
+        let count = menu_state.publish_jobs.len();
+        let Some(panel) = menu_state.jobs_panel.as_mut() else {
+            return;
+        };
+
+        if let Some(form) = panel.form.as_mut() {
+            match code {
+                KeyCode::Esc => panel.form = None,
+                KeyCode::Tab | KeyCode::Down => form.next_field(),
+                KeyCode::BackTab | KeyCode::Up => form.prev_field(),
+                KeyCode::Backspace => form.delete_char(),
+                KeyCode::Char(c) => form.insert_char(c),
+                KeyCode::Enter => match form.job() {
+                    Ok(job) => {
+                        menu_state.publish_jobs.push(JobState::new(job));
+                        panel.selected = count;
+                        panel.form = None;
+                    }
+                    Err(error) => form.error = Some(error),
+                },
+                _ => {}
+            }
+            return;
+        }
+
+        match code {
+            KeyCode::Esc | KeyCode::Char('j') => menu_state.jobs_panel = None,
+            KeyCode::Down if count > 0 => panel.selected = (panel.selected + 1) % count,
+            KeyCode::Up if count > 0 => panel.selected = (panel.selected + count - 1) % count,
+            KeyCode::Char('a') => menu_state.open_job_form(),
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                if let Some(state) = menu_state.publish_jobs.get_mut(panel.selected) {
+                    state.toggle(Instant::now());
+                }
+            }
+            KeyCode::Delete if panel.selected < count => {
+                menu_state.publish_jobs.remove(panel.selected);
+                panel.selected = panel.selected.min(count.saturating_sub(2));
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles a key press while the export form is open.
+    fn handle_export_key(&self, code: KeyCode) {
+        let Ok(mut menu_state) = self.menu_state.lock() else {
+            return;
+        };
+        let Some(form) = menu_state.export_form.as_mut() else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc => menu_state.export_form = None,
+            KeyCode::Tab | KeyCode::Down => form.next_field(),
+            KeyCode::BackTab | KeyCode::Up => form.prev_field(),
+            KeyCode::Backspace => form.delete_char(),
+            KeyCode::Char(c) => form.insert_char(c),
+            KeyCode::Enter => menu_state.run_export(),
+            _ => {}
+        }
+    }
+
+    /// Handles a key press while the Sparkplug view is open.
+    fn handle_sparkplug_view_key(&self, code: KeyCode) {
+        let Ok(mut guard) = self.menu_state.lock() else {
+            return;
+        };
+        let menu_state = &mut *guard;
+        let count = menu_state.sparkplug.as_ref().map_or(0, |state| state.rows().len());
+        let Some(view) = menu_state.sparkplug_view.as_mut() else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('q') => {
+                menu_state.sparkplug_view = None
+            }
+            KeyCode::Down if count > 0 => view.selected = (view.selected + 1) % count,
+            KeyCode::Up if count > 0 => view.selected = (view.selected + count - 1) % count,
+            _ => {}
+        }
+    }
+
+    /// Handles a key press while the JSON popup is open.
+    fn handle_json_view_key(&self, code: KeyCode) {
+        let Ok(mut menu_state) = self.menu_state.lock() else {
+            return;
+        };
+        let Some(view) = menu_state.json_view.as_mut() else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc | KeyCode::Char('v') | KeyCode::Char('q') => menu_state.json_view = None,
+            KeyCode::Up => view.up(1),
+            KeyCode::Down => view.down(1),
+            KeyCode::PageUp => view.up(self.page_size),
+            KeyCode::PageDown => view.down(self.page_size),
+            KeyCode::Home => view.cursor = 0,
+            KeyCode::End => view.end(),
+            KeyCode::Enter | KeyCode::Char(' ') => view.toggle_fold(),
+            KeyCode::Left => view.fold(),
+            KeyCode::Right => view.unfold(),
+            KeyCode::Char('e') => view.unfold_all(),
+            KeyCode::Char('c') => view.fold_all(),
+            _ => {}
+        }
+    }
+
+    /// Handles a key press while the subscriptions overlay is open.
+    fn handle_subscriptions_panel_key(&self, code: KeyCode) {
+        let Ok(mut guard) = self.menu_state.lock() else {
+            return;
+        };
+        let menu_state = &mut *guard;
+        let Some(panel) = menu_state.subscriptions_panel.as_mut() else {
+            return;
+        };
+        let count = menu_state.subscriptions.len();
+
+        let result = match code {
+            KeyCode::Esc => {
+                menu_state.subscriptions_panel = None;
+                return;
+            }
+            KeyCode::Down if count > 0 => {
+                panel.selected = (panel.selected + 1) % count;
+                return;
+            }
+            KeyCode::Up if count > 0 => {
+                panel.selected = (panel.selected + count - 1) % count;
+                return;
+            }
+            KeyCode::Backspace => {
+                panel.input.pop();
+                return;
+            }
+            KeyCode::Char(c) => {
+                panel.input.push(c);
+                return;
+            }
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut panel.input);
+
+                input.trim().parse::<SubscriptionFilter>().and_then(|filter| {
+                    menu_state.request_subscription(&filter)?;
+                    self.client()
+                        .and_then(|client| client.try_subscribe(&filter.filter, filter.qos))
+                        .inspect_err(|_| menu_state.remove_subscription(&filter.filter))
+                })
+                .inspect_err(|_| {
+                    if let Some(panel) = menu_state.subscriptions_panel.as_mut() {
+                        panel.input = input.clone();
+                    }
+                })
+            }
+            KeyCode::Delete => {
+                let index = panel.selected;
+
+                menu_state
+                    .request_unsubscribe(index)
+                    .and_then(|filter| match filter {
+                        Some(filter) => self.client()?.try_unsubscribe(&filter),
+                        None => Ok(()),
+                    })
+            }
+            _ => return,
+        };
+
+        let count = menu_state.subscriptions.len();
+        if let Some(panel) = menu_state.subscriptions_panel.as_mut() {
+            panel.selected = panel.selected.min(count.saturating_sub(1));
+            panel.error = result.err();
+        }
+    }
+
+    /// Renders the status bar with the connection state and the subscribed filters
+    /// with their SUBACK outcome.
+    fn render_status_bar(
+        f: &mut ratatui::Frame,
+        area: ratatui::layout::Rect,
+        app: &TopicActivityMenuState,
+    ) {
+        let (connection, connection_color) = match (&app.replay, &app.connection) {
+            (Some(replay), _) => (replay_status(replay), Color::Cyan),
+            (None, ConnectionState::Connecting) => ("Connecting...".to_string(), Color::Yellow),
+            (None, ConnectionState::Connected) => ("Connected".to_string(), Color::Green),
+            (
+                None,
+                ConnectionState::Reconnecting {
+                    attempt,
+                    last_error,
+                    retry_in,
+                },
+            ) => (
+                format!(
+                    "Reconnecting (attempt {}, in {}s): {}",
+                    attempt,
+                    retry_in.as_secs(),
+                    last_error
+                ),
+                Color::Red,
+            ),
+        };
+
+        let mut spans = vec![
+            Span::styled(
+                connection,
+                Style::default().fg(connection_color).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" | "),
+            Span::styled("Subscriptions: ", Style::default().add_modifier(Modifier::BOLD)),
+        ];
+
+        for (i, subscription) in app.subscriptions.iter().enumerate() {
+            if i > 0 {
+                spans.push(Span::raw(" | "));
+            }
+
+            let (text, color) = match &subscription.status {
+                SubscriptionStatus::Pending => {
+                    (format!("{} (pending)", subscription.filter), Color::Yellow)
+                }
+                SubscriptionStatus::Granted(qos) => {
+                    (format!("{} (QoS {})", subscription.filter, qos), Color::Green)
+                }
+                SubscriptionStatus::Failed(reason) => {
+                    (format!("{} FAILED: {}", subscription.filter, reason), Color::Red)
+                }
+                SubscriptionStatus::Unsubscribing => {
+                    (format!("{} (unsubscribing)", subscription.filter), Color::Yellow)
+                }
+            };
+
+            spans.push(Span::styled(text, Style::default().fg(color)));
+        }
+
+        if let Some(publication) = app.publications.back() {
+            spans.push(Span::raw(" | "));
+            spans.push(publication_span(publication));
+        }
+
+        if let Some(recorder) = &app.recorder {
+            spans.push(Span::raw(" | "));
+            spans.push(Span::styled(
+                format!("REC {} ({} msgs)", recorder.path().display(), recorder.records()),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ));
+        } else if let Some(error) = &app.record_error {
+            spans.push(Span::raw(" | "));
+            spans.push(Span::styled(
+                format!("Recording failed: {}", error),
+                Style::default().fg(Color::Red),
+            ));
+        }
+
+        if let Some(status) = &app.export_status {
+            let (text, color) = match status {
+                ExportStatus::Running { path, .. } => {
+                    (format!("Exporting to {}...", path.display()), Color::Yellow)
+                }
+                ExportStatus::Done(message) => (message.clone(), Color::Green),
+                ExportStatus::Failed(error) => (format!("Export failed: {}", error), Color::Red),
+            };
+            spans.push(Span::raw(" | "));
+            spans.push(Span::styled(text, Style::default().fg(color)));
+        }
+
+        if let Some(error) = &app.history_error {
+            spans.push(Span::raw(" | "));
+            spans.push(Span::styled(
+                format!("History failed: {}", error),
+                Style::default().fg(Color::Red),
+            ));
+        }
+
+        let running_jobs = app.running_jobs();
+        if running_jobs > 0 {
+            spans.push(Span::raw(" | "));
+            spans.push(Span::styled(
+                format!("Jobs: {} running", running_jobs),
+                Style::default().fg(Color::Green),
+            ));
+        }
+
+        f.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    /// Renders the MQTT v5 properties of the selected message.
+    fn render_properties_panel(
+        f: &mut ratatui::Frame,
+        area: ratatui::layout::Rect,
+        app: &TopicActivityMenuState,
+    ) {
+        let lines = match app.selected_message() {
+            Some(msg) => match &msg.properties {
+                Some(props) => properties_lines(props),
+                None => vec![Line::from("No properties (MQTT 3.1.1 connection)")],
+            },
+            None => vec![Line::from("No message selected")],
+        };
+
+        let panel = Paragraph::new(lines)
+            .block(detail_block("Properties", app.focused_pane == Pane::Detail));
+
+        f.render_widget(panel, area);
+    }
+
+    /// Renders the decoded payload of the selected message as an indented tree,
+    /// starting at the scroll position of the detail panel.
+    fn render_decoded_panel(
+        f: &mut ratatui::Frame,
+        area: ratatui::layout::Rect,
+        app: &TopicActivityMenuState,
+    ) {
+        let focused = app.focused_pane == Pane::Detail;
+
+        let (Some(topic), Some(msg)) = (app.selected_topic(), app.selected_message()) else {
+            let panel =
+                Paragraph::new("No message selected").block(detail_block("Decoded", focused));
+            f.render_widget(panel, area);
+            return;
+        };
+
+        let decoded = app.decoded(&topic.name, msg);
+        let mut lines: Vec<Line> = Vec::new();
+
+        if let Some(error) = &decoded.error {
+            lines.push(Line::styled(error.clone(), Style::default().fg(Color::Red)));
+        }
+
+        let tree_lines = decoded.value.tree_lines();
+        let first = app.detail_scroll.min(tree_lines.len().saturating_sub(1));
+        lines.extend(tree_lines.into_iter().skip(first).map(decoded_tree_line));
+
+        let title = format!("Decoded ({})", decoded.decoder);
+        f.render_widget(Paragraph::new(lines).block(detail_block(&title, focused)), area);
+    }
+
+    /// Renders the payload of the selected message as a hex and ASCII dump,
+    /// starting at the scroll position of the detail panel.
+    fn render_hex_dump_panel(
+        f: &mut ratatui::Frame,
+        area: ratatui::layout::Rect,
+        app: &TopicActivityMenuState,
+    ) {
+        let focused = app.focused_pane == Pane::Detail;
+
+        let Some(msg) = app.selected_message() else {
+            let panel = Paragraph::new("No message selected").block(detail_block("Hex", focused));
+            f.render_widget(panel, area);
+            return;
+        };
+
+        let height = area.height.saturating_sub(2) as usize;
+        let first = app
+            .detail_scroll
+            .min(hex_dump::line_count(msg.payload.len()).saturating_sub(1));
+        let lines: Vec<Line> = hex_dump::hex_dump(&msg.payload, first, height)
+            .into_iter()
+            .map(|line| {
+                Line::from(vec![
+                    Span::styled(line.offset, Style::default().fg(Color::DarkGray)),
+                    Span::raw("  "),
+                    Span::raw(line.hex),
+                    Span::styled(format!(" |{}|", line.ascii), Style::default().fg(Color::Cyan)),
+                ])
+            })
+            .collect();
+
+        let title = format!("Hex ({} bytes)", format_count(msg.payload.len() as u64));
+        let panel = if lines.is_empty() {
+            Paragraph::new("Empty payload")
         } else {
-            vec![Line::from("No topics")]
+            Paragraph::new(lines)
         };
 
-        let activity = Paragraph::new(activity_text)
-            .block(Block::default().title("Activity").borders(Borders::ALL));
+        f.render_widget(panel.block(detail_block(&title, focused)), area);
+    }
+}
+
+/// Formats a line of the decoded payload tree.
+fn decoded_tree_line(line: TreeLine) -> Line<'static> {
+    let mut spans = vec![Span::raw("  ".repeat(line.depth))];
+
+    if let Some(key) = line.key {
+        spans.push(Span::styled(
+            format!("{}: ", key),
+            Style::default().fg(Color::LightBlue).add_modifier(Modifier::BOLD),
+        ));
+    }
+
+    let value_style = if line.is_container {
+        Style::default().fg(Color::DarkGray)
+    } else {
+        Style::default()
+    };
+    spans.push(Span::styled(line.value, value_style));
+
+    Line::from(spans)
+}
+
+/// Single-line field of the publish composer, highlighted when focused.
+fn composer_field(title: &str, value: &str, focused: bool) -> Paragraph<'static> {
+    let (text, style) = if focused {
+        (format!("{}_", value), Style::default().fg(Color::Black).bg(Color::White))
+    } else {
+        (value.to_string(), Style::default())
+    };
+
+    Paragraph::new(text)
+        .style(style)
+        .block(Block::default().title(title.to_string()).borders(Borders::ALL))
+}
+
+/// A payload line with the cursor shown as a reversed character.
+fn cursor_line(line: &str, col: usize) -> Line<'static> {
+    let mut chars = line.chars();
+    let before: String = chars.by_ref().take(col).collect();
+    let under = chars.next().map_or(" ".to_string(), String::from);
+    let after: String = chars.collect();
+
+    Line::from(vec![
+        Span::raw(before),
+        Span::styled(under, Style::default().add_modifier(Modifier::REVERSED)),
+        Span::raw(after),
+    ])
+}
+
+/// Outcome of a publication, for the status bar and the composer.
+fn publication_span(publication: &Publication) -> Span<'static> {
+    let (status, color) = match &publication.status {
+        PublishStatus::Queued => ("queued".to_string(), Color::Yellow),
+        PublishStatus::AwaitingAck if publication.qos == 2 => {
+            ("waiting for PUBCOMP".to_string(), Color::Yellow)
+        }
+        PublishStatus::AwaitingAck => ("waiting for PUBACK".to_string(), Color::Yellow),
+        PublishStatus::Delivered if publication.qos == 0 => ("sent".to_string(), Color::Green),
+        PublishStatus::Delivered => ("acknowledged".to_string(), Color::Green),
+        PublishStatus::Failed(reason) => (format!("FAILED: {}", reason), Color::Red),
+    };
+
+    Span::styled(
+        format!("Published {} (QoS {}): {}", publication.topic, publication.qos, status),
+        Style::default().fg(color),
+    )
+}
 
-        f.render_widget(activity, chunks[1]);
+/// Formats an edge node or device of the Sparkplug view with its online state.
+/// Nodes also show their last sequence number and the gaps seen.
+fn sparkplug_row_item(state: &SparkplugState, row: &SparkplugRow) -> ListItem<'static> {
+    let Some(node) = state.node(row) else {
+        return ListItem::new(Line::from(row.node.clone()));
+    };
+
+    let (name, online) = match &row.device {
+        Some(device) => (
+            format!("  {}", device),
+            node.devices.get(device).is_some_and(|device| device.online),
+        ),
+        None => (format!("{}/{}", row.group, row.node), node.online),
+    };
+
+    let mut spans = vec![
+        Span::raw(format!("{} ", name)),
+        if online {
+            Span::styled("ONLINE", Style::default().fg(Color::Green))
+        } else {
+            Span::styled("OFFLINE", Style::default().fg(Color::Red))
+        },
+    ];
+
+    if row.device.is_none() {
+        if let Some(seq) = node.last_seq {
+            spans.push(Span::styled(
+                format!(" seq {}", seq),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        if node.seq_gaps > 0 {
+            spans.push(Span::styled(
+                format!(" ({} seq gaps)", node.seq_gaps),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
     }
+
+    ListItem::new(Line::from(spans))
+}
+
+/// Color of a piece of the JSON popup.
+fn json_token_style(kind: TokenKind) -> Style {
+    match kind {
+        TokenKind::Key => Style::default().fg(Color::LightBlue).add_modifier(Modifier::BOLD),
+        TokenKind::String => Style::default().fg(Color::Green),
+        TokenKind::Number => Style::default().fg(Color::Cyan),
+        TokenKind::Bool => Style::default().fg(Color::Yellow),
+        TokenKind::Null => Style::default().fg(Color::Magenta),
+        TokenKind::Punctuation | TokenKind::Plain => Style::default(),
+        TokenKind::Folded => Style::default().fg(Color::DarkGray),
+        TokenKind::Error => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+    }
+}
+
+/// Block around the detail panel, highlighted when it has the focus.
+fn detail_block(title: &str, focused: bool) -> Block<'static> {
+    let border_style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+
+    Block::default()
+        .title(title.to_string())
+        .borders(Borders::ALL)
+        .border_style(border_style)
+}
+
+/// Formats a count with thousands separators, e.g. `1,204`.
+fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::new();
+
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+
+    formatted
+}
+
+/// Formats a row of the topic tree: indentation, fold marker, level name and the
+/// message count and last activity of the subtree.
+fn tree_row_item(row: &TreeRow) -> ListItem<'static> {
+    let marker = match (row.has_children, row.expanded) {
+        (true, true) => "▾ ",
+        (true, false) => "▸ ",
+        (false, _) => "  ",
+    };
+    let label = if row.label.is_empty() {
+        "(empty)"
+    } else {
+        row.label.as_str()
+    };
+
+    let mut stats = if row.has_children {
+        format!(" {} topics, {} msgs", row.topic_count, row.message_count)
+    } else {
+        format!(" {} msgs", row.message_count)
+    };
+    if row.has_children && row.retained_count > 0 {
+        stats.push_str(&format!(", {} retained", row.retained_count));
+    }
+    if let Some(last_activity) = &row.last_activity {
+        let time = last_activity.rsplit(' ').next().unwrap_or(last_activity);
+        stats.push_str(&format!(", {}", time));
+    }
+
+    let mut spans = vec![Span::raw(format!("{}{}{}", "  ".repeat(row.depth), marker, label))];
+    if !row.has_children && row.retained_count > 0 {
+        spans.push(retained_marker());
+    }
+    spans.push(Span::styled(stats, Style::default().fg(Color::DarkGray)));
+
+    ListItem::new(Line::from(spans))
+}
+
+/// Progress of a replay for the status bar.
+fn replay_status(replay: &Replay) -> String {
+    let state = if replay.is_finished() {
+        "finished"
+    } else if replay.paused {
+        "paused"
+    } else {
+        replay.speed.label()
+    };
+
+    format!(
+        "Replay {}: {}/{} ({}; space: pause, .: step, f: speed)",
+        replay.path.display(),
+        replay.position(),
+        replay.len(),
+        state
+    )
+}
+
+/// Formats a publish job: its state, topic, schedule and progress.
+fn job_item(state: &JobState) -> ListItem<'static> {
+    let job = &state.job;
+    let (status, color) = match (&state.error, state.is_running()) {
+        (_, true) => ("running", Color::Green),
+        (Some(_), false) => ("failed", Color::Red),
+        (None, false) => ("stopped", Color::DarkGray),
+    };
+    let progress = match job.count {
+        Some(count) => format!("{}/{} sent", state.sent, count),
+        None => format!("{} sent", state.sent),
+    };
+    let retain = if job.retain { ", retain" } else { "" };
+
+    let mut spans = vec![
+        Span::styled(format!("{:<8} ", status), Style::default().fg(color)),
+        Span::raw(format!(
+            "{} every {}ms (QoS {}{}), {}: {}",
+            job.topic,
+            job.interval.as_millis(),
+            job.qos,
+            retain,
+            progress,
+            job.template.source()
+        )),
+    ];
+    if let Some(error) = &state.error {
+        spans.push(Span::styled(format!(" {}", error), Style::default().fg(Color::Red)));
+    }
+
+    ListItem::new(Line::from(spans))
+}
+
+/// Marks a topic holding a retained message in the topic list.
+fn retained_marker() -> Span<'static> {
+    Span::styled(" [R]", Style::default().fg(Color::Magenta))
+}
+
+/// Summary shown in the activity panel when a tree node that is not a topic is selected.
+fn subtree_summary_lines(row: &TreeRow) -> Vec<Line<'static>> {
+    vec![
+        Line::from(Span::styled(
+            format!("[{}/#]", row.path),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(format!("Topics: {}", row.topic_count)),
+        Line::from(format!("Messages: {}", row.message_count)),
+        Line::from(format!("Retained: {}", row.retained_count)),
+        Line::from(format!(
+            "Last activity: {}",
+            row.last_activity.as_deref().unwrap_or("never")
+        )),
+        Line::from(""),
+        Line::from("Right/Left: expand/collapse, e/c: expand/collapse all, t: flat list"),
+    ]
+}
+
+/// Formats the MQTT v5 properties of a message, one per line.
+fn properties_lines(props: &MessageProperties) -> Vec<Line<'static>> {
+    let key_style = Style::default().fg(Color::LightBlue).add_modifier(Modifier::BOLD);
+    let entry = |key: &str, value: String| {
+        Line::from(vec![Span::styled(format!("{}: ", key), key_style), Span::raw(value)])
+    };
+
+    let mut lines = Vec::new();
+
+    if let Some(content_type) = &props.content_type {
+        lines.push(entry("Content type", content_type.clone()));
+    }
+    if let Some(indicator) = props.payload_format_indicator {
+        let format = if indicator == 1 { "UTF-8" } else { "bytes" };
+        lines.push(entry("Payload format", format.into()));
+    }
+    if let Some(topic) = &props.response_topic {
+        lines.push(entry("Response topic", topic.clone()));
+    }
+    if let Some(data) = &props.correlation_data {
+        let data = match std::str::from_utf8(data) {
+            Ok(text) => text.to_string(),
+            Err(_) => data.iter().map(|b| format!("{:02x}", b)).collect(),
+        };
+        lines.push(entry("Correlation data", data));
+    }
+    if let Some(expiry) = props.message_expiry_interval {
+        lines.push(entry("Message expiry", format!("{}s", expiry)));
+    }
+    if let Some(alias) = props.topic_alias {
+        lines.push(entry("Topic alias", alias.to_string()));
+    }
+    if !props.subscription_identifiers.is_empty() {
+        let ids: Vec<String> =
+            props.subscription_identifiers.iter().map(|id| id.to_string()).collect();
+        lines.push(entry("Subscription ids", ids.join(", ")));
+    }
+    for (key, value) in &props.user_properties {
+        lines.push(entry(&format!("User property '{}'", key), value.clone()));
+    }
+
+    if lines.is_empty() {
+        lines.push(Line::from("No properties"));
+    }
+
+    lines
 }
 
 impl Screen for TopicActivityScreen<'_> {
     fn run(&mut self) -> std::io::Result<()> {
         loop {
             {
-                let menu_guard = self
+                let mut menu_guard = self
                     .menu_state
                     .lock()
-                    .map_err(|_| {
-                        std::io::Error::new(
-                            std::io::ErrorKind::Other, "App mutex poisoned"
-                        )
-                    })?;
+                    .map_err(|_| std::io::Error::other("App mutex poisoned"))?;
+                menu_guard.sync_history_page();
+                menu_guard.poll_export();
+
+                let message_list = &mut self.message_list;
+                let mut page_size = self.page_size;
 
                 self.terminal.draw(|f| {
-                    TopicActivityScreen::render_topic_activity_screen_ui(f, &*menu_guard);
+                    page_size = TopicActivityScreen::render_topic_activity_screen_ui(
+                        f,
+                        &menu_guard,
+                        message_list,
+                    );
                 })?;
+
+                self.page_size = page_size;
             }
 
             if self.handle_input()? {
@@ -136,6 +1664,18 @@ impl Screen for TopicActivityScreen<'_> {
             // Tick
             if self.last_tick.elapsed() >= self.tick_rate {
                 self.last_tick = Instant::now();
+
+                if let Ok(mut menu_state) = self.menu_state.lock() {
+                    // Replayed messages keep their capture time, so they age with the
+                    // playback rather than the wall clock.
+                    let now = match &menu_state.replay {
+                        Some(replay) => replay.current_time(),
+                        None => Some(OffsetDateTime::now_utc()),
+                    };
+                    if let Some(now) = now {
+                        menu_state.prune_expired(now);
+                    }
+                }
             }
         }
 
@@ -153,17 +1693,232 @@ impl Screen for TopicActivityScreen<'_> {
         }
 
         if let Event::Key(key) = event::read()? {
+            let (
+                panel_open,
+                json_view_open,
+                sparkplug_view_open,
+                composer_open,
+                clear_open,
+                jobs_open,
+                export_open,
+            ) = self
+                .menu_state
+                .lock()
+                .map(|menu_state| {
+                    (
+                        menu_state.subscriptions_panel.is_some(),
+                        menu_state.json_view.is_some(),
+                        menu_state.sparkplug_view.is_some(),
+                        menu_state.composer.is_some(),
+                        menu_state.clear_retained.is_some(),
+                        menu_state.jobs_panel.is_some(),
+                        menu_state.export_form.is_some(),
+                    )
+                })
+                .unwrap_or_default();
+
+            if export_open {
+                self.handle_export_key(key.code);
+                return Ok(false);
+            }
+
+            if jobs_open {
+                self.handle_jobs_panel_key(key.code);
+                return Ok(false);
+            }
+
+            if clear_open {
+                self.handle_clear_retained_key(key.code);
+                return Ok(false);
+            }
+
+            if composer_open {
+                self.handle_composer_key(key);
+                return Ok(false);
+            }
+
+            if panel_open {
+                self.handle_subscriptions_panel_key(key.code);
+                return Ok(false);
+            }
+
+            if json_view_open {
+                self.handle_json_view_key(key.code);
+                return Ok(false);
+            }
+
+            if sparkplug_view_open {
+                self.handle_sparkplug_view_key(key.code);
+                return Ok(false);
+            }
+
             match key.code {
                 KeyCode::Char('q') => return Ok(true),
 
                 KeyCode::Down => {
                     if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
-                        topic_activity_menu_state.next();
+                        match topic_activity_menu_state.focused_pane {
+                            Pane::Topics => topic_activity_menu_state.next(),
+                            Pane::Messages => topic_activity_menu_state.message_down(1),
+                            Pane::Detail => topic_activity_menu_state.detail_down(1),
+                        }
                     }
                 }
                 KeyCode::Up => {
                     if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
-                        topic_activity_menu_state.previous();
+                        match topic_activity_menu_state.focused_pane {
+                            Pane::Topics => topic_activity_menu_state.previous(),
+                            Pane::Messages => topic_activity_menu_state.message_up(1),
+                            Pane::Detail => topic_activity_menu_state.detail_up(1),
+                        }
+                    }
+                }
+                KeyCode::Tab => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.switch_pane();
+                    }
+                }
+                KeyCode::PageUp => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        match topic_activity_menu_state.focused_pane {
+                            Pane::Detail => topic_activity_menu_state.detail_up(self.page_size),
+                            _ => topic_activity_menu_state.message_up(self.page_size),
+                        }
+                    }
+                }
+                KeyCode::PageDown => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        match topic_activity_menu_state.focused_pane {
+                            Pane::Detail => topic_activity_menu_state.detail_down(self.page_size),
+                            _ => topic_activity_menu_state.message_down(self.page_size),
+                        }
+                    }
+                }
+                KeyCode::Home => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.message_home();
+                    }
+                }
+                KeyCode::End => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.message_end();
+                    }
+                }
+                KeyCode::Char('p') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.toggle_detail_panel(DetailPanel::Properties);
+                    }
+                }
+                KeyCode::Char('d') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.toggle_detail_panel(DetailPanel::Decoded);
+                    }
+                }
+                KeyCode::Char('D') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.cycle_decoder();
+                    }
+                }
+                KeyCode::Char('v') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.open_json_view();
+                    }
+                }
+                KeyCode::Char('x') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.toggle_detail_panel(DetailPanel::HexDump);
+                    }
+                }
+                KeyCode::Char('t') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.toggle_view();
+                    }
+                }
+                KeyCode::Right => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.expand_selected();
+                    }
+                }
+                KeyCode::Left => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.collapse_selected();
+                    }
+                }
+                KeyCode::Enter => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.toggle_selected();
+                    }
+                }
+                KeyCode::Char('e') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.expand_all();
+                    }
+                }
+                KeyCode::Char('c') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.collapse_all();
+                    }
+                }
+                KeyCode::Char('s') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.subscriptions_panel =
+                            Some(SubscriptionsPanel::default());
+                    }
+                }
+                KeyCode::Char('P') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.open_composer();
+                    }
+                }
+                KeyCode::Char('r') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.resend_selected_message();
+                    }
+                }
+                KeyCode::Char('n') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.sparkplug_view = Some(SparkplugView::default());
+                    }
+                }
+                KeyCode::Char('j') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.jobs_panel = Some(JobsPanel::default());
+                    }
+                }
+                KeyCode::Char('w') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.toggle_recording();
+                    }
+                }
+                KeyCode::Char(' ') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock()
+                        && let Some(replay) = topic_activity_menu_state.replay.as_mut()
+                    {
+                        replay.toggle_pause();
+                    }
+                }
+                KeyCode::Char('.') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock()
+                        && let Some(replay) = topic_activity_menu_state.replay.as_mut()
+                    {
+                        replay.step();
+                    }
+                }
+                KeyCode::Char('f') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock()
+                        && let Some(replay) = topic_activity_menu_state.replay.as_mut()
+                    {
+                        replay.cycle_speed();
+                    }
+                }
+                KeyCode::Char('R') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.open_clear_retained();
+                    }
+                }
+                KeyCode::Char('E') => {
+                    if let Ok(mut topic_activity_menu_state) = self.menu_state.lock() {
+                        topic_activity_menu_state.open_export_form();
                     }
                 }
                 _ => {}